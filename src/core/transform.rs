@@ -1,6 +1,6 @@
 use entity::Entity;
 use cgmath::{Point3, Vector3, Matrix4, EuclideanSpace, Quaternion, InnerSpace};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 #[derive(Copy, Clone, Debug)]
 pub struct Transform {
@@ -47,13 +47,13 @@ impl Default for TransformPair {
 
 pub struct TransformStore {
     // Stores (previous, current) transforms
-    transforms: HashMap<Entity, TransformPair>,
+    transforms: BTreeMap<Entity, TransformPair>,
 }
 
 impl TransformStore {
     pub fn new() -> TransformStore {
         TransformStore {
-            transforms: HashMap::new()
+            transforms: BTreeMap::new()
         }
     }
 
@@ -70,6 +70,10 @@ impl TransformStore {
         self.transforms.get_mut(entity)
     }
 
+    pub fn transforms(&self) -> &BTreeMap<Entity, TransformPair> {
+        &self.transforms
+    }
+
     /// Clears all transforms from the store.
     pub fn clear(&mut self) {
         self.transforms.clear();
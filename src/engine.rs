@@ -1,14 +1,27 @@
 use entity::{EntityManager, EntityBlueprint, Entity, LinearComponentStorage};
 use render::*;
-use physics::{PhysicsEngine, CollisionComponentStore,
-    CollisionEngine, RigidBody, ForceGenerator};
+use physics::{PhysicsEngine, PhysicsState, CollisionComponentStore,
+    CollisionEngine, RigidBody, ScopedForceGenerator, PhysicsMaterial, Joint, Integrator, AngularIntegrator};
 use input_manager::InputManager;
 use message::{Message, MessageReceiver};
 use camera::{Camera, CameraController};
 use time_keeper::TimeKeeper;
+use snapshot::{Snapshot, BodyState, CollisionState};
+use replay::{Replay, Playback};
 use core::{Transform, TransformPair, TransformStore};
 use std;
+use std::io;
+use std::path::{Path, PathBuf};
 use interop;
+use nalgebra::{Isometry3, Translation3, UnitQuaternion};
+
+/// Where snapshots are saved to and restored from by key presses.
+const SNAPSHOT_PATH: &'static str = "snapshot.txt";
+
+// A small timestep keeps resting contacts and joints stable. Fast bodies
+// which would still pass through thin models should enable continuous
+// collision detection instead of relying on an even smaller timestep.
+const TIMESTEP: f64 = 1.0 / 200.0;
 
 pub struct Engine<Initializer: SceneInitializer> {
     initializer: Initializer,
@@ -16,14 +29,25 @@ pub struct Engine<Initializer: SceneInitializer> {
     systems: Systems,
     stores: ComponentStores,
     entity_manager: EntityManager,
-    scene_index: usize
+    scene_index: usize,
+
+    /// When set, every frame takes this many physics steps regardless of the wall clock
+    steps_per_frame: Option<u32>,
+
+    /// The number of physics steps taken since the engine started running
+    step: u64,
+
+    recording: Option<(Replay, PathBuf)>,
+    playback: Option<Playback>
 }
 
 struct ComponentStores {
     pub scene: SceneRenderableStore,
     pub transform: TransformStore,
     pub rigid_bodies: LinearComponentStorage<RigidBody>,
-    pub force: LinearComponentStorage<ForceGenerator>,
+    pub force: LinearComponentStorage<ScopedForceGenerator>,
+    pub material: LinearComponentStorage<PhysicsMaterial>,
+    pub joint: LinearComponentStorage<Joint>,
     pub collision: CollisionComponentStore,
     pub camera: Camera
 }
@@ -49,12 +73,40 @@ impl Systems {
 }
 
 impl ComponentStores {
-    pub fn assemble_blueprint(&mut self, entity: Entity, blueprint: EntityBlueprint) {
+    /// Assembles the blueprint for the given entity. The entities of all blueprints
+    /// in the scene are needed to resolve the bodies of joints.
+    ///
+    /// Panics if the blueprint attaches a plane or a triangle mesh to a dynamic body,
+    /// since they do not enclose a finite volume.
+    pub fn assemble_blueprint(&mut self,
+                              entity: Entity,
+                              blueprint: EntityBlueprint,
+                              scene_entities: &[Entity]) {
+        let is_dynamic = blueprint.rigid_body.as_ref().map_or(false, |rb| rb.as_dynamic().is_some());
+        let requires_static_body = blueprint.collision.as_ref().map_or(false, |model| model.requires_static_body());
+        assert!(!(is_dynamic && requires_static_body),
+            "Planes and triangle meshes may only belong to static bodies.");
+
         if let Some(rb) = blueprint.rigid_body {
             self.rigid_bodies.set_component_for_entity(entity, rb);
         }
+        // Without a collision model, the filter and sensor flag have nothing to apply to
         if let Some(collision) = blueprint.collision {
             self.collision.set_component_model(entity, collision);
+            if let Some(filter) = blueprint.collision_filter {
+                self.collision.set_component_filter(entity, filter);
+            }
+            if blueprint.sensor {
+                self.collision.set_component_sensor(entity, true);
+            }
+
+            // Models without a rigid body, such as sensors, are placed by the transform
+            if let Some(transform) = blueprint.transform {
+                let pose = Isometry3::from_parts(
+                    Translation3::from_vector(interop::cgmath_point3_to_nalgebra(&transform.position).coords),
+                    UnitQuaternion::new_normalize(interop::cgmath_quat_to_nalgebra(&transform.orientation)));
+                self.collision.set_component_pose(entity, pose);
+            }
         }
         if let Some(transform) = blueprint.transform {
             self.transform.set_transform(entity, TransformPair {
@@ -66,7 +118,59 @@ impl ComponentStores {
             self.scene.set_renderable(entity, renderable);
         }
         if let Some(force) = blueprint.force {
-            self.force.set_component_for_entity(entity, force);
+            self.force.set_component_for_entity(entity, force.map_bodies(|&index| scene_entities[index]));
+        }
+        if let Some(material) = blueprint.material {
+            self.material.set_component_for_entity(entity, material);
+        }
+        if let Some(joint) = blueprint.joint {
+            self.joint.set_component_for_entity(entity, joint.map_bodies(|&index| scene_entities[index]));
+        }
+    }
+
+    /// Captures the state of the bodies, transforms and collision models,
+    /// along with the given state of the physics engine.
+    pub fn snapshot(&self, scene_index: usize, physics: PhysicsState) -> Snapshot {
+        let bodies = self.rigid_bodies.components().iter()
+            .map(|&(ref rb, entity)| (BodyState::of(rb), entity))
+            .collect();
+
+        let transforms = self.transform.transforms().iter()
+            .map(|(&entity, pair)| (pair.clone(), entity))
+            .collect();
+
+        let collision = izip!(self.collision.entities(), self.collision.models(),
+                              self.collision.filters(), self.collision.sensors())
+            .map(|(&entity, model, &filter, &sensor)| (CollisionState {
+                model: model.clone(),
+                filter: filter,
+                sensor: sensor
+            }, entity))
+            .collect();
+
+        Snapshot {
+            scene_index: scene_index,
+            bodies: bodies,
+            transforms: transforms,
+            collision: collision,
+            physics: physics
+        }
+    }
+
+    /// Restores the state captured in the snapshot into the stores,
+    /// which must hold the scene the snapshot was taken from.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        for &(ref state, entity) in &snapshot.bodies {
+            let rb = state.to_rigid_body(self.rigid_bodies.lookup_component_for_entity(entity));
+            self.rigid_bodies.set_component_for_entity(entity, rb);
+        }
+        for &(ref pair, entity) in &snapshot.transforms {
+            self.transform.set_transform(entity, pair.clone());
+        }
+        for &(ref state, entity) in &snapshot.collision {
+            self.collision.set_component_model(entity, state.model.clone());
+            self.collision.set_component_filter(entity, state.filter);
+            self.collision.set_component_sensor(entity, state.sensor);
         }
     }
 
@@ -76,6 +180,8 @@ impl ComponentStores {
         self.rigid_bodies.clear();
         self.collision.clear();
         self.force.clear();
+        self.material.clear();
+        self.joint.clear();
     }
 }
 
@@ -97,19 +203,66 @@ impl<I> Engine<I> where I: SceneInitializer {
             systems: Systems::new(),
             stores: prepare_component_stores(),
             entity_manager: EntityManager::new(),
-            scene_index: usize::max_value()
+            scene_index: usize::max_value(),
+            steps_per_frame: None,
+            step: 0,
+            recording: None,
+            playback: None
+        }
+    }
+
+    /// Replaces the integrator used for the linear motion of all scenes.
+    pub fn set_integrator(&mut self, integrator: Box<Integrator>) {
+        self.systems.physics.set_integrator(integrator);
+    }
+
+    /// Replaces the integrator used for the rotational motion of all scenes.
+    pub fn set_angular_integrator(&mut self, integrator: Box<AngularIntegrator>) {
+        self.systems.physics.set_angular_integrator(integrator);
+    }
+
+    /// Makes every frame take the given number of physics steps, so that the
+    /// simulation no longer depends on how long frames take to render.
+    pub fn set_lockstep(&mut self, steps_per_frame: u32) {
+        assert!(steps_per_frame > 0);
+        self.steps_per_frame = Some(steps_per_frame);
+        if let Some((ref mut replay, _)) = self.recording {
+            replay.steps_per_frame = steps_per_frame;
         }
     }
 
+    /// Records the input of the run to the given path once the run ends. The run
+    /// is made lockstep, with one step per frame unless set otherwise.
+    ///
+    /// Restoring a snapshot during the run makes the replay depend on the contents
+    /// of the snapshot file at the time.
+    pub fn record_replay<P: AsRef<Path>>(&mut self, path: P) {
+        let steps_per_frame = self.steps_per_frame.unwrap_or(1);
+        self.set_lockstep(steps_per_frame);
+        self.recording = Some((Replay::new(TIMESTEP, steps_per_frame), path.as_ref().to_path_buf()));
+    }
+
+    /// Plays back the input recorded in the given replay instead of the input
+    /// from the window, which is ignored except for closing it.
+    pub fn play_replay<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let replay = Replay::load(path)?;
+        if replay.timestep != TIMESTEP {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("The replay was recorded with a timestep of {}, but the engine uses {}.",
+                        replay.timestep, TIMESTEP)));
+        }
+        self.set_lockstep(replay.steps_per_frame);
+        self.playback = Some(Playback::new(replay));
+        Ok(())
+    }
+
     pub fn run(&mut self) {
         let window = Window::new();
 
-        // For now we use an insanely high timestep to partially
-        // make up for the fact that our physics engine doesn't handle
-        // collisions very well yet.
-        const TIMESTEP: f64 = 1.0 / 200.0;
-
-        let mut time_keeper = TimeKeeper::new();
+        let mut time_keeper = match self.steps_per_frame {
+            Some(steps_per_frame) => TimeKeeper::lockstep(TIMESTEP, steps_per_frame),
+            None => TimeKeeper::new()
+        };
 
         self.systems.scene.compile_shaders(&window);
 
@@ -122,8 +275,11 @@ impl<I> Engine<I> where I: SceneInitializer {
                 self.systems.physics.simulate(TIMESTEP,
                     &mut self.stores.rigid_bodies,
                     &self.stores.collision,
-                    &self.stores.force);
+                    &self.stores.force,
+                    &self.stores.material,
+                    &self.stores.joint);
                 sync_transforms(&self.stores.rigid_bodies, &mut self.stores.transform);
+                self.step += 1;
             }
 
             let progress = time_keeper.accumulated() / TIMESTEP;
@@ -136,9 +292,29 @@ impl<I> Engine<I> where I: SceneInitializer {
             self.systems.scene.render(&mut frame, progress, self.stores.camera.clone(), &self.stores.scene, &self.stores.transform);
             frame.finish();
 
-            let messages = window.check_events();
+            // Collision events from all physics steps in this frame
+            // are dispatched along with the window events
+            let mut messages = self.systems.physics.drain_messages();
+            let window_messages = window.check_events();
+            match self.playback {
+                Some(ref mut playback) => {
+                    // The window can still be closed, but all other input comes from the replay
+                    messages.extend(window_messages.into_iter().filter(|message| match message {
+                        &Message::WindowClosed => true,
+                        _ => false
+                    }));
+                    messages.extend(playback.messages_until(self.step));
+                },
+                None => messages.extend(window_messages)
+            }
             self.dispatch_messages(messages);
         }
+
+        if let Some((ref replay, ref path)) = self.recording {
+            if let Err(error) = replay.save(path) {
+                eprintln!("Failed to save replay to {}: {}", path.display(), error);
+            }
+        }
     }
 
     fn dispatch_messages(&mut self, messages: Vec<Message>) {
@@ -146,6 +322,10 @@ impl<I> Engine<I> where I: SceneInitializer {
         let mut response = Vec::new();
 
         while !messages.is_empty() {
+            if let Some((ref mut replay, _)) = self.recording {
+                replay.record(self.step, &messages);
+            }
+
             response.clear();
             response.extend(self.systems.input.process_messages(&messages));
             response.extend(self.systems.camera.process_messages(&messages));
@@ -155,15 +335,37 @@ impl<I> Engine<I> where I: SceneInitializer {
         }
     }
 
+    /// Captures the state of the running scene.
+    pub fn snapshot(&self) -> Snapshot {
+        self.stores.snapshot(self.scene_index, self.systems.physics.save_state())
+    }
+
+    /// Reassembles the scene the snapshot was taken from, and resumes its simulation
+    /// from the state captured in the snapshot.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.reset_scene(snapshot.scene_index);
+        if self.scene_index == snapshot.scene_index {
+            self.stores.restore(snapshot);
+            self.systems.physics.restore_state(&snapshot.physics);
+        }
+    }
+
     fn reset_scene(&mut self, index: usize) {
         let new_scene = self.initializer.create_scene(index);
         if let Some(new_scene) = new_scene {
             let reset_camera = self.scene_index != index;
-            reassemble_scene(&mut self.entity_manager,
-                             &mut self.stores, new_scene, reset_camera);
 
-            // Temporary hack: make sure to clear state in physics engine
-            self.systems.physics = PhysicsEngine::new();
+            // Entities are numbered afresh for every scene, so that a snapshot
+            // refers to the same entities whenever its scene is assembled
+            self.entity_manager = EntityManager::new();
+            let disabled_pairs = reassemble_scene(&mut self.entity_manager,
+                                                  &mut self.stores, new_scene, reset_camera);
+            self.systems.scene.clear_buffers();
+
+            self.systems.physics.reset();
+            for (a, b) in disabled_pairs {
+                self.systems.physics.disable_collision_between(a, b);
+            }
             self.scene_index = index;
         }
     }
@@ -175,6 +377,17 @@ impl<I> MessageReceiver for Engine<I> where I: SceneInitializer {
             match message.clone() {
                 Message::WindowClosed => self.should_continue = false,
                 Message::ReloadScene { index } => self.reset_scene(index),
+                Message::SaveSnapshot => {
+                    if let Err(error) = self.snapshot().save(SNAPSHOT_PATH) {
+                        eprintln!("Failed to save snapshot to {}: {}", SNAPSHOT_PATH, error);
+                    }
+                },
+                Message::RestoreSnapshot => {
+                    match Snapshot::load(SNAPSHOT_PATH) {
+                        Ok(snapshot) => self.restore(&snapshot),
+                        Err(error) => eprintln!("Failed to restore snapshot from {}: {}", SNAPSHOT_PATH, error)
+                    }
+                },
                 _ => ()
             };
         }
@@ -189,23 +402,31 @@ fn prepare_component_stores() -> ComponentStores {
         transform: TransformStore::new(),
         rigid_bodies: LinearComponentStorage::new(),
         force: LinearComponentStorage::new(),
+        material: LinearComponentStorage::new(),
+        joint: LinearComponentStorage::new(),
         collision: CollisionComponentStore::new(),
         camera: Camera::look_in(Point3::origin(), Vector3::unit_y(), Vector3::unit_z()).unwrap()
     }
 }
 
+/// Assembles the scene into the stores, and returns the pairs of entities
+/// which the blueprints prevent from colliding with each other.
 fn reassemble_scene(entity_manager: &mut EntityManager,
                     stores: &mut ComponentStores,
                     scene: SceneBlueprint,
-                    reset_camera: bool) {
+                    reset_camera: bool) -> Vec<(Entity, Entity)> {
     if reset_camera {
         stores.camera = scene.camera;
     }
 
     stores.clear();
-    for blueprint in scene.blueprints {
-        stores.assemble_blueprint(entity_manager.create(), blueprint);
+    let entities: Vec<Entity> = scene.blueprints.iter().map(|_| entity_manager.create()).collect();
+    let mut disabled_pairs = Vec::new();
+    for (&entity, blueprint) in entities.iter().zip(scene.blueprints) {
+        disabled_pairs.extend(blueprint.no_collision_with.iter().map(|&index| (entity, entities[index])));
+        stores.assemble_blueprint(entity, blueprint, &entities);
     }
+    disabled_pairs
 }
 
 fn sync_transforms(bodies: &LinearComponentStorage<RigidBody>,
@@ -217,19 +438,28 @@ fn sync_transforms(bodies: &LinearComponentStorage<RigidBody>,
     // while SceneRenderables have interpolated positions, with
     // no common notion of Transform
     for &(ref rb, entity) in bodies.components() {
-        if let &RigidBody::Dynamic(ref rb) = rb {
+        // Static bodies never move, so their transforms are left as they were assembled
+        let poses = match rb {
+            &RigidBody::Static(_) => None,
+            &RigidBody::Dynamic(ref rb) => Some((rb.prev_state.position, rb.prev_state.orientation,
+                                                 rb.state.position, rb.state.orientation)),
+            &RigidBody::Kinematic(ref rb) => Some((rb.prev_position, rb.prev_orientation,
+                                                   rb.position, rb.orientation))
+        };
+
+        if let Some((prev_position, prev_orientation, position, orientation)) = poses {
             let old_pair = transforms.lookup(&entity)
                                     .cloned()
                                     .unwrap_or_default();
             let new_pair = TransformPair {
                 prev: Transform {
-                    position: interop::nalgebra_point3_to_cgmath(&rb.prev_state.position),
-                    orientation: interop::nalgebra_unit_quat_to_cgmath(&rb.prev_state.orientation),
+                    position: interop::nalgebra_point3_to_cgmath(&prev_position),
+                    orientation: interop::nalgebra_unit_quat_to_cgmath(&prev_orientation),
                     .. old_pair.prev
                 },
                 current: Transform {
-                    position: interop::nalgebra_point3_to_cgmath(&rb.state.position),
-                    orientation: interop::nalgebra_unit_quat_to_cgmath(&rb.state.orientation),
+                    position: interop::nalgebra_point3_to_cgmath(&position),
+                    orientation: interop::nalgebra_unit_quat_to_cgmath(&orientation),
                     .. old_pair.current
                 }
             };
@@ -1,13 +1,33 @@
-use ::physics::{RigidBody, StaticRigidBody, CollisionModel, ForceGenerator};
+use ::physics::{RigidBody, StaticRigidBody, KinematicRigidBody, KinematicMotion,
+    CollisionModel, CollisionFilter, ScopedForceGenerator, PhysicsMaterial, Joint, SleepThresholds};
 use ::render::{SceneRenderable};
 use ::core::Transform;
 
 pub struct EntityBlueprint {
     pub rigid_body: Option<RigidBody>,
     pub collision: Option<CollisionModel>,
+    pub collision_filter: Option<CollisionFilter>,
+
+    /// Whether the collision model is a sensor, which only
+    /// detects other collision models without affecting them
+    pub sensor: bool,
+
     pub renderable: Option<SceneRenderable>,
     pub transform: Option<Transform>,
-    pub force: Option<ForceGenerator>
+
+    /// A force generator, where any bodies it acts on or is scoped to are
+    /// referred to by the indices of their blueprints in the scene
+    pub force: Option<ScopedForceGenerator<usize>>,
+
+    pub material: Option<PhysicsMaterial>,
+
+    /// A joint between two other entities of the same scene, which are referred
+    /// to by the indices of their blueprints in the scene
+    pub joint: Option<Joint<usize>>,
+
+    /// Other entities of the same scene which this entity never collides with,
+    /// regardless of collision filters, referred to by the indices of their blueprints
+    pub no_collision_with: Vec<usize>
 }
 
 impl EntityBlueprint {
@@ -15,9 +35,14 @@ impl EntityBlueprint {
         EntityBlueprint {
             rigid_body: None,
             collision: None,
+            collision_filter: None,
+            sensor: false,
             renderable: None,
             transform: None,
-            force: None
+            force: None,
+            material: None,
+            joint: None,
+            no_collision_with: Vec::new()
         }
     }
 
@@ -31,4 +56,58 @@ impl EntityBlueprint {
         }
         self
     }
+
+    /// Turns a dynamic rigid body into a kinematic one, which follows the given motion
+    /// from its current pose.
+    #[allow(dead_code)]
+    pub fn make_kinematic(mut self, motion: KinematicMotion) -> Self {
+        if let Some(RigidBody::Dynamic(rb)) = self.rigid_body {
+            let kinematic_rb = KinematicRigidBody::new(rb.state.position, rb.state.orientation, motion);
+            self.rigid_body = Some(RigidBody::Kinematic(kinematic_rb));
+        }
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn make_sensor(mut self) -> Self {
+        self.sensor = true;
+        self
+    }
+
+    /// Enables continuous collision detection for a dynamic rigid body.
+    #[allow(dead_code)]
+    pub fn enable_ccd(mut self) -> Self {
+        if let Some(RigidBody::Dynamic(ref mut rb)) = self.rigid_body {
+            rb.ccd = true;
+        }
+        self
+    }
+
+    /// Sets the velocities below which a dynamic rigid body may fall asleep,
+    /// or prevents it from ever falling asleep if `None` is given.
+    #[allow(dead_code)]
+    pub fn with_sleep_thresholds(mut self, thresholds: Option<SleepThresholds>) -> Self {
+        if let Some(RigidBody::Dynamic(ref mut rb)) = self.rigid_body {
+            rb.sleep_thresholds = thresholds;
+        }
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_collision_filter(mut self, filter: CollisionFilter) -> Self {
+        self.collision_filter = Some(filter);
+        self
+    }
+
+    /// Prevents collisions with the entity of the blueprint at the given index in the scene.
+    #[allow(dead_code)]
+    pub fn without_collision_with(mut self, index: usize) -> Self {
+        self.no_collision_with.push(index);
+        self
+    }
+
+    pub fn with_material(mut self, material: PhysicsMaterial) -> Self {
+        self.material = Some(material);
+        self
+    }
 }
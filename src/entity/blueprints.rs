@@ -1,8 +1,9 @@
 use ::entity::EntityBlueprint;
-use render::{unit_sphere_renderable, box_renderable};
-use geometry::{Sphere, Cuboid};
-use physics::{Mass, RigidBody, DynamicRigidBody, DynamicBodyState, CollisionModel};
-use cgmath::{Vector3};
+use render::{unit_sphere_renderable, box_renderable, composite_renderable, Shading, SceneRenderable};
+use geometry::{Sphere, Cuboid, Capsule, Cylinder, Cone, Plane, SurfaceMesh, MassProperties,
+    unit_sphere, box_mesh, capsule_mesh, cylinder_mesh, cone_mesh, plane_mesh};
+use physics::{Mass, RigidBody, DynamicRigidBody, StaticRigidBody, DynamicBodyState, CollisionModel};
+use cgmath::{self, Vector3};
 use core::Transform;
 use nalgebra;
 use interop;
@@ -78,3 +79,342 @@ pub fn cuboid(cuboid: Cuboid<f64>, mass: f64) -> EntityBlueprint {
 
     blueprint
 }
+
+/// A blueprint of a rigid body composed of several collision models of uniform density,
+/// with zero velocity. The children are placed relative to the given position,
+/// while the origin of the resulting body is placed at the center of mass.
+pub fn compound(children: Vec<(nalgebra::Isometry3<f64>, CollisionModel)>,
+                position: nalgebra::Point3<f64>,
+                mass: f64) -> EntityBlueprint {
+    let center_of_mass = CollisionModel::Compound(children.clone()).mass_properties(1.0).center_of_mass;
+
+    // Move the children such that the center of mass coincides with the origin of the body
+    let shift = nalgebra::Isometry3::from_parts(
+        nalgebra::Translation3::from_vector(- center_of_mass.coords),
+        nalgebra::UnitQuaternion::identity());
+    let children: Vec<_> = children.into_iter()
+                                   .map(|(isometry, child)| (shift * isometry, child))
+                                   .collect();
+
+    solid(CollisionModel::Compound(children),
+          position + center_of_mass.coords,
+          nalgebra::UnitQuaternion::identity(),
+          mass)
+}
+
+/// A blueprint of a capsule with zero velocity.
+#[allow(dead_code)]
+pub fn capsule(capsule: Capsule<f64>, mass: f64) -> EntityBlueprint {
+    let model = CollisionModel::Capsule(Capsule {
+        center: nalgebra::Point3::origin(),
+        rotation: nalgebra::UnitQuaternion::identity(),
+        .. capsule
+    });
+    solid(model, capsule.center, capsule.rotation, mass)
+}
+
+/// A blueprint of a cylinder with zero velocity.
+#[allow(dead_code)]
+pub fn cylinder(cylinder: Cylinder<f64>, mass: f64) -> EntityBlueprint {
+    let model = CollisionModel::Cylinder(Cylinder {
+        center: nalgebra::Point3::origin(),
+        rotation: nalgebra::UnitQuaternion::identity(),
+        .. cylinder
+    });
+    solid(model, cylinder.center, cylinder.rotation, mass)
+}
+
+/// A blueprint of a cone with zero velocity.
+#[allow(dead_code)]
+pub fn cone(cone: Cone<f64>, mass: f64) -> EntityBlueprint {
+    // The center of mass lies halfway between the center and the base
+    let offset = nalgebra::Vector3::new(0.0, 0.5 * cone.half_height, 0.0);
+    let model = CollisionModel::Cone(Cone {
+        center: nalgebra::Point3::from_coordinates(offset),
+        rotation: nalgebra::UnitQuaternion::identity(),
+        .. cone
+    });
+    solid(model, cone.center - cone.rotation * offset, cone.rotation, mass)
+}
+
+/// A blueprint of a static plane, such as the ground.
+pub fn ground_plane(plane: Plane<f64>) -> EntityBlueprint {
+    let mut blueprint = EntityBlueprint::empty();
+    let model = CollisionModel::Plane(Plane { point: nalgebra::Point3::origin(), .. plane });
+
+    blueprint.renderable = Some(composite_renderable(&meshes_of(&model)));
+    blueprint.transform = Some(Transform {
+        position: interop::nalgebra_point3_to_cgmath(&plane.point),
+        .. Transform::default()
+    });
+    blueprint.collision = Some(model);
+    blueprint.rigid_body = Some(RigidBody::Static(StaticRigidBody {
+        position: plane.point,
+        orientation: nalgebra::UnitQuaternion::identity()
+    }));
+
+    blueprint
+}
+
+/// A blueprint of a body of uniform density enclosed by the given mesh, with zero velocity.
+/// The mesh must be closed and oriented counter-clockwise as seen from the outside.
+/// Its vertices are given in world coordinates, and are shifted such that the origin
+/// of the body is at its center of mass.
+///
+/// The mass properties are computed from the mesh itself, while collisions
+/// are detected against the convex hull of the mesh.
+#[allow(dead_code)]
+pub fn mesh(mesh: SurfaceMesh<f64>, density: f64) -> EntityBlueprint {
+    let properties = MassProperties::mesh(&mesh, density);
+    let center = properties.center_of_mass;
+
+    let vertices = mesh.vertices().iter()
+                       .map(|v| cgmath::Point3::new(v.x - center.x, v.y - center.y, v.z - center.z))
+                       .collect();
+    let shifted = SurfaceMesh::from_indices(vertices, mesh.triangle_indices().to_vec())
+        .expect("Shifting the vertices leaves the indices valid.");
+    let model = CollisionModel::convex_hull(&shifted)
+        .expect("A closed mesh enclosing a volume must have a convex hull.");
+    let renderable = composite_renderable(&[(single_precision(&shifted), Shading::Flat)]);
+
+    let properties = MassProperties {
+        center_of_mass: nalgebra::Point3::origin(),
+        .. properties
+    };
+    dynamic_body(model, renderable, properties, center, nalgebra::UnitQuaternion::identity())
+}
+
+/// A blueprint of static geometry such as terrain, given by a triangle mesh
+/// in world coordinates.
+#[allow(dead_code)]
+pub fn terrain(mesh: SurfaceMesh<f64>) -> EntityBlueprint {
+    let mut blueprint = EntityBlueprint::empty();
+    let model = CollisionModel::TriMesh(mesh);
+
+    blueprint.renderable = Some(composite_renderable(&meshes_of(&model)));
+    blueprint.transform = Some(Transform::default());
+    blueprint.collision = Some(model);
+    blueprint.rigid_body = Some(RigidBody::Static(StaticRigidBody {
+        position: nalgebra::Point3::origin(),
+        orientation: nalgebra::UnitQuaternion::identity()
+    }));
+
+    blueprint
+}
+
+/// A blueprint of a dynamic body at rest with uniform density. The collision model
+/// must be given relative to the center of mass of the body, and is also used
+/// to build the renderable.
+fn solid(model: CollisionModel,
+         position: nalgebra::Point3<f64>,
+         orientation: nalgebra::UnitQuaternion<f64>,
+         mass: f64) -> EntityBlueprint {
+    let properties = model.mass_properties(1.0).with_mass(mass);
+    let renderable = composite_renderable(&meshes_of(&model));
+    dynamic_body(model, renderable, properties, position, orientation)
+}
+
+fn dynamic_body(model: CollisionModel,
+                renderable: SceneRenderable,
+                properties: MassProperties,
+                position: nalgebra::Point3<f64>,
+                orientation: nalgebra::UnitQuaternion<f64>) -> EntityBlueprint {
+    let mut blueprint = EntityBlueprint::empty();
+
+    debug_assert!(nalgebra::norm(&properties.center_of_mass.coords) < 1e-9,
+        "The body must be centered at its center of mass.");
+    let inv_inertia_tensor = properties.inertia.try_inverse()
+                                .expect("Provided inertia tensor must be invertible.");
+
+    let rb_state = DynamicBodyState {
+        position: position,
+        orientation: orientation,
+        .. DynamicBodyState::default()
+    };
+
+    blueprint.renderable = Some(renderable);
+    blueprint.transform = Some(Transform {
+        position: interop::nalgebra_point3_to_cgmath(&position),
+        orientation: interop::nalgebra_unit_quat_to_cgmath(&orientation),
+        .. Transform::default()
+    });
+    blueprint.collision = Some(model);
+    blueprint.rigid_body = Some(RigidBody::Dynamic(DynamicRigidBody {
+        state: rb_state.clone(),
+        prev_state: rb_state,
+        inv_inertia_body: inv_inertia_tensor,
+        mass: Mass::new(properties.mass),
+        .. DynamicRigidBody::default()
+    }));
+
+    blueprint
+}
+
+/// Two spheres connected by a bar along the x axis, centered at the given position.
+#[allow(dead_code)]
+pub fn dumbbell(position: nalgebra::Point3<f64>, sphere_radius: f64, bar_length: f64, mass: f64)
+    -> EntityBlueprint {
+    let half_length = 0.5 * bar_length;
+    let bar_thickness = 0.25 * sphere_radius;
+    compound(vec![
+        placed(ball(sphere_radius), nalgebra::Vector3::new(- half_length, 0.0, 0.0)),
+        placed(ball(sphere_radius), nalgebra::Vector3::new(half_length, 0.0, 0.0)),
+        placed(block(nalgebra::Vector3::new(half_length, bar_thickness, bar_thickness)),
+               nalgebra::Vector3::new(0.0, 0.0, 0.0))
+    ], position, mass)
+}
+
+/// Two arms of equal length and square cross section, joined at a right angle.
+/// One arm extends along the x axis and the other along the z axis,
+/// from the outer corner at the given position.
+#[allow(dead_code)]
+pub fn l_shape(position: nalgebra::Point3<f64>, arm_length: f64, thickness: f64, mass: f64)
+    -> EntityBlueprint {
+    assert!(arm_length > thickness, "The arms must be longer than they are thick.");
+    let h = 0.5 * thickness;
+    let upright_half_length = 0.5 * (arm_length - thickness);
+    compound(vec![
+        placed(block(nalgebra::Vector3::new(0.5 * arm_length, h, h)),
+               nalgebra::Vector3::new(0.5 * arm_length, 0.0, h)),
+        placed(block(nalgebra::Vector3::new(h, h, upright_half_length)),
+               nalgebra::Vector3::new(h, 0.0, thickness + upright_half_length))
+    ], position, mass)
+}
+
+/// A table top supported by four legs at its corners. The given position is
+/// the point on the floor directly beneath the center of the table top.
+#[allow(dead_code)]
+pub fn table(position: nalgebra::Point3<f64>,
+             top_half_size: nalgebra::Vector3<f64>,
+             leg_length: f64,
+             leg_thickness: f64,
+             mass: f64) -> EntityBlueprint {
+    let h = 0.5 * leg_thickness;
+    let leg = nalgebra::Vector3::new(h, h, 0.5 * leg_length);
+    let (x, y) = (top_half_size.x - h, top_half_size.y - h);
+
+    let mut children = vec![
+        placed(block(top_half_size), nalgebra::Vector3::new(0.0, 0.0, leg_length + top_half_size.z))
+    ];
+    for &(sx, sy) in [(1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0), (1.0, -1.0)].iter() {
+        children.push(placed(block(leg), nalgebra::Vector3::new(sx * x, sy * y, 0.5 * leg_length)));
+    }
+    compound(children, position, mass)
+}
+
+fn ball(radius: f64) -> CollisionModel {
+    CollisionModel::Sphere(Sphere { radius: radius, center: nalgebra::Point3::origin() })
+}
+
+fn block(half_size: nalgebra::Vector3<f64>) -> CollisionModel {
+    CollisionModel::Cuboid(Cuboid {
+        center: nalgebra::Point3::origin(),
+        half_size: half_size,
+        rotation: nalgebra::UnitQuaternion::identity()
+    })
+}
+
+fn placed(model: CollisionModel, offset: nalgebra::Vector3<f64>)
+    -> (nalgebra::Isometry3<f64>, CollisionModel) {
+    let isometry = nalgebra::Isometry3::from_parts(nalgebra::Translation3::from_vector(offset),
+                                                   nalgebra::UnitQuaternion::identity());
+    (isometry, model)
+}
+
+fn meshes_of(model: &CollisionModel) -> Vec<(SurfaceMesh<f32>, Shading)> {
+    let mut meshes = Vec::new();
+    collect_meshes(model, &nalgebra::Isometry3::identity(), &mut meshes);
+    meshes
+}
+
+/// Tessellates the collision model for rendering, with vertices transformed by the isometry.
+fn collect_meshes(model: &CollisionModel,
+                  isometry: &nalgebra::Isometry3<f64>,
+                  meshes: &mut Vec<(SurfaceMesh<f32>, Shading)>) {
+    match *model {
+        CollisionModel::Sphere(ref sphere) => {
+            let mesh = transform_mesh(&unit_sphere(3), |v| {
+                isometry * (sphere.center + sphere.radius * v.coords)
+            });
+            meshes.push((mesh, Shading::Smooth));
+        },
+        CollisionModel::Cuboid(ref cuboid) => {
+            let half_size = cuboid.half_size;
+            let mesh = box_mesh(half_size.x as f32, half_size.y as f32, half_size.z as f32);
+            let mesh = transform_mesh(&mesh, |v| {
+                isometry * (cuboid.center + cuboid.rotation * v.coords)
+            });
+            meshes.push((mesh, Shading::Flat));
+        },
+        CollisionModel::Capsule(ref capsule) => {
+            let mesh = capsule_mesh(capsule.half_height as f32, capsule.radius as f32, 32, 8);
+            let mesh = transform_mesh(&mesh, |v| isometry * (capsule.center + capsule.rotation * v.coords));
+            meshes.push((mesh, Shading::Smooth));
+        },
+        CollisionModel::Cylinder(ref cylinder) => {
+            let mesh = cylinder_mesh(cylinder.half_height as f32, cylinder.radius as f32, 32);
+            let mesh = transform_mesh(&mesh, |v| isometry * (cylinder.center + cylinder.rotation * v.coords));
+            meshes.push((mesh, Shading::Smooth));
+        },
+        CollisionModel::Cone(ref cone) => {
+            let mesh = cone_mesh(cone.half_height as f32, cone.radius as f32, 32);
+            let mesh = transform_mesh(&mesh, |v| isometry * (cone.center + cone.rotation * v.coords));
+            meshes.push((mesh, Shading::Smooth));
+        },
+        CollisionModel::Plane(ref plane) => {
+            // Planes are infinite, so we can only render a large square
+            let rotation = rotation_from_y_axis(&plane.normal);
+            let mesh = transform_mesh(&plane_mesh(100.0), |v| isometry * (plane.point + rotation * v.coords));
+            meshes.push((mesh, Shading::Flat));
+        },
+        CollisionModel::ConvexHull(ref mesh) => {
+            let mesh = transform_mesh(&single_precision(mesh), |v| isometry * v);
+            meshes.push((mesh, Shading::Flat));
+        },
+        CollisionModel::TriMesh(ref mesh) => {
+            let mesh = transform_mesh(&single_precision(mesh), |v| isometry * v);
+            meshes.push((mesh, Shading::Smooth));
+        },
+        CollisionModel::Compound(ref children) => {
+            for &(ref child_isometry, ref child) in children {
+                collect_meshes(child, &(isometry * child_isometry), meshes);
+            }
+        }
+    }
+}
+
+fn single_precision(mesh: &SurfaceMesh<f64>) -> SurfaceMesh<f32> {
+    let vertices = mesh.vertices().iter()
+                                  .map(|v| cgmath::Point3::new(v.x as f32, v.y as f32, v.z as f32))
+                                  .collect();
+    SurfaceMesh::from_indices(vertices, mesh.triangle_indices().to_vec())
+        .expect("Converting the vertices leaves the indices valid.")
+}
+
+fn transform_mesh<F>(mesh: &SurfaceMesh<f32>, transform: F) -> SurfaceMesh<f32>
+    where F: Fn(nalgebra::Point3<f64>) -> nalgebra::Point3<f64>
+{
+    let vertices = mesh.vertices().iter()
+        .map(|v| {
+            let v = transform(nalgebra::Point3::new(v.x as f64, v.y as f64, v.z as f64));
+            cgmath::Point3::new(v.x as f32, v.y as f32, v.z as f32)
+        })
+        .collect();
+    SurfaceMesh::from_indices(vertices, mesh.triangle_indices().to_vec())
+        .expect("Transforming the vertices leaves the indices valid.")
+}
+
+/// The shortest rotation that takes the y axis to the given unit vector.
+fn rotation_from_y_axis(direction: &nalgebra::Vector3<f64>) -> nalgebra::UnitQuaternion<f64> {
+    let y = nalgebra::Vector3::new(0.0, 1.0, 0.0);
+    let axis = y.cross(direction);
+    let (sin, cos) = (nalgebra::norm(&axis), y.dot(direction));
+    if sin > 1e-12 {
+        nalgebra::UnitQuaternion::from_axis_angle(&nalgebra::Unit::new_normalize(axis), sin.atan2(cos))
+    } else if cos > 0.0 {
+        nalgebra::UnitQuaternion::identity()
+    } else {
+        let x = nalgebra::Unit::new_normalize(nalgebra::Vector3::new(1.0, 0.0, 0.0));
+        nalgebra::UnitQuaternion::from_axis_angle(&x, ::std::f64::consts::PI)
+    }
+}
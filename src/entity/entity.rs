@@ -16,6 +16,14 @@ impl From<Entity> for u32 {
     }
 }
 
+impl From<u32> for Entity {
+    /// Refers to the entity with the given ID, for example when reading it back
+    /// after it has been written out. The entity need not be alive.
+    fn from(id: u32) -> Entity {
+        Entity { id: id }
+    }
+}
+
 impl From<Entity> for usize {
     fn from(e: Entity) -> usize {
         e.id as usize
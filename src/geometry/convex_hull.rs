@@ -0,0 +1,302 @@
+use geometry::{SurfaceMesh, TriangleIndices};
+use cgmath::{Point3, Vector3, InnerSpace};
+use std::collections::HashMap;
+
+struct Face {
+    vertices: [usize; 3],
+    normal: Vector3<f64>,
+    offset: f64,
+
+    /// Indices of the points that lie in front of the face,
+    /// and which have not been assigned to any other face.
+    outside: Vec<usize>,
+    alive: bool
+}
+
+impl Face {
+    fn new(points: &[Point3<f64>], a: usize, b: usize, c: usize) -> Face {
+        let normal = (points[b] - points[a]).cross(points[c] - points[a]).normalize();
+        Face {
+            vertices: [a, b, c],
+            normal: normal,
+            offset: normal.dot(points[a] - Point3::new(0.0, 0.0, 0.0)),
+            outside: Vec::new(),
+            alive: true
+        }
+    }
+
+    fn distance(&self, point: &Point3<f64>) -> f64 {
+        self.normal.dot(point - Point3::new(0.0, 0.0, 0.0)) - self.offset
+    }
+
+    fn edges(&self) -> [(usize, usize); 3] {
+        let v = &self.vertices;
+        [(v[0], v[1]), (v[1], v[2]), (v[2], v[0])]
+    }
+}
+
+/// Computes the convex hull of a set of points with the Quickhull algorithm.
+/// The hull of a mesh is given by the hull of its vertices.
+///
+/// The triangles of the returned mesh are oriented counter-clockwise when viewed
+/// from the outside, and the mesh only contains the vertices of the hull.
+/// Points with infinite or NaN coordinates are ignored. Returns `None` if the
+/// remaining points do not span a volume, i.e. if they are all coplanar.
+pub fn convex_hull(points: &[Point3<f64>]) -> Option<SurfaceMesh<f64>> {
+    // Comparisons between distances would be meaningless for non-finite points
+    let finite_points: Vec<Point3<f64>> = points.iter()
+        .filter(|p| p.x.is_finite() && p.y.is_finite() && p.z.is_finite())
+        .cloned()
+        .collect();
+    let points = &finite_points[..];
+
+    let tolerance = hull_tolerance(points);
+    let mut faces = match initial_tetrahedron(points, tolerance) {
+        Some(faces) => faces,
+        None => return None
+    };
+
+    // Directed edges of the hull, each associated with the face it belongs to
+    let mut edge_faces = HashMap::new();
+    for (index, face) in faces.iter().enumerate() {
+        for &edge in face.edges().iter() {
+            edge_faces.insert(edge, index);
+        }
+    }
+
+    let hull_vertices: Vec<usize> = faces.iter().flat_map(|face| face.vertices.to_vec()).collect();
+    let candidates: Vec<usize> = (0 .. points.len()).filter(|i| !hull_vertices.contains(i)).collect();
+    assign_to_faces(points, &candidates, &mut faces, 0, tolerance);
+
+    while let Some(start) = faces.iter().position(|face| face.alive && !face.outside.is_empty()) {
+        // The point farthest in front of the face is certainly on the hull
+        let eye = {
+            let face = &faces[start];
+            face.outside.iter().cloned()
+                .max_by(|&i, &j| face.distance(&points[i]).partial_cmp(&face.distance(&points[j])).unwrap())
+                .unwrap()
+        };
+
+        // Find all faces visible from the eye point, which are connected to the start face,
+        // along with the horizon: the boundary of the visible region
+        let mut visible = vec![start];
+        let mut horizon = Vec::new();
+        let mut stack = vec![start];
+        while let Some(index) = stack.pop() {
+            for &(a, b) in faces[index].edges().iter() {
+                let neighbor = edge_faces[&(b, a)];
+                if visible.contains(&neighbor) {
+                    continue;
+                }
+                if faces[neighbor].distance(&points[eye]) > tolerance {
+                    visible.push(neighbor);
+                    stack.push(neighbor);
+                } else {
+                    horizon.push((a, b));
+                }
+            }
+        }
+
+        let mut orphans = Vec::new();
+        for &index in &visible {
+            let face = &mut faces[index];
+            face.alive = false;
+            orphans.extend(face.outside.drain(..).filter(|&i| i != eye));
+            for edge in face.edges().iter() {
+                edge_faces.remove(edge);
+            }
+        }
+
+        // Connect the eye point to every edge on the horizon
+        let first_new_face = faces.len();
+        for &(a, b) in &horizon {
+            let face = Face::new(points, a, b, eye);
+            for &edge in face.edges().iter() {
+                edge_faces.insert(edge, faces.len());
+            }
+            faces.push(face);
+        }
+        assign_to_faces(points, &orphans, &mut faces, first_new_face, tolerance);
+    }
+
+    // Only keep the points that are vertices of the hull
+    let mut vertex_map = HashMap::new();
+    let mut vertices = Vec::new();
+    let mut triangles = Vec::new();
+    for face in faces.iter().filter(|face| face.alive) {
+        let mut indices = [0; 3];
+        for (new_index, &old_index) in indices.iter_mut().zip(face.vertices.iter()) {
+            *new_index = *vertex_map.entry(old_index).or_insert_with(|| {
+                vertices.push(points[old_index]);
+                vertices.len() - 1
+            });
+        }
+        triangles.push(TriangleIndices::new(indices[0], indices[1], indices[2]));
+    }
+
+    Some(SurfaceMesh::from_indices(vertices, triangles)
+        .expect("Indices of the hull must be valid."))
+}
+
+/// Points closer to a face than this tolerance are considered to lie in its plane.
+fn hull_tolerance(points: &[Point3<f64>]) -> f64 {
+    let max_coordinate = points.iter()
+        .fold(0.0f64, |max, p| max.max(p.x.abs()).max(p.y.abs()).max(p.z.abs()));
+    1e-10 * max_coordinate.max(1.0)
+}
+
+/// Assigns each point to the outside set of the first face, starting from
+/// the given face index, that it lies in front of. Points that lie in front of
+/// no face are inside the hull, and are discarded.
+fn assign_to_faces(points: &[Point3<f64>], candidates: &[usize], faces: &mut [Face],
+                   first_face: usize, tolerance: f64) {
+    for &i in candidates {
+        let face = faces[first_face ..].iter_mut()
+                                       .find(|face| face.alive && face.distance(&points[i]) > tolerance);
+        if let Some(face) = face {
+            face.outside.push(i);
+        }
+    }
+}
+
+/// Constructs a tetrahedron from four extreme points, with outward facing triangles.
+fn initial_tetrahedron(points: &[Point3<f64>], tolerance: f64) -> Option<Vec<Face>> {
+    if points.len() < 4 {
+        return None;
+    }
+
+    let farthest = |distance: &Fn(&Point3<f64>) -> f64| {
+        (0 .. points.len())
+            .max_by(|&i, &j| distance(&points[i]).partial_cmp(&distance(&points[j])).unwrap())
+            .unwrap()
+    };
+
+    let a = farthest(&|p: &Point3<f64>| -p.x);
+    let b = farthest(&|p: &Point3<f64>| (p - points[a]).magnitude2());
+    let ab = points[b] - points[a];
+    if ab.magnitude() <= tolerance {
+        return None;
+    }
+
+    let c = farthest(&|p: &Point3<f64>| ab.cross(p - points[a]).magnitude2());
+    let normal = ab.cross(points[c] - points[a]);
+    if normal.magnitude() <= tolerance * ab.magnitude() {
+        return None;
+    }
+
+    let d = farthest(&|p: &Point3<f64>| normal.dot(p - points[a]).abs());
+    if normal.normalize().dot(points[d] - points[a]).abs() <= tolerance {
+        return None;
+    }
+
+    // Orient the base triangle such that d lies behind it
+    let (b, c) = if normal.dot(points[d] - points[a]) > 0.0 { (c, b) } else { (b, c) };
+    Some(vec![
+        Face::new(points, a, b, c),
+        Face::new(points, a, d, b),
+        Face::new(points, b, d, c),
+        Face::new(points, c, d, a)
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::convex_hull;
+    use geometry::{SurfaceMesh, unit_sphere};
+    use cgmath::{Point3, InnerSpace, EuclideanSpace};
+
+    /// A simple deterministic pseudo-random number generator
+    fn random_points(n: usize, seed: u64) -> Vec<Point3<f64>> {
+        let mut state = seed;
+        let mut next = move || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+        (0 .. n).map(|_| Point3::new(2.0 * next() - 1.0, 2.0 * next() - 1.0, 2.0 * next() - 1.0))
+                .collect()
+    }
+
+    fn volume(mesh: &SurfaceMesh<f64>) -> f64 {
+        mesh.triangles()
+            .map(|t| t.a.to_vec().dot(t.b.to_vec().cross(t.c.to_vec())) / 6.0)
+            .sum()
+    }
+
+    fn assert_contains(hull: &SurfaceMesh<f64>, points: &[Point3<f64>]) {
+        for triangle in hull.triangles() {
+            let normal = (triangle.b - triangle.a).cross(triangle.c - triangle.a).normalize();
+            for p in points {
+                assert!(normal.dot(p - triangle.a) < 1e-9, "Point {:?} lies outside the hull", p);
+            }
+        }
+    }
+
+    #[test]
+    fn hull_of_cube_with_interior_points() {
+        let mut points = random_points(200, 42);
+        for &x in [-1.0, 1.0].iter() {
+            for &y in [-1.0, 1.0].iter() {
+                for &z in [-1.0, 1.0].iter() {
+                    points.push(Point3::new(x, y, z));
+                }
+            }
+        }
+
+        let hull = convex_hull(&points).unwrap();
+
+        assert_eq!(8, hull.num_vertices());
+        assert_eq!(12, hull.num_triangles());
+        assert!((volume(&hull) - 8.0).abs() < 1e-12);
+        assert_contains(&hull, &points);
+    }
+
+    #[test]
+    fn hull_of_sphere_contains_all_vertices() {
+        let sphere = unit_sphere(2);
+        let points: Vec<Point3<f64>> = sphere.vertices().iter()
+            .map(|v| Point3::new(v.x as f64, v.y as f64, v.z as f64))
+            .collect();
+
+        let hull = convex_hull(&points).unwrap();
+
+        // Every vertex of the sphere is extreme, so all of them are on the hull
+        assert_eq!(sphere.num_vertices(), hull.num_vertices());
+        assert_eq!(2 * hull.num_vertices() - 4, hull.num_triangles());
+        assert_contains(&hull, &points);
+    }
+
+    #[test]
+    fn hull_of_random_points_contains_all_points() {
+        let points = random_points(1000, 7);
+        let hull = convex_hull(&points).unwrap();
+
+        assert!(volume(&hull) > 0.0 && volume(&hull) < 8.0);
+        assert_contains(&hull, &points);
+    }
+
+    #[test]
+    fn coplanar_points_have_no_hull() {
+        let points: Vec<Point3<f64>> = random_points(50, 3).into_iter()
+            .map(|p| Point3::new(p.x, p.y, 0.0))
+            .collect();
+        assert!(convex_hull(&points).is_none());
+        assert!(convex_hull(&points[0 .. 3]).is_none());
+    }
+
+    #[test]
+    fn non_finite_points_are_ignored() {
+        let mut points = random_points(100, 5);
+        points.push(Point3::new(::std::f64::NAN, 0.0, 0.0));
+        points.push(Point3::new(0.0, ::std::f64::INFINITY, 0.0));
+        let hull = convex_hull(&points).unwrap();
+
+        assert_contains(&hull, &points[0 .. 100]);
+        assert_eq!(hull.num_vertices(), convex_hull(&points[0 .. 100]).unwrap().num_vertices());
+
+        let coplanar_with_nan = vec![
+            Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0), Point3::new(0.0, 0.0, ::std::f64::NAN)
+        ];
+        assert!(convex_hull(&coplanar_with_nan).is_none());
+    }
+}
@@ -0,0 +1,394 @@
+use geometry::{Sphere, Cuboid, Capsule, Cylinder, Cone, SurfaceMesh};
+use nalgebra::{zero, Point3, Vector3, Matrix3, UnitQuaternion, Isometry3, Translation3};
+use std::f64::consts::PI;
+
+/// The mass, center of mass and inertia tensor of a rigid body.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MassProperties {
+    pub mass: f64,
+    pub center_of_mass: Point3<f64>,
+
+    /// The inertia tensor with respect to the center of mass.
+    pub inertia: Matrix3<f64>
+}
+
+impl MassProperties {
+    /// Mass properties of a solid sphere of uniform density.
+    pub fn sphere(sphere: &Sphere<f64>, density: f64) -> Self {
+        let r = sphere.radius;
+        let mass = density * (4.0 / 3.0) * PI * r * r * r;
+        MassProperties {
+            mass: mass,
+            center_of_mass: sphere.center,
+            inertia: Matrix3::identity() * ((2.0 / 5.0) * mass * r * r)
+        }
+    }
+
+    /// Mass properties of a solid cuboid of uniform density.
+    pub fn cuboid(cuboid: &Cuboid<f64>, density: f64) -> Self {
+        let extents = 2.0 * cuboid.half_size;
+        let mass = density * extents.x * extents.y * extents.z;
+        let diagonal = Vector3::new(extents.y * extents.y + extents.z * extents.z,
+                                    extents.x * extents.x + extents.z * extents.z,
+                                    extents.x * extents.x + extents.y * extents.y);
+        let local = MassProperties {
+            mass: mass,
+            center_of_mass: Point3::origin(),
+            inertia: (mass / 12.0) * Matrix3::from_diagonal(&diagonal)
+        };
+        local.placed_at(&cuboid.center, cuboid.rotation)
+    }
+
+    /// Mass properties of a solid capsule of uniform density.
+    pub fn capsule(capsule: &Capsule<f64>, density: f64) -> Self {
+        let (h, r) = (capsule.half_height, capsule.radius);
+        let cylinder_mass = density * PI * r * r * 2.0 * h;
+        let hemispheres_mass = density * (4.0 / 3.0) * PI * r * r * r;
+
+        // The hemispheres contribute their inertia about their own centers of mass,
+        // which lie 3r/8 from the flat faces, shifted to the center of the capsule
+        let axial = cylinder_mass * r * r / 2.0 + hemispheres_mass * (2.0 / 5.0) * r * r;
+        let transverse = cylinder_mass * (h * h / 3.0 + r * r / 4.0)
+            + hemispheres_mass * ((2.0 / 5.0) * r * r + h * h + (3.0 / 4.0) * h * r);
+        let local = MassProperties {
+            mass: cylinder_mass + hemispheres_mass,
+            center_of_mass: Point3::origin(),
+            inertia: Matrix3::from_diagonal(&Vector3::new(transverse, axial, transverse))
+        };
+        local.placed_at(&capsule.center, capsule.rotation)
+    }
+
+    /// Mass properties of a solid cylinder of uniform density.
+    pub fn cylinder(cylinder: &Cylinder<f64>, density: f64) -> Self {
+        let (h, r) = (cylinder.half_height, cylinder.radius);
+        let mass = density * PI * r * r * 2.0 * h;
+        let axial = mass * r * r / 2.0;
+        let transverse = mass * (3.0 * r * r + 4.0 * h * h) / 12.0;
+        let local = MassProperties {
+            mass: mass,
+            center_of_mass: Point3::origin(),
+            inertia: Matrix3::from_diagonal(&Vector3::new(transverse, axial, transverse))
+        };
+        local.placed_at(&cylinder.center, cylinder.rotation)
+    }
+
+    /// Mass properties of a solid cone of uniform density.
+    pub fn cone(cone: &Cone<f64>, density: f64) -> Self {
+        let (height, r) = (2.0 * cone.half_height, cone.radius);
+        let mass = density * PI * r * r * height / 3.0;
+        let axial = (3.0 / 10.0) * mass * r * r;
+        let transverse = (3.0 / 20.0) * mass * r * r + (3.0 / 80.0) * mass * height * height;
+        // The center of mass lies a quarter of the height above the base
+        let local = MassProperties {
+            mass: mass,
+            center_of_mass: Point3::new(0.0, - 0.25 * height, 0.0),
+            inertia: Matrix3::from_diagonal(&Vector3::new(transverse, axial, transverse))
+        };
+        local.placed_at(&cone.center, cone.rotation)
+    }
+
+    /// Mass properties of the solid of uniform density enclosed by the given mesh,
+    /// which must be closed, and whose triangles must be oriented counter-clockwise
+    /// as seen from the outside. For unit density, the mass equals the volume.
+    ///
+    /// The integrals over the volume are transformed into integrals over the surface
+    /// by the divergence theorem, which are then evaluated exactly for each triangle.
+    /// See David Eberly, "Polyhedral Mass Properties (Revisited)",
+    /// https://www.geometrictools.com/Documentation/PolyhedralMassProperties.pdf
+    pub fn mesh(mesh: &SurfaceMesh<f64>, density: f64) -> Self {
+        // Integrate relative to a point on the surface, which avoids
+        // cancellation for meshes located far from the origin
+        let reference = mesh.vertices().first().map_or(Vector3::new(0.0, 0.0, 0.0),
+                                                        |v| Vector3::new(v.x, v.y, v.z));
+
+        // Integrals of 1, x, y, z, x², y², z², xy, yz and zx over the volume
+        let mut integrals = [0.0; 10];
+        for triangle in mesh.triangles() {
+            let p: Vec<Vector3<f64>> = [triangle.a, triangle.b, triangle.c].iter()
+                .map(|v| Vector3::new(v.x, v.y, v.z) - reference)
+                .collect();
+            let d = (p[1] - p[0]).cross(&(p[2] - p[0]));
+            let (f1x, f2x, f3x, g0x, g1x, g2x) = subexpressions(p[0].x, p[1].x, p[2].x);
+            let (_, f2y, f3y, g0y, g1y, g2y) = subexpressions(p[0].y, p[1].y, p[2].y);
+            let (_, f2z, f3z, g0z, g1z, g2z) = subexpressions(p[0].z, p[1].z, p[2].z);
+
+            integrals[0] += d.x * f1x;
+            integrals[1] += d.x * f2x;
+            integrals[2] += d.y * f2y;
+            integrals[3] += d.z * f2z;
+            integrals[4] += d.x * f3x;
+            integrals[5] += d.y * f3y;
+            integrals[6] += d.z * f3z;
+            integrals[7] += d.x * (p[0].y * g0x + p[1].y * g1x + p[2].y * g2x);
+            integrals[8] += d.y * (p[0].z * g0y + p[1].z * g1y + p[2].z * g2y);
+            integrals[9] += d.z * (p[0].x * g0z + p[1].x * g1z + p[2].x * g2z);
+        }
+        let weights = [1.0 / 6.0, 1.0 / 24.0, 1.0 / 24.0, 1.0 / 24.0, 1.0 / 60.0,
+                       1.0 / 60.0, 1.0 / 60.0, 1.0 / 120.0, 1.0 / 120.0, 1.0 / 120.0];
+        for (integral, weight) in integrals.iter_mut().zip(weights.iter()) {
+            *integral *= density * weight;
+        }
+
+        let mass = integrals[0];
+        assert!(mass > 0.0, "The mesh must be closed, and oriented counter-clockwise from the outside.");
+        let c = Vector3::new(integrals[1], integrals[2], integrals[3]) / mass;
+
+        // Second moments with respect to the center of mass
+        let xx = integrals[4] - mass * c.x * c.x;
+        let yy = integrals[5] - mass * c.y * c.y;
+        let zz = integrals[6] - mass * c.z * c.z;
+        let xy = integrals[7] - mass * c.x * c.y;
+        let yz = integrals[8] - mass * c.y * c.z;
+        let zx = integrals[9] - mass * c.z * c.x;
+
+        MassProperties {
+            mass: mass,
+            center_of_mass: Point3::from_coordinates(c + reference),
+            inertia: Matrix3::new(yy + zz, -xy, -zx,
+                                  -xy, xx + zz, -yz,
+                                  -zx, -yz, xx + yy)
+        }
+    }
+
+    fn placed_at(&self, center: &Point3<f64>, rotation: UnitQuaternion<f64>) -> Self {
+        self.transformed(&Isometry3::from_parts(Translation3::from_vector(center.coords), rotation))
+    }
+
+    /// Expresses the mass properties in the coordinate system
+    /// in which the body is placed by the given isometry.
+    pub fn transformed(&self, isometry: &Isometry3<f64>) -> Self {
+        let rotation = isometry.rotation.to_rotation_matrix();
+        let inverse_rotation = isometry.rotation.inverse().to_rotation_matrix();
+        MassProperties {
+            mass: self.mass,
+            center_of_mass: isometry * self.center_of_mass,
+            inertia: rotation * (self.inertia * inverse_rotation)
+        }
+    }
+
+    /// Computes the inertia tensor with respect to the given point
+    /// by the parallel axis theorem.
+    pub fn inertia_about(&self, point: &Point3<f64>) -> Matrix3<f64> {
+        let d = self.center_of_mass - point;
+        self.inertia + self.mass * (Matrix3::identity() * d.dot(&d) - d * d.transpose())
+    }
+
+    /// Combines the mass properties of several parts into the mass properties
+    /// of the rigid body composed of all the parts.
+    pub fn combine(parts: &[MassProperties]) -> Self {
+        let mass: f64 = parts.iter().map(|part| part.mass).sum();
+        assert!(mass > 0.0, "The combined mass must be positive.");
+
+        let weighted_center = parts.iter()
+            .fold(zero::<Vector3<f64>>(), |sum, part| sum + part.mass * part.center_of_mass.coords);
+        let center_of_mass = Point3::from_coordinates(weighted_center / mass);
+        let inertia = parts.iter()
+            .fold(zero::<Matrix3<f64>>(), |sum, part| sum + part.inertia_about(&center_of_mass));
+
+        MassProperties {
+            mass: mass,
+            center_of_mass: center_of_mass,
+            inertia: inertia
+        }
+    }
+
+    /// Uniformly rescales the density such that the total mass
+    /// equals the given mass.
+    pub fn with_mass(&self, mass: f64) -> Self {
+        let scale = mass / self.mass;
+        MassProperties {
+            mass: mass,
+            center_of_mass: self.center_of_mass,
+            inertia: scale * self.inertia
+        }
+    }
+}
+
+/// Subexpressions of the polynomial integrals over a triangle, given the values
+/// of a single coordinate at its three vertices.
+fn subexpressions(w0: f64, w1: f64, w2: f64) -> (f64, f64, f64, f64, f64, f64) {
+    let temp0 = w0 + w1;
+    let f1 = temp0 + w2;
+    let temp1 = w0 * w0;
+    let temp2 = temp1 + w1 * temp0;
+    let f2 = temp2 + w2 * f1;
+    let f3 = w0 * temp1 + w1 * temp2 + w2 * f2;
+    let g0 = f2 + w0 * (f1 + w0);
+    let g1 = f2 + w1 * (f1 + w1);
+    let g2 = f2 + w2 * (f1 + w2);
+    (f1, f2, f3, g0, g1, g2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MassProperties;
+    use geometry::{Sphere, Cuboid, Capsule, Cylinder, Cone, SurfaceMesh, unit_sphere, box_mesh};
+    use cgmath;
+    use nalgebra::{zero, norm, Point3, Vector3, Matrix3, Unit, UnitQuaternion, Isometry3, Translation3};
+
+    fn assert_matrix_eq(expected: &Matrix3<f64>, actual: &Matrix3<f64>) {
+        let error = (expected - actual).iter().fold(0.0f64, |max, x| max.max(x.abs()));
+        assert!(error < 1e-12, "Expected {:?}, got {:?}", expected, actual);
+    }
+
+    #[test]
+    fn adjacent_cubes_combine_into_cuboid() {
+        let cube = |x| Cuboid {
+            center: Point3::new(x, 0.0, 0.0),
+            half_size: Vector3::new(0.5, 0.5, 0.5),
+            rotation: UnitQuaternion::identity()
+        };
+        let combined = MassProperties::combine(&[
+            MassProperties::cuboid(&cube(-0.5), 2.0),
+            MassProperties::cuboid(&cube(0.5), 2.0)
+        ]);
+        let expected = MassProperties::cuboid(&Cuboid {
+            center: Point3::origin(),
+            half_size: Vector3::new(1.0, 0.5, 0.5),
+            rotation: UnitQuaternion::identity()
+        }, 2.0);
+
+        assert!((combined.mass - expected.mass).abs() < 1e-12);
+        assert!(norm(&(combined.center_of_mass - expected.center_of_mass)) < 1e-12);
+        assert_matrix_eq(&expected.inertia, &combined.inertia);
+    }
+
+    #[test]
+    fn rotated_cuboid_has_permuted_principal_moments() {
+        let half_size = Vector3::new(1.0, 2.0, 3.0);
+        let axis_aligned = MassProperties::cuboid(&Cuboid {
+            center: Point3::origin(),
+            half_size: half_size,
+            rotation: UnitQuaternion::identity()
+        }, 1.0);
+        // Rotating by a quarter turn about z swaps the x and y axes
+        let rotation = UnitQuaternion::from_axis_angle(
+            &Unit::new_normalize(Vector3::new(0.0, 0.0, 1.0)), 0.5 * ::std::f64::consts::PI);
+        let rotated = MassProperties::cuboid(&Cuboid {
+            center: Point3::origin(),
+            half_size: half_size,
+            rotation: rotation
+        }, 1.0);
+
+        let (ixx, iyy, izz) = (axis_aligned.inertia[(0, 0)],
+                               axis_aligned.inertia[(1, 1)],
+                               axis_aligned.inertia[(2, 2)]);
+        assert_matrix_eq(&Matrix3::from_diagonal(&Vector3::new(iyy, ixx, izz)), &rotated.inertia);
+    }
+
+    #[test]
+    fn transformed_sphere_inertia_about_origin_follows_parallel_axis_theorem() {
+        let sphere = Sphere { radius: 1.0, center: Point3::origin() };
+        let properties = MassProperties::sphere(&sphere, 1.0);
+        let isometry = Isometry3::from_parts(Translation3::new(0.0, 2.0, 0.0),
+                                             UnitQuaternion::identity());
+        let moved = properties.transformed(&isometry);
+        let inertia = moved.inertia_about(&Point3::origin());
+
+        let m = properties.mass;
+        let i = properties.inertia[(0, 0)];
+        assert_matrix_eq(&Matrix3::from_diagonal(&Vector3::new(i + 4.0 * m, i, i + 4.0 * m)), &inertia);
+    }
+
+    /// Approximates the mass properties of a body of unit density by sampling
+    /// the midpoints of a regular grid of cells covering the cube [-1, 1]^3.
+    fn sampled_mass_properties<F>(inside: F) -> MassProperties where F: Fn(&Point3<f64>) -> bool {
+        let n = 100;
+        let cell = 2.0 / n as f64;
+        let coordinate = |i: usize| -1.0 + (i as f64 + 0.5) * cell;
+
+        let mut parts = Vec::new();
+        for i in 0 .. n {
+            for j in 0 .. n {
+                for k in 0 .. n {
+                    let p = Point3::new(coordinate(i), coordinate(j), coordinate(k));
+                    if inside(&p) {
+                        parts.push(MassProperties {
+                            mass: cell * cell * cell,
+                            center_of_mass: p,
+                            inertia: zero()
+                        });
+                    }
+                }
+            }
+        }
+        MassProperties::combine(&parts)
+    }
+
+    fn assert_approximately_equal(expected: &MassProperties, actual: &MassProperties) {
+        let tolerance = 0.02 * expected.mass;
+        assert!((expected.mass - actual.mass).abs() < tolerance,
+            "Expected {:?}, got {:?}", expected, actual);
+        assert!(norm(&(expected.center_of_mass - actual.center_of_mass)) < 0.02,
+            "Expected {:?}, got {:?}", expected, actual);
+        let error = (expected.inertia - actual.inertia).iter().fold(0.0f64, |max, x| max.max(x.abs()));
+        assert!(error < 0.03 * expected.inertia.iter().fold(0.0f64, |max, x| max.max(x.abs())),
+            "Expected {:?}, got {:?}", expected, actual);
+    }
+
+    #[test]
+    fn solids_of_revolution_match_sampled_mass_properties() {
+        let (h, r) = (0.4, 0.5);
+        let capsule = Capsule { center: Point3::origin(), half_height: h, radius: r,
+                                rotation: UnitQuaternion::identity() };
+        let cylinder = Cylinder { center: Point3::origin(), half_height: h, radius: r,
+                                  rotation: UnitQuaternion::identity() };
+        let cone = Cone { center: Point3::origin(), half_height: h, radius: r,
+                          rotation: UnitQuaternion::identity() };
+        let radial = |p: &Point3<f64>| (p.x * p.x + p.z * p.z).sqrt();
+
+        assert_approximately_equal(&MassProperties::capsule(&capsule, 1.0),
+            &sampled_mass_properties(|p| {
+                let axial_distance = (p.y.abs() - h).max(0.0);
+                radial(p).powi(2) + axial_distance * axial_distance <= r * r
+            }));
+        assert_approximately_equal(&MassProperties::cylinder(&cylinder, 1.0),
+            &sampled_mass_properties(|p| p.y.abs() <= h && radial(p) <= r));
+        assert_approximately_equal(&MassProperties::cone(&cone, 1.0),
+            &sampled_mass_properties(|p| p.y.abs() <= h && radial(p) <= r * (h - p.y) / (2.0 * h)));
+    }
+
+    fn double_precision<F>(mesh: &SurfaceMesh<f32>, transform: F) -> SurfaceMesh<f64>
+        where F: Fn(Point3<f64>) -> Point3<f64>
+    {
+        let vertices = mesh.vertices().iter()
+            .map(|v| transform(Point3::new(v.x as f64, v.y as f64, v.z as f64)))
+            .map(|v| cgmath::Point3::new(v.x, v.y, v.z))
+            .collect();
+        SurfaceMesh::from_indices(vertices, mesh.triangle_indices().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn mesh_of_box_matches_analytic_cuboid() {
+        let cuboid = Cuboid {
+            center: Point3::new(1.0, -2.0, 3.0),
+            half_size: Vector3::new(0.5, 1.0, 2.0),
+            rotation: UnitQuaternion::from_axis_angle(
+                &Unit::new_normalize(Vector3::new(1.0, 2.0, 3.0)), 0.7)
+        };
+        let mesh = double_precision(&box_mesh(0.5, 1.0, 2.0),
+                                    |v| cuboid.center + cuboid.rotation * v.coords);
+
+        let expected = MassProperties::cuboid(&cuboid, 3.0);
+        let actual = MassProperties::mesh(&mesh, 3.0);
+
+        assert!((expected.mass - actual.mass).abs() < 1e-12 * expected.mass);
+        assert!(norm(&(expected.center_of_mass - actual.center_of_mass)) < 1e-12);
+        let error = (expected.inertia - actual.inertia).iter().fold(0.0f64, |max, x| max.max(x.abs()));
+        assert!(error < 1e-10, "Expected {:?}, got {:?}", expected, actual);
+    }
+
+    #[test]
+    fn mesh_of_sphere_approximates_analytic_sphere() {
+        let sphere = Sphere { radius: 2.0, center: Point3::new(0.5, 0.0, -1.0) };
+        let mesh = double_precision(&unit_sphere(4), |v| sphere.center + sphere.radius * v.coords);
+
+        let expected = MassProperties::sphere(&sphere, 1.0);
+        let actual = MassProperties::mesh(&mesh, 1.0);
+
+        assert!((expected.mass - actual.mass).abs() < 0.01 * expected.mass);
+        assert!(norm(&(expected.center_of_mass - actual.center_of_mass)) < 1e-9);
+        let error = (expected.inertia - actual.inertia).iter().fold(0.0f64, |max, x| max.max(x.abs()));
+        assert!(error < 0.02 * expected.inertia[(0, 0)], "Expected {:?}, got {:?}", expected, actual);
+    }
+}
@@ -5,4 +5,10 @@ mod surface_mesh;
 pub use self::surface_mesh::*;
 
 mod shapes;
-pub use self::shapes::*;
\ No newline at end of file
+pub use self::shapes::*;
+
+mod mass_properties;
+pub use self::mass_properties::MassProperties;
+
+mod convex_hull;
+pub use self::convex_hull::convex_hull;
@@ -120,3 +120,122 @@ pub fn box_mesh(halfx: f32, halfy: f32, halfz: f32) -> SurfaceMesh<f32> {
     SurfaceMesh::from_indices(vertices, indices)
         .expect("The mesh generated should always be valid.")
 }
+
+/// A capsule whose axis is the y axis, with `num_rings` rings of latitude
+/// in each of the two hemispherical caps.
+pub fn capsule_mesh(half_height: f32, radius: f32, num_segments: usize, num_rings: usize)
+    -> SurfaceMesh<f32> {
+    assert!(half_height >= 0.0);
+    assert!(radius > 0.0);
+    assert!(num_rings > 0);
+
+    let quarter_turn = 0.5 * ::std::f32::consts::PI;
+    let latitude = |ring: usize| quarter_turn * ring as f32 / num_rings as f32;
+
+    // From the bottom pole to the equator of the lower cap, and then
+    // from the equator of the upper cap to the top pole
+    let mut profile = Vec::new();
+    for ring in (0 .. num_rings + 1).rev() {
+        let phi = latitude(ring);
+        profile.push((- half_height - radius * phi.sin(), radius * phi.cos()));
+    }
+    for ring in 0 .. num_rings + 1 {
+        let phi = latitude(ring);
+        profile.push((half_height + radius * phi.sin(), radius * phi.cos()));
+    }
+
+    surface_of_revolution(&profile, num_segments)
+}
+
+/// A cylinder whose axis is the y axis.
+pub fn cylinder_mesh(half_height: f32, radius: f32, num_segments: usize) -> SurfaceMesh<f32> {
+    assert!(half_height > 0.0);
+    assert!(radius > 0.0);
+
+    // The rims are repeated, so that the caps do not share vertices with the side
+    let (h, r) = (half_height, radius);
+    surface_of_revolution(&[(-h, 0.0), (-h, r), (-h, r), (h, r), (h, r), (h, 0.0)], num_segments)
+}
+
+/// A cone whose axis is the y axis, with the apex at `half_height`
+/// and the base at `-half_height`.
+pub fn cone_mesh(half_height: f32, radius: f32, num_segments: usize) -> SurfaceMesh<f32> {
+    assert!(half_height > 0.0);
+    assert!(radius > 0.0);
+
+    let (h, r) = (half_height, radius);
+    surface_of_revolution(&[(-h, 0.0), (-h, r), (-h, r), (h, 0.0)], num_segments)
+}
+
+/// A square in the xz plane centered at the origin, facing the positive y direction.
+pub fn plane_mesh(half_size: f32) -> SurfaceMesh<f32> {
+    assert!(half_size > 0.0);
+
+    let s = half_size;
+    let vertices = vec![
+        Point3::new(-s, 0.0, -s),
+        Point3::new(-s, 0.0, s),
+        Point3::new(s, 0.0, s),
+        Point3::new(s, 0.0, -s)
+    ];
+    let indices = vec![
+        TriangleIndices::new(0, 1, 2),
+        TriangleIndices::new(2, 3, 0)
+    ];
+
+    SurfaceMesh::from_indices(vertices, indices)
+        .expect("The mesh generated should always be valid.")
+}
+
+/// Sweeps a profile curve about the y axis. The profile is given by (y, radius)
+/// pairs ordered from the bottom to the top of the surface. Points of zero radius
+/// become a single vertex, and repeated consecutive points split the surface into
+/// parts that do not share vertices.
+fn surface_of_revolution(profile: &[(f32, f32)], num_segments: usize) -> SurfaceMesh<f32> {
+    assert!(num_segments >= 3);
+
+    let mut vertices = Vec::new();
+    let mut ring_offsets = Vec::new();
+    for &(y, radius) in profile {
+        ring_offsets.push(vertices.len());
+        if radius == 0.0 {
+            vertices.push(Point3::new(0.0, y, 0.0));
+        } else {
+            for segment in 0 .. num_segments {
+                let theta = 2.0 * ::std::f32::consts::PI * segment as f32 / num_segments as f32;
+                vertices.push(Point3::new(radius * theta.cos(), y, radius * theta.sin()));
+            }
+        }
+    }
+
+    let vertex = |ring: usize, segment: usize| {
+        if profile[ring].1 == 0.0 {
+            ring_offsets[ring]
+        } else {
+            ring_offsets[ring] + segment % num_segments
+        }
+    };
+
+    let mut indices = Vec::new();
+    for ring in 0 .. profile.len() - 1 {
+        if profile[ring] == profile[ring + 1] {
+            continue;
+        }
+
+        // Each quad between two rings is split into two triangles,
+        // one of which degenerates if either ring is a single point
+        for segment in 0 .. num_segments {
+            let (bottom0, bottom1) = (vertex(ring, segment), vertex(ring, segment + 1));
+            let (top0, top1) = (vertex(ring + 1, segment), vertex(ring + 1, segment + 1));
+            if profile[ring].1 != 0.0 {
+                indices.push(TriangleIndices::new(bottom0, top0, bottom1));
+            }
+            if profile[ring + 1].1 != 0.0 {
+                indices.push(TriangleIndices::new(bottom1, top0, top1));
+            }
+        }
+    }
+
+    SurfaceMesh::from_indices(vertices, indices)
+        .expect("The mesh generated should always be valid.")
+}
@@ -13,3 +13,41 @@ pub struct Cuboid<S> where S: Real + Scalar {
     pub half_size: Vector3<S>,
     pub rotation: UnitQuaternion<S>
 }
+
+/// A cylinder capped by two hemispheres. Its axis is the local y axis,
+/// and the half height refers to the cylindrical part only.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Capsule<S> where S: Real + Scalar {
+    pub center: Point3<S>,
+    pub half_height: S,
+    pub radius: S,
+    pub rotation: UnitQuaternion<S>
+}
+
+/// A circular cylinder, whose axis is the local y axis.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Cylinder<S> where S: Real + Scalar {
+    pub center: Point3<S>,
+    pub half_height: S,
+    pub radius: S,
+    pub rotation: UnitQuaternion<S>
+}
+
+/// A circular cone, whose axis is the local y axis. The apex lies at a distance
+/// `half_height` above the center, and the base the same distance below it.
+/// Note that the center is therefore not the center of mass.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Cone<S> where S: Real + Scalar {
+    pub center: Point3<S>,
+    pub half_height: S,
+    pub radius: S,
+    pub rotation: UnitQuaternion<S>
+}
+
+/// An infinite plane through the given point. Everything behind the plane,
+/// as seen from the direction of the unit normal, is considered solid.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Plane<S> where S: Real + Scalar {
+    pub point: Point3<S>,
+    pub normal: Vector3<S>
+}
@@ -43,6 +43,10 @@ impl InputManager {
             VirtualKeyCode::Down  if released => camera(CameraAction::RotateDownEnd),
             VirtualKeyCode::Key0  if released => Some(Message::ReloadScene { index: 0 }),
             VirtualKeyCode::Key1  if released => Some(Message::ReloadScene { index: 1 }),
+            VirtualKeyCode::Key2  if released => Some(Message::ReloadScene { index: 2 }),
+            VirtualKeyCode::Key3  if released => Some(Message::ReloadScene { index: 3 }),
+            VirtualKeyCode::F5    if released => Some(Message::SaveSnapshot),
+            VirtualKeyCode::F9    if released => Some(Message::RestoreSnapshot),
             _ => None,
         };
 
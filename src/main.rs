@@ -29,6 +29,8 @@ mod message;
 mod camera;
 mod time_keeper;
 mod interop;
+mod snapshot;
+mod replay;
 
 use engine::Engine;
 
@@ -39,16 +41,22 @@ use entity::blueprints;
 use camera::Camera;
 use render::Color;
 use engine::{SceneBlueprint, SceneInitializer};
-use physics::{RigidBody, ForceGenerator};
+use physics::{RigidBody, ForceGenerator, ScopedForceGenerator, ForceScope, Region, GravitationMethod, Falloff,
+    PhysicsMaterial, Joint, JointKind, JointMotor, joint_frame, KinematicMotion, Keyframe,
+    Integrator, SemiImplicitEuler, VelocityVerlet, RungeKutta4, Yoshida4,
+    AngularIntegrator, ExplicitEulerRotation, ImplicitMidpointRotation};
 
 use cgmath::{Point3, Vector3, EuclideanSpace, Zero, Quaternion};
-use geometry::{Sphere, Cuboid};
+use geometry::{Sphere, Cuboid, Capsule, Cylinder, Cone, Plane};
+use std::rc::Rc;
 
 impl SceneInitializer for Initializer {
     fn create_scene(&self, index: usize) -> Option<SceneBlueprint> {
         match index {
             0 => Some(self.create_scene0()),
             1 => Some(self.create_scene1()),
+            2 => Some(self.create_scene2()),
+            3 => Some(self.create_scene3()),
             _ => None
         }
     }
@@ -72,11 +80,78 @@ struct CuboidObject {
     color: Color,
 }
 
+/// Supported arguments:
+///
+/// --lockstep <steps>              Take a fixed number of physics steps per frame
+/// --record <path>                 Record the input of the run to a replay file
+/// --replay <path>                 Play back the input of a replay file
+/// --integrator <name>             semi_implicit_euler, velocity_verlet, runge_kutta4 or yoshida4
+/// --angular-integrator <name>     explicit_euler or implicit_midpoint
 fn main() {
     let mut engine = Engine::new(Initializer);
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next();
+        match (arg.as_str(), value) {
+            ("--lockstep", Some(steps)) => match steps.parse() {
+                Ok(steps) if steps > 0 => engine.set_lockstep(steps),
+                _ => {
+                    eprintln!("Invalid number of steps per frame '{}'.", steps);
+                    return;
+                }
+            },
+            ("--integrator", Some(name)) => match integrator_named(&name) {
+                Some(integrator) => engine.set_integrator(integrator),
+                None => {
+                    eprintln!("Unknown integrator '{}'.", name);
+                    return;
+                }
+            },
+            ("--angular-integrator", Some(name)) => match angular_integrator_named(&name) {
+                Some(integrator) => engine.set_angular_integrator(integrator),
+                None => {
+                    eprintln!("Unknown angular integrator '{}'.", name);
+                    return;
+                }
+            },
+            ("--record", Some(path)) => engine.record_replay(path),
+            ("--replay", Some(path)) => {
+                if let Err(error) = engine.play_replay(&path) {
+                    eprintln!("Failed to load replay from {}: {}", path, error);
+                    return;
+                }
+            },
+            _ => {
+                eprintln!("Unrecognized argument '{}'.", arg);
+                return;
+            }
+        }
+    }
+
     engine.run();
 }
 
+fn integrator_named(name: &str) -> Option<Box<Integrator>> {
+    let integrator: Box<Integrator> = match name {
+        "semi_implicit_euler" => Box::new(SemiImplicitEuler),
+        "velocity_verlet" => Box::new(VelocityVerlet::new()),
+        "runge_kutta4" => Box::new(RungeKutta4::new()),
+        "yoshida4" => Box::new(Yoshida4::new()),
+        _ => return None
+    };
+    Some(integrator)
+}
+
+fn angular_integrator_named(name: &str) -> Option<Box<AngularIntegrator>> {
+    let integrator: Box<AngularIntegrator> = match name {
+        "explicit_euler" => Box::new(ExplicitEulerRotation),
+        "implicit_midpoint" => Box::new(ImplicitMidpointRotation::new()),
+        _ => return None
+    };
+    Some(integrator)
+}
+
 impl Default for SphereObject {
     fn default() -> Self {
         let gray = Color::rgb(0.5, 0.5, 0.5);
@@ -216,9 +291,11 @@ impl Initializer {
         let graybrown = Color::rgb(205.0 / 255.0, 133.0 / 255.0 ,63.0/255.0);
 
         let blueprints = vec![
+            // The heavy central body keeps the smaller spheres in
+            // roughly circular orbits
             SphereObject::default()
                          .radius(5.0)
-                         .mass(1e11)
+                         .mass(2e12)
                          .color(blue)
                          .subdivisions(4)
                          .create_blueprint(),
@@ -262,7 +339,16 @@ impl Initializer {
                          .orientation(Quaternion::new(1.0, 0.0, 0.0, 0.0))
                          .mass(0.2)
                          .color(green)
-                         .create_blueprint()
+                         .create_blueprint(),
+
+            EntityBlueprint {
+                force: Some(ForceGenerator::MutualGravitation {
+                    g: 6.674e-11,
+                    softening: 0.0,
+                    method: GravitationMethod::Exact
+                }.global()),
+                .. EntityBlueprint::empty()
+            }
         ];
 
         SceneBlueprint {
@@ -278,30 +364,328 @@ impl Initializer {
         let red = Color::rgb(1.0, 0.0, 0.0);
         let graybrown = Color::rgb(205.0 / 255.0, 133.0 / 255.0 ,63.0/255.0);
 
+        // Partially inelastic collisions, so that the box eventually comes to rest
+        let stone = PhysicsMaterial::new(0.5, 0.8, 0.6);
+        let wood = PhysicsMaterial::new(0.3, 0.5, 0.4);
+
+        let mut ground = blueprints::ground_plane(Plane {
+            point: nalgebra::Point3::origin(),
+            normal: nalgebra::Vector3::new(0.0, 0.0, 1.0)
+        }).with_material(stone);
+        ground.renderable.as_mut().unwrap().color = red;
+
+        // Lying on their sides, with their axes along the x axis
+        let on_side = nalgebra::UnitQuaternion::from_axis_angle(
+            &nalgebra::Unit::new_normalize(nalgebra::Vector3::new(0.0, 0.0, 1.0)),
+            0.5 * ::std::f64::consts::PI);
+
         let blueprints = vec![
-            CuboidObject::default()
-                         .center(Point3::new(0.0, 0.0, -5.0))
-                         .half_size(Vector3::new(5.0, 5.0, 5.0))
-                         .mass(1e10)
-                         .color(red)
-                         .create_blueprint()
-                         .make_static(),
+            ground,
 
             CuboidObject::default()
                          .center(Point3::new(2.5, 0.0, 6.0))
                          .velocity(Vector3::new(0.0, 0.0, -1.0))
                          .mass(1.0)
                          .color(graybrown)
-                         .create_blueprint(),
+                         .create_blueprint()
+                         .with_material(wood),
+
+            blueprints::table(nalgebra::Point3::new(-2.0, 0.0, 0.0),
+                              nalgebra::Vector3::new(1.0, 0.6, 0.05),
+                              0.8, 0.1, 5.0)
+                       .with_material(wood),
+
+            blueprints::dumbbell(nalgebra::Point3::new(0.0, 2.0, 3.0), 0.3, 1.2, 2.0)
+                       .with_material(stone),
+
+            blueprints::capsule(Capsule {
+                center: nalgebra::Point3::new(0.0, -2.0, 2.0),
+                half_height: 0.5,
+                radius: 0.3,
+                rotation: on_side
+            }, 1.0).with_material(wood),
+
+            blueprints::cylinder(Cylinder {
+                center: nalgebra::Point3::new(2.0, 2.5, 1.0),
+                half_height: 0.4,
+                radius: 0.4,
+                rotation: nalgebra::UnitQuaternion::identity()
+            }, 1.0).with_material(wood),
+
+            blueprints::cone(Cone {
+                center: nalgebra::Point3::new(-2.0, -2.5, 1.0),
+                half_height: 0.5,
+                radius: 0.5,
+                rotation: nalgebra::UnitQuaternion::identity()
+            }, 1.0).with_material(wood),
+
+            EntityBlueprint {
+                force: Some(ForceGenerator::UniformAccelerationField {
+                    acceleration: nalgebra::Vector3::new(0.0, 0.0, -9.81)
+                }.global()),
+                .. EntityBlueprint::empty()
+            }
+        ];
+
+        SceneBlueprint {
+            blueprints: blueprints,
+            camera: camera
+        }
+    }
+
+    fn create_scene2(&self) -> SceneBlueprint {
+        let camera = Camera::look_in(Point3::new(12.0, 0.0, 4.0), -Vector3::unit_x(), Vector3::unit_z())
+                            .unwrap();
+
+        let red = Color::rgb(1.0, 0.0, 0.0);
+        let blue = Color::rgb(0.0, 0.0, 1.0);
+        let graybrown = Color::rgb(205.0 / 255.0, 133.0 / 255.0 ,63.0/255.0);
+        let stone = PhysicsMaterial::new(0.5, 0.8, 0.6);
+        let x_axis = nalgebra::Vector3::new(1.0, 0.0, 0.0);
 
+        let mut ground = blueprints::ground_plane(Plane {
+            point: nalgebra::Point3::origin(),
+            normal: nalgebra::Vector3::new(0.0, 0.0, 1.0)
+        }).with_material(stone);
+        ground.renderable.as_mut().unwrap().color = red;
+
+        let mut blueprints = vec![
+            ground,
             EntityBlueprint {
                 force: Some(ForceGenerator::UniformAccelerationField {
                     acceleration: nalgebra::Vector3::new(0.0, 0.0, -9.81)
-                }),
+                }.global()),
                 .. EntityBlueprint::empty()
             }
         ];
 
+        // Joints refer to the bodies they connect by the index of their blueprints
+        fn add(blueprints: &mut Vec<EntityBlueprint>, blueprint: EntityBlueprint) -> usize {
+            blueprints.push(blueprint);
+            blueprints.len() - 1
+        }
+        fn join(blueprints: &mut Vec<EntityBlueprint>, joint: Joint<usize>) {
+            blueprints.push(EntityBlueprint { joint: Some(joint), .. EntityBlueprint::empty() });
+        }
+        let anchor = |position: nalgebra::Point3<f64>| {
+            blueprints::sphere(Sphere { center: position, radius: 0.1 }, 1.0, 2).make_static()
+        };
+
+        // A chain of spheres, released from a horizontal position
+        let mut previous = add(&mut blueprints, anchor(nalgebra::Point3::new(0.0, -3.0, 6.0)));
+        for i in 1 .. 9 {
+            let center = nalgebra::Point3::new(0.0, -3.0 + 0.5 * i as f64, 6.0);
+            let mut link = blueprints::sphere(Sphere { center: center, radius: 0.2 }, 0.5, 2);
+            link.renderable.as_mut().unwrap().color = graybrown;
+            let link = add(&mut blueprints, link);
+
+            join(&mut blueprints, Joint::new(
+                previous, joint_frame(nalgebra::Point3::new(0.0, 0.25, 0.0), &x_axis),
+                link, joint_frame(nalgebra::Point3::new(0.0, -0.25, 0.0), &x_axis),
+                JointKind::BallSocket));
+            previous = link;
+        }
+
+        // A paddle wheel, driven by a motor about the x axis
+        let hub = add(&mut blueprints, anchor(nalgebra::Point3::new(0.0, 3.0, 2.5)));
+        let mut paddle = blueprints::cuboid(Cuboid {
+            center: nalgebra::Point3::new(0.0, 3.0, 2.5),
+            half_size: nalgebra::Vector3::new(0.1, 2.0, 0.2),
+            rotation: nalgebra::UnitQuaternion::identity()
+        }, 5.0);
+        paddle.renderable.as_mut().unwrap().color = blue;
+        let paddle = add(&mut blueprints, paddle);
+        join(&mut blueprints, Joint::new(
+            hub, joint_frame(nalgebra::Point3::origin(), &x_axis),
+            paddle, joint_frame(nalgebra::Point3::origin(), &x_axis),
+            JointKind::Hinge {
+                limits: None,
+                motor: Some(JointMotor { target_velocity: 1.0, max_torque: 100.0 })
+            }));
+
+        // A weight on a rope, which falls until the rope is taut
+        let hook = add(&mut blueprints, anchor(nalgebra::Point3::new(-2.0, 0.0, 6.0)));
+        let weight = add(&mut blueprints,
+            blueprints::sphere(Sphere { center: nalgebra::Point3::new(-2.0, 1.0, 5.5), radius: 0.3 }, 2.0, 3));
+        join(&mut blueprints, Joint::new(
+            hook, joint_frame(nalgebra::Point3::origin(), &x_axis),
+            weight, joint_frame(nalgebra::Point3::origin(), &x_axis),
+            JointKind::Distance { min: 0.0, max: 3.0 }));
+
+        // A carriage which slides down an inclined rail until it reaches the end
+        let rail_axis = nalgebra::Vector3::new(0.0, 1.0, -0.5);
+        let rail = add(&mut blueprints, anchor(nalgebra::Point3::new(2.0, -2.0, 3.0)));
+        let mut carriage = blueprints::cuboid(Cuboid {
+            center: nalgebra::Point3::new(2.0, -2.0, 3.0),
+            half_size: nalgebra::Vector3::new(0.3, 0.3, 0.2),
+            rotation: nalgebra::UnitQuaternion::identity()
+        }, 1.0);
+        carriage.renderable.as_mut().unwrap().color = graybrown;
+        let carriage = add(&mut blueprints, carriage);
+        join(&mut blueprints, Joint::new(
+            rail, joint_frame(nalgebra::Point3::origin(), &rail_axis),
+            carriage, joint_frame(nalgebra::Point3::origin(), &rail_axis),
+            JointKind::Slider { limits: Some((0.0, 3.0)) }));
+
+        // A platform which carries a box back and forth
+        let wood = PhysicsMaterial::new(0.3, 0.5, 0.4);
+        let platform_pose = |x: f64, y: f64, z: f64| nalgebra::Isometry3::from_parts(
+            nalgebra::Translation3::from_vector(nalgebra::Vector3::new(x, y, z)), nalgebra::UnitQuaternion::identity());
+        let keyframes = vec![
+            Keyframe { time: 0.0, pose: platform_pose(4.0, -2.0, 0.5) },
+            Keyframe { time: 3.0, pose: platform_pose(4.0, 2.0, 2.0) },
+            Keyframe { time: 6.0, pose: platform_pose(4.0, -2.0, 0.5) }
+        ];
+        let mut platform = blueprints::cuboid(Cuboid {
+            center: nalgebra::Point3::new(4.0, -2.0, 0.5),
+            half_size: nalgebra::Vector3::new(0.8, 0.8, 0.1),
+            rotation: nalgebra::UnitQuaternion::identity()
+        }, 1.0).make_kinematic(KinematicMotion::Keyframes { keyframes: keyframes, looping: true })
+               .with_material(wood);
+        platform.renderable.as_mut().unwrap().color = blue;
+        blueprints.push(platform);
+        blueprints.push(blueprints::cuboid(Cuboid {
+            center: nalgebra::Point3::new(4.0, -2.0, 0.85),
+            half_size: nalgebra::Vector3::new(0.25, 0.25, 0.25),
+            rotation: nalgebra::UnitQuaternion::identity()
+        }, 1.0).with_material(wood));
+
+        // A sweeper arm turning about the vertical axis, which pushes the balls around
+        let sweep = |t: f64| nalgebra::Isometry3::from_parts(
+            nalgebra::Translation3::from_vector(nalgebra::Vector3::new(-4.0, 0.0, 0.3)),
+            nalgebra::UnitQuaternion::from_axis_angle(
+                &nalgebra::Unit::new_normalize(nalgebra::Vector3::new(0.0, 0.0, 1.0)), 0.8 * t));
+        let mut sweeper = blueprints::cuboid(Cuboid {
+            center: nalgebra::Point3::new(-4.0, 0.0, 0.3),
+            half_size: nalgebra::Vector3::new(2.0, 0.1, 0.3),
+            rotation: nalgebra::UnitQuaternion::identity()
+        }, 1.0).make_kinematic(KinematicMotion::Scripted(Rc::new(sweep)));
+        sweeper.renderable.as_mut().unwrap().color = blue;
+        blueprints.push(sweeper);
+        for &(x, y) in &[(-4.5, 1.0), (-3.0, -1.5), (-5.5, -0.5)] {
+            let ball = blueprints::sphere(Sphere { center: nalgebra::Point3::new(x, y, 0.3), radius: 0.3 }, 1.0, 3);
+            blueprints.push(ball.with_material(stone));
+        }
+
+        SceneBlueprint {
+            blueprints: blueprints,
+            camera: camera
+        }
+    }
+
+    fn create_scene3(&self) -> SceneBlueprint {
+        let camera = Camera::look_in(Point3::new(14.0, 0.0, 4.0), -Vector3::unit_x(), Vector3::unit_z())
+                            .unwrap();
+
+        let red = Color::rgb(1.0, 0.0, 0.0);
+        let blue = Color::rgb(0.0, 0.0, 1.0);
+        let green = Color::rgb(0.0, 1.0, 0.0);
+        let graybrown = Color::rgb(205.0 / 255.0, 133.0 / 255.0 ,63.0/255.0);
+        let gravity = nalgebra::Vector3::new(0.0, 0.0, -9.81);
+
+        let mut ground = blueprints::ground_plane(Plane {
+            point: nalgebra::Point3::origin(),
+            normal: nalgebra::Vector3::new(0.0, 0.0, 1.0)
+        }).with_material(PhysicsMaterial::new(0.5, 0.8, 0.6));
+        ground.renderable.as_mut().unwrap().color = red;
+
+        let force = |generator: ScopedForceGenerator<usize>| EntityBlueprint {
+            force: Some(generator),
+            .. EntityBlueprint::empty()
+        };
+        let mut blueprints = vec![
+            ground,
+            force(ForceGenerator::UniformAccelerationField { acceleration: gravity }.global()),
+            force(ForceGenerator::Drag { linear: 0.0, quadratic: 0.01 }.global()),
+
+            // A breeze along the y axis, which picks up with height
+            force(ForceGenerator::Wind {
+                velocity: Rc::new(|x: nalgebra::Point3<f64>| nalgebra::Vector3::new(0.0, 0.5 * x.z.max(0.0), 0.0)),
+                coefficient: 0.02
+            }.global())
+        ];
+
+        // A box hanging from a spring attached to one of its corners,
+        // so that it twists as it bounces
+        let hook = blueprints.len();
+        blueprints.push(blueprints::sphere(Sphere { center: nalgebra::Point3::new(0.0, -4.0, 7.0), radius: 0.1 }, 1.0, 2)
+                            .make_static());
+        let weight = blueprints.len();
+        let mut box_weight = blueprints::cuboid(Cuboid {
+            center: nalgebra::Point3::new(0.0, -4.0, 5.0),
+            half_size: nalgebra::Vector3::new(0.4, 0.4, 0.4),
+            rotation: nalgebra::UnitQuaternion::identity()
+        }, 2.0);
+        box_weight.renderable.as_mut().unwrap().color = graybrown;
+        blueprints.push(box_weight);
+        blueprints.push(force(ForceGenerator::Spring {
+            body1: hook,
+            anchor1: nalgebra::Point3::origin(),
+            body2: weight,
+            anchor2: nalgebra::Point3::new(0.4, 0.4, 0.4),
+            rest_length: 1.5,
+            stiffness: 40.0,
+            damping: 0.5
+        }.global()));
+
+        // Balls of different densities in a pool whose surface is at z = 1.5,
+        // released from above. Only the densest one sinks to the bottom.
+        let surface = Plane {
+            point: nalgebra::Point3::new(0.0, 0.0, 1.5),
+            normal: nalgebra::Vector3::new(0.0, 0.0, 1.0)
+        };
+        let water_density = 1000.0;
+        let radius = 0.4;
+        let volume = 4.0 / 3.0 * std::f64::consts::PI * radius * radius * radius;
+        let mut balls = Vec::new();
+        for (i, &relative_density) in [0.25, 0.75, 1.5].iter().enumerate() {
+            let center = nalgebra::Point3::new(0.0, -1.0 + 1.2 * i as f64, 3.0);
+            let mut ball = blueprints::sphere(Sphere { center: center, radius: radius },
+                                              relative_density * water_density * volume, 3);
+            ball.renderable.as_mut().unwrap().color = blue;
+            balls.push(blueprints.len());
+            blueprints.push(ball);
+        }
+        blueprints.push(force(ForceGenerator::Buoyancy {
+            center_of_buoyancy: nalgebra::Point3::origin(),
+            surface: surface,
+            fluid_density: water_density,
+            gravity: gravity
+        }.scoped(ForceScope::Bodies(balls))));
+
+        // Small spheres flung around a point attractor, which holds them up
+        // against gravity unless they stray out of the sphere it acts within
+        let attractor_center = nalgebra::Point3::new(0.0, 4.0, 5.0);
+        let attractor_region = Region::Sphere(Sphere { center: attractor_center, radius: 4.0 });
+        blueprints.push(force(ForceGenerator::PointAttractor {
+            center: attractor_center,
+            strength: 15.0,
+            falloff: Falloff::Constant
+        }.scoped(ForceScope::Region(attractor_region))));
+        for &(y, z, vx) in &[(4.0, 6.5, 2.0), (5.0, 5.0, -2.5), (3.0, 4.0, 1.5)] {
+            blueprints.push(SphereObject::default()
+                                .center(Point3::new(0.0, y, z))
+                                .velocity(Vector3::new(vx, 0.0, 0.0))
+                                .radius(0.2)
+                                .mass(0.5)
+                                .color(green)
+                                .create_blueprint());
+        }
+
+        // A rocket whose engine is mounted off-center,
+        // so that it lifts off on a curved path
+        let mut rocket = blueprints::cuboid(Cuboid {
+            center: nalgebra::Point3::new(-3.0, 0.0, 0.6),
+            half_size: nalgebra::Vector3::new(0.2, 0.2, 0.6),
+            rotation: nalgebra::UnitQuaternion::identity()
+        }, 1.0);
+        rocket.renderable.as_mut().unwrap().color = graybrown;
+        rocket.force = Some(ForceGenerator::Thrust {
+            force: nalgebra::Vector3::new(0.0, 0.0, 15.0),
+            point: nalgebra::Point3::new(0.0, 0.02, -0.6)
+        }.scoped(ForceScope::OwnEntity));
+        blueprints.push(rocket);
+
         SceneBlueprint {
             blueprints: blueprints,
             camera: camera
@@ -1,12 +1,38 @@
 use glium::glutin::{ElementState, VirtualKeyCode};
 use camera::CameraAction;
+use entity::Entity;
+use nalgebra::{Point3, Vector3};
 
 #[derive(Clone, Debug)]
 pub enum Message {
     WindowClosed,
     KeyboardInputReceived(ElementState, VirtualKeyCode),
     CameraCommand(CameraAction),
-    ReloadScene { index: usize }
+    ReloadScene { index: usize },
+
+    /// Save the state of the running scene to disk, or restore it from there.
+    SaveSnapshot,
+    RestoreSnapshot,
+
+    /// Two entities came into contact. The point and the normal are those of the
+    /// deepest contact, with the normal pointing from `a` towards `b`, and `impulse`
+    /// is the total normal impulse applied to keep the two entities apart.
+    CollisionStarted {
+        a: Entity,
+        b: Entity,
+        point: Point3<f64>,
+        normal: Vector3<f64>,
+        impulse: f64
+    },
+
+    /// Two entities which were previously in contact are no longer in contact.
+    CollisionEnded { a: Entity, b: Entity },
+
+    /// The collision model of `entity` started intersecting the sensor of `sensor`.
+    TriggerEntered { sensor: Entity, entity: Entity },
+
+    /// The collision model of `entity` no longer intersects the sensor of `sensor`.
+    TriggerExited { sensor: Entity, entity: Entity }
 }
 
 pub trait MessageReceiver {
@@ -0,0 +1,300 @@
+use nalgebra::{zero, norm_squared, Point3, Vector3};
+use std::f64;
+use std::usize;
+
+const NONE: usize = usize::MAX;
+
+// Beyond this depth, bodies are no longer separated into distinct cells.
+// This guards against unbounded subdivision when bodies (nearly) coincide.
+const MAX_DEPTH: usize = 32;
+
+struct OctreeNode {
+    center: Point3<f64>,
+    half_size: f64,
+    mass: f64,
+    // Mass-weighted sum of positions, which becomes the center of mass
+    // once divided by the total mass
+    weighted_position: Vector3<f64>,
+    children: [usize; 8],
+    // For leaves, the first body in the linked list of bodies contained in the cell
+    first_body: usize,
+    is_leaf: bool
+}
+
+impl OctreeNode {
+    fn new(center: Point3<f64>, half_size: f64) -> OctreeNode {
+        OctreeNode {
+            center: center,
+            half_size: half_size,
+            mass: 0.0,
+            weighted_position: Vector3::new(0.0, 0.0, 0.0),
+            children: [NONE; 8],
+            first_body: NONE,
+            is_leaf: true
+        }
+    }
+
+    fn center_of_mass(&self) -> Point3<f64> {
+        Point3::from_coordinates(self.weighted_position / self.mass)
+    }
+}
+
+/// An octree over a set of point masses, used to approximate the gravitational
+/// acceleration of every body by means of the Barnes–Hut algorithm.
+///
+/// The tree retains its allocations between rebuilds, so that it may be
+/// reused from step to step.
+pub struct Octree {
+    nodes: Vec<OctreeNode>,
+    // Links bodies residing in the same leaf
+    next_body: Vec<usize>
+}
+
+impl Octree {
+    pub fn new() -> Octree {
+        Octree {
+            nodes: Vec::new(),
+            next_body: Vec::new()
+        }
+    }
+
+    /// Rebuilds the tree so that it contains the given bodies.
+    pub fn rebuild(&mut self, x: &[Point3<f64>], m: &[f64]) {
+        assert!(x.len() == m.len());
+        self.nodes.clear();
+        self.next_body.clear();
+        self.next_body.resize(x.len(), NONE);
+
+        if x.is_empty() {
+            return;
+        }
+
+        // Determine a bounding cube for all bodies
+        let mut min = x[0];
+        let mut max = x[0];
+        for p in x {
+            for k in 0 .. 3 {
+                min[k] = min[k].min(p[k]);
+                max[k] = max[k].max(p[k]);
+            }
+        }
+        let center = Point3::from_coordinates(0.5 * (min.coords + max.coords));
+        let half_size = (0 .. 3).map(|k| 0.5 * (max[k] - min[k]))
+                                 .fold(0.0, f64::max);
+
+        // Make the root cell slightly larger than strictly necessary, so that
+        // bodies on the boundary are unambiguously contained
+        let half_size = if half_size > 0.0 { 1.01 * half_size } else { 1.0 };
+        self.nodes.push(OctreeNode::new(center, half_size));
+
+        for i in 0 .. x.len() {
+            self.insert(0, i, 0, x, m);
+        }
+    }
+
+    fn insert(&mut self, node: usize, body: usize, depth: usize, x: &[Point3<f64>], m: &[f64]) {
+        self.nodes[node].mass += m[body];
+        self.nodes[node].weighted_position += m[body] * x[body].coords;
+
+        if self.nodes[node].is_leaf {
+            let first_body = self.nodes[node].first_body;
+            if first_body == NONE {
+                self.nodes[node].first_body = body;
+                return;
+            } else if depth >= MAX_DEPTH {
+                self.next_body[body] = first_body;
+                self.nodes[node].first_body = body;
+                return;
+            } else {
+                // Split the leaf, moving its (single) body into the appropriate child
+                self.nodes[node].first_body = NONE;
+                self.nodes[node].is_leaf = false;
+                let child = self.child_containing(node, &x[first_body]);
+                self.insert(child, first_body, depth + 1, x, m);
+            }
+        }
+
+        let child = self.child_containing(node, &x[body]);
+        self.insert(child, body, depth + 1, x, m);
+    }
+
+    fn child_containing(&mut self, node: usize, p: &Point3<f64>) -> usize {
+        let center = self.nodes[node].center;
+        let mut octant = 0;
+        if p.x >= center.x { octant |= 1; }
+        if p.y >= center.y { octant |= 2; }
+        if p.z >= center.z { octant |= 4; }
+
+        if self.nodes[node].children[octant] == NONE {
+            let quarter_size = 0.5 * self.nodes[node].half_size;
+            let sign = |bit| if octant & bit != 0 { quarter_size } else { -quarter_size };
+            let child_center = center + Vector3::new(sign(1), sign(2), sign(4));
+            self.nodes.push(OctreeNode::new(child_center, quarter_size));
+            let index = self.nodes.len() - 1;
+            self.nodes[node].children[octant] = index;
+        }
+
+        self.nodes[node].children[octant]
+    }
+
+    /// Accumulates the approximate gravitational acceleration of every body into `a`.
+    /// The bodies must be the same as those the tree was last built from.
+    pub fn accumulate_gravitation(&self,
+                                  g: f64,
+                                  softening: f64,
+                                  theta: f64,
+                                  x: &[Point3<f64>],
+                                  m: &[f64],
+                                  a: &mut [Vector3<f64>])
+    {
+        assert!(x.len() == m.len() && m.len() == a.len());
+        assert!(x.len() == self.next_body.len(),
+            "The octree must be rebuilt from the same bodies.");
+        assert!(theta >= 0.0);
+
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let eps2 = softening * softening;
+        let theta2 = theta * theta;
+        // Like in the exact summation, coincident bodies without softening are left out
+        let attraction = |from: &Point3<f64>, to: &Point3<f64>, mass: f64| {
+            let r = to - from;
+            let r2 = norm_squared(&r) + eps2;
+            if r2 == 0.0 {
+                return zero();
+            }
+            (g * mass / (r2 * r2.sqrt())) * r
+        };
+
+        let mut stack = Vec::new();
+        for i in 0 .. x.len() {
+            stack.clear();
+            stack.push(0);
+
+            while let Some(index) = stack.pop() {
+                let node = &self.nodes[index];
+                if node.mass == 0.0 {
+                    continue;
+                }
+
+                if node.is_leaf {
+                    let mut body = node.first_body;
+                    while body != NONE {
+                        if body != i {
+                            a[i] += attraction(&x[i], &x[body], m[body]);
+                        }
+                        body = self.next_body[body];
+                    }
+                } else {
+                    // The cell is sufficiently far away if its size s and distance d
+                    // satisfy s / d < theta, or equivalently, s² < theta² d².
+                    let center_of_mass = node.center_of_mass();
+                    let size = 2.0 * node.half_size;
+                    let d2 = norm_squared(&(center_of_mass - x[i]));
+                    if size * size < theta2 * d2 {
+                        a[i] += attraction(&x[i], &center_of_mass, node.mass);
+                    } else {
+                        stack.extend(node.children.iter().cloned().filter(|&c| c != NONE));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Octree;
+    use physics::gravity::accumulate_pairwise_gravitation;
+    use nalgebra::{zero, norm, norm_squared, Point3, Vector3};
+
+    // A simple linear congruential generator, so that the test is deterministic
+    // without having to depend on an external crate for random numbers
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> f64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.0 >> 11) as f64 / (1u64 << 53) as f64
+        }
+    }
+
+    fn random_bodies(n: usize) -> (Vec<Point3<f64>>, Vec<f64>) {
+        let mut rng = Lcg(42);
+        let mut x = Vec::new();
+        let mut m = Vec::new();
+        for _ in 0 .. n {
+            x.push(Point3::new(100.0 * rng.next() - 50.0,
+                               100.0 * rng.next() - 50.0,
+                               20.0 * rng.next() - 10.0));
+            m.push(1.0 + 9.0 * rng.next());
+        }
+        (x, m)
+    }
+
+    fn accelerations(x: &[Point3<f64>], m: &[f64], theta: Option<f64>) -> Vec<Vector3<f64>> {
+        let mut a = vec![zero::<Vector3<f64>>(); x.len()];
+        match theta {
+            Some(theta) => {
+                let mut octree = Octree::new();
+                octree.rebuild(x, m);
+                octree.accumulate_gravitation(1.0, 0.1, theta, x, m, &mut a);
+            },
+            None => accumulate_pairwise_gravitation(1.0, 0.1, x, m, &mut a)
+        }
+        a
+    }
+
+    #[test]
+    fn barnes_hut_with_zero_opening_angle_is_exact() {
+        let (x, m) = random_bodies(200);
+        let exact = accelerations(&x, &m, None);
+        let approximate = accelerations(&x, &m, Some(0.0));
+
+        for (a_exact, a_approx) in exact.iter().zip(approximate.iter()) {
+            assert!(norm(&(a_exact - a_approx)) <= 1e-10 * norm(a_exact));
+        }
+    }
+
+    #[test]
+    fn barnes_hut_approximates_pairwise_sum() {
+        let (x, m) = random_bodies(2000);
+        let exact = accelerations(&x, &m, None);
+        let approximate = accelerations(&x, &m, Some(0.5));
+
+        // Bodies whose net acceleration nearly cancels out may have large relative
+        // errors, so we measure the worst error relative to the RMS acceleration,
+        // in addition to the mean relative error.
+        let n = exact.len() as f64;
+        let rms = (exact.iter().map(|a| norm_squared(a)).sum::<f64>() / n).sqrt();
+        let mut mean_relative_error = 0.0;
+        let mut max_scaled_error: f64 = 0.0;
+        for (a_exact, a_approx) in exact.iter().zip(approximate.iter()) {
+            let error = norm(&(a_exact - a_approx));
+            mean_relative_error += error / norm(a_exact) / n;
+            max_scaled_error = max_scaled_error.max(error / rms);
+        }
+
+        assert!(mean_relative_error < 2e-2, "Mean relative error was {}", mean_relative_error);
+        assert!(max_scaled_error < 5e-2, "Max scaled error was {}", max_scaled_error);
+    }
+
+    #[test]
+    fn barnes_hut_handles_coincident_bodies() {
+        let x = vec![Point3::new(1.0, 2.0, 3.0); 4];
+        let m = vec![1.0; 4];
+        let a = accelerations(&x, &m, Some(0.5));
+
+        for a_i in a {
+            assert!(norm(&a_i) < 1e-12);
+        }
+    }
+
+    #[test]
+    fn barnes_hut_on_no_bodies() {
+        let a = accelerations(&[], &[], Some(0.5));
+        assert!(a.is_empty());
+    }
+}
@@ -1,17 +1,149 @@
 use entity::Entity;
 use std::collections::HashMap;
-use geometry::{Sphere, Cuboid};
+use geometry::{Sphere, Cuboid, Capsule, Cylinder, Cone, Plane, SurfaceMesh, MassProperties, convex_hull};
+use nalgebra::Isometry3;
 
 pub type CollisionComponentId = usize;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub enum CollisionModel {
     Sphere(Sphere<f64>),
-    Cuboid(Cuboid<f64>)
+    Cuboid(Cuboid<f64>),
+    Capsule(Capsule<f64>),
+    Cylinder(Cylinder<f64>),
+    Cone(Cone<f64>),
+
+    /// An infinite plane, which may only belong to static bodies.
+    Plane(Plane<f64>),
+
+    /// A convex polyhedron, given by a closed mesh whose vertices all lie on its hull.
+    /// Use `CollisionModel::convex_hull` to construct it from arbitrary points.
+    ConvexHull(SurfaceMesh<f64>),
+
+    /// A triangle mesh, which need not be closed or convex. Since it has
+    /// no well-defined interior, it may only belong to static bodies.
+    TriMesh(SurfaceMesh<f64>),
+
+    /// A rigid composition of several collision models, each of which is
+    /// placed relative to the origin of the compound by the given isometry.
+    Compound(Vec<(Isometry3<f64>, CollisionModel)>)
+}
+
+impl CollisionModel {
+    /// Builds a convex hull model from the vertices of the given mesh.
+    /// Returns `None` if the vertices are all coplanar.
+    pub fn convex_hull(mesh: &SurfaceMesh<f64>) -> Option<CollisionModel> {
+        convex_hull(mesh.vertices()).map(CollisionModel::ConvexHull)
+    }
+
+    /// Whether the model can only be attached to a static body.
+    pub fn requires_static_body(&self) -> bool {
+        match *self {
+            CollisionModel::Plane(_) | CollisionModel::TriMesh(_) => true,
+            CollisionModel::Compound(ref children) =>
+                children.iter().any(|&(_, ref child)| child.requires_static_body()),
+            _ => false
+        }
+    }
+
+    /// The volume enclosed by the model.
+    ///
+    /// Panics if the model contains a plane or a triangle mesh, like `mass_properties`.
+    pub fn volume(&self) -> f64 {
+        self.mass_properties(1.0).mass
+    }
+
+    /// Computes the mass properties of the model for the given uniform density,
+    /// expressed in the coordinate system of the model.
+    ///
+    /// Panics if the model contains a plane or a triangle mesh,
+    /// which do not enclose a finite volume.
+    pub fn mass_properties(&self, density: f64) -> MassProperties {
+        match *self {
+            CollisionModel::Sphere(ref sphere) => MassProperties::sphere(sphere, density),
+            CollisionModel::Cuboid(ref cuboid) => MassProperties::cuboid(cuboid, density),
+            CollisionModel::Capsule(ref capsule) => MassProperties::capsule(capsule, density),
+            CollisionModel::Cylinder(ref cylinder) => MassProperties::cylinder(cylinder, density),
+            CollisionModel::Cone(ref cone) => MassProperties::cone(cone, density),
+            CollisionModel::Plane(_) => panic!("A plane has infinite mass."),
+            CollisionModel::ConvexHull(ref mesh) => MassProperties::mesh(mesh, density),
+            CollisionModel::TriMesh(_) => panic!("A triangle mesh does not enclose a volume."),
+            CollisionModel::Compound(ref children) => {
+                let parts: Vec<MassProperties> = children.iter()
+                    .map(|&(ref isometry, ref child)| child.mass_properties(density).transformed(isometry))
+                    .collect();
+                MassProperties::combine(&parts)
+            }
+        }
+    }
+}
+
+/// The number of available collision groups.
+pub const NUM_COLLISION_GROUPS: usize = 30;
+
+/// Determines which other collision models a collision model may interact with,
+/// based on bitmasks of collision groups. Two models interact only if each is
+/// a member of a group in the other's whitelist, and neither is a member of
+/// a group in the other's blacklist.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CollisionFilter {
+    pub membership: u32,
+    pub whitelist: u32,
+    pub blacklist: u32
+}
+
+impl Default for CollisionFilter {
+    /// Member of all groups, and interacts with all groups.
+    fn default() -> Self {
+        let all_groups = (1 << NUM_COLLISION_GROUPS) - 1;
+        CollisionFilter {
+            membership: all_groups,
+            whitelist: all_groups,
+            blacklist: 0
+        }
+    }
+}
+
+fn group_mask(groups: &[usize]) -> u32 {
+    groups.iter().fold(0, |mask, &group| {
+        assert!(group < NUM_COLLISION_GROUPS, "Collision group index out of range.");
+        mask | (1 << group)
+    })
+}
+
+impl CollisionFilter {
+    #[allow(dead_code)]
+    pub fn member_of(mut self, groups: &[usize]) -> Self {
+        self.membership = group_mask(groups);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_whitelist(mut self, groups: &[usize]) -> Self {
+        self.whitelist = group_mask(groups);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_blacklist(mut self, groups: &[usize]) -> Self {
+        self.blacklist = group_mask(groups);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn interacts_with(&self, other: &CollisionFilter) -> bool {
+        self.membership & other.whitelist != 0
+            && other.membership & self.whitelist != 0
+            && self.membership & other.blacklist == 0
+            && other.membership & self.blacklist == 0
+    }
 }
 
 pub struct CollisionComponentStore {
     models: Vec<CollisionModel>,
+    filters: Vec<CollisionFilter>,
+    sensors: Vec<bool>,
+    poses: Vec<Isometry3<f64>>,
     entities: Vec<Entity>,
 
     entity_map: HashMap<Entity, CollisionComponentId>,
@@ -21,6 +153,9 @@ impl CollisionComponentStore {
     pub fn new() -> CollisionComponentStore {
         CollisionComponentStore {
             models: Vec::new(),
+            filters: Vec::new(),
+            sensors: Vec::new(),
+            poses: Vec::new(),
             entities: Vec::new(),
             entity_map: HashMap::new(),
         }
@@ -33,6 +168,9 @@ impl CollisionComponentStore {
         let index = self.entity_map.entry(entity).or_insert(next_available_index).clone();
         if index == next_available_index {
             self.models.push(model);
+            self.filters.push(CollisionFilter::default());
+            self.sensors.push(false);
+            self.poses.push(Isometry3::identity());
             self.entities.push(entity);
         } else {
             self.models[index] = model;
@@ -41,6 +179,36 @@ impl CollisionComponentStore {
         index
     }
 
+    /// Sets the collision filter of the entity, which must already have a collision model.
+    pub fn set_component_filter(&mut self, entity: Entity, filter: CollisionFilter) {
+        let index = self.entity_map.get(&entity).cloned()
+                        .expect("Entity must have a collision model before a filter can be set.");
+        self.filters[index] = filter;
+    }
+
+    /// Turns the collision model of the entity into a sensor, or back into a solid model.
+    /// Sensors never take part in collision response, and only report when other
+    /// collision models enter or leave them. Unlike solid models, sensors
+    /// do not require the entity to have a rigid body.
+    pub fn set_component_sensor(&mut self, entity: Entity, sensor: bool) {
+        let index = self.entity_map.get(&entity).cloned()
+                        .expect("Entity must have a collision model before it can be made a sensor.");
+        self.sensors[index] = sensor;
+    }
+
+    /// Sets the pose of the collision model of an entity without a rigid body, such as
+    /// a sensor, which is otherwise placed at the origin. The collision models of
+    /// entities with rigid bodies follow their bodies instead.
+    pub fn set_component_pose(&mut self, entity: Entity, pose: Isometry3<f64>) {
+        let index = self.entity_map.get(&entity).cloned()
+                        .expect("Entity must have a collision model before its pose can be set.");
+        self.poses[index] = pose;
+    }
+
+    pub fn lookup_model(&self, entity: Entity) -> Option<&CollisionModel> {
+        self.entity_map.get(&entity).map(|&index| &self.models[index])
+    }
+
     pub fn num_components(&self) -> usize {
         assert!(self.models.len() == self.entities.len());
         self.models.len()
@@ -50,13 +218,57 @@ impl CollisionComponentStore {
         self.models.as_slice()
     }
 
+    pub fn filters<'a>(&'a self) -> &'a [CollisionFilter] {
+        self.filters.as_slice()
+    }
+
+    pub fn sensors<'a>(&'a self) -> &'a [bool] {
+        self.sensors.as_slice()
+    }
+
+    pub fn poses<'a>(&'a self) -> &'a [Isometry3<f64>] {
+        self.poses.as_slice()
+    }
+
     pub fn entities<'a>(&'a self) -> &'a [Entity] {
         self.entities.as_slice()
     }
 
     pub fn clear(&mut self) {
         self.models.clear();
+        self.filters.clear();
+        self.sensors.clear();
+        self.poses.clear();
         self.entity_map.clear();
         self.entities.clear();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::CollisionFilter;
+
+    #[test]
+    fn default_filters_interact() {
+        assert!(CollisionFilter::default().interacts_with(&CollisionFilter::default()));
+    }
+
+    #[test]
+    fn blacklisted_group_does_not_interact() {
+        let debris = CollisionFilter::default().member_of(&[1]).with_blacklist(&[1]);
+        let other = CollisionFilter::default().member_of(&[0]);
+        assert!(!debris.interacts_with(&debris));
+        assert!(debris.interacts_with(&other));
+        assert!(other.interacts_with(&debris));
+    }
+
+    #[test]
+    fn interaction_requires_mutual_whitelisting() {
+        let a = CollisionFilter::default().member_of(&[0]).with_whitelist(&[1]);
+        let b = CollisionFilter::default().member_of(&[1]).with_whitelist(&[2]);
+        let c = CollisionFilter::default().member_of(&[1]).with_whitelist(&[0]);
+        assert!(!a.interacts_with(&b));
+        assert!(!b.interacts_with(&a));
+        assert!(a.interacts_with(&c));
+    }
+}
@@ -1,40 +1,351 @@
 use physics::*;
-use nalgebra::{Vector3, Point3, Matrix3, UnitQuaternion, Isometry3, Translation3};
+use physics::contact_solver::{ContactSolver, ContactPoint};
+use physics::islands::wake_disturbed_islands;
+use nalgebra::{zero, norm, Point3, Vector3, UnitQuaternion, Isometry3, Translation3};
 use ncollide::world::{CollisionWorld3, CollisionGroups, GeometricQueryType};
-use ncollide::shape::{ShapeHandle3, Ball, Cuboid};
-use ncollide::query::Contact;
+use ncollide::shape::{Shape, ShapeHandle3, Ball, Cuboid, Capsule, Cylinder, Cone, Plane, Compound3,
+    ConvexHull, TriMesh};
+use ncollide::query::{self, Proximity, Ray};
+use ncollide::bounding_volume::{AABB, BoundingVolume};
 use entity::{Entity, LinearComponentStorage};
-use ordered_float::OrderedFloat;
-
-use itertools::Itertools;
+use message::Message;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::cmp::Ordering;
+use std::sync::Arc;
+use std;
+use interop;
 
 pub struct CollisionEngine {
-    world: CollisionWorld3<f64, Entity>
+    world: CollisionWorld3<f64, Entity>,
+    solver: ContactSolver,
+
+    // Retained between steps to avoid reallocation
+    contacts: Vec<ContactPoint>,
+
+    // Pairs of entities in contact after the last step
+    touching: Vec<(Entity, Entity)>,
+
+    // Pairs of (sensor, entity) where the entity was inside the sensor after the last step
+    triggered: Vec<(Entity, Entity)>,
+    sensors: HashSet<Entity>,
+
+    // The filter and sensor flag with which each collision object was added to the world
+    added_with: HashMap<Entity, (CollisionFilter, bool)>,
+
+    messages: Vec<Message>,
+
+    // Pairs of entities which never collide, ordered as in ContactPoint
+    disabled_pairs: HashSet<(Entity, Entity)>,
+
+    // Pairs of entities connected by joints which do not collide, rebuilt every step
+    jointed_pairs: HashSet<(Entity, Entity)>,
+
+    // Pairs of entities connected by contacts or joints in the last step,
+    // through which simulation islands are formed
+    island_pairs: Vec<(Entity, Entity)>
+}
+
+/// The intersection of a ray with a collision model.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RayHit {
+    pub entity: Entity,
+    pub point: Point3<f64>,
+
+    /// The outward normal of the surface at the point of intersection.
+    pub normal: Vector3<f64>,
+
+    /// The distance from the origin of the ray to the point of intersection.
+    pub distance: f64
+}
+
+/// Orders the entities of a pair consistently.
+fn ordered_pair(a: Entity, b: Entity) -> (Entity, Entity) {
+    if a <= b { (a, b) } else { (b, a) }
+}
+
+/// The pose of the shape of a collision model relative to the origin of its body.
+fn local_pose(model: &CollisionModel) -> Isometry3<f64> {
+    match *model {
+        CollisionModel::Sphere(ref sphere) =>
+            Isometry3::from_parts(Translation3::from_vector(sphere.center.coords),
+                                  UnitQuaternion::identity()),
+        CollisionModel::Cuboid(ref cuboid) =>
+            Isometry3::from_parts(Translation3::from_vector(cuboid.center.coords),
+                                  cuboid.rotation),
+        CollisionModel::Capsule(ref capsule) =>
+            Isometry3::from_parts(Translation3::from_vector(capsule.center.coords),
+                                  capsule.rotation),
+        CollisionModel::Cylinder(ref cylinder) =>
+            Isometry3::from_parts(Translation3::from_vector(cylinder.center.coords),
+                                  cylinder.rotation),
+        CollisionModel::Cone(ref cone) =>
+            Isometry3::from_parts(Translation3::from_vector(cone.center.coords),
+                                  cone.rotation),
+        CollisionModel::Plane(ref plane) =>
+            Isometry3::from_parts(Translation3::from_vector(plane.point.coords),
+                                  UnitQuaternion::identity()),
+        CollisionModel::ConvexHull(_) | CollisionModel::TriMesh(_) |
+        CollisionModel::Compound(_) => Isometry3::identity()
+    }
+}
+
+fn shape_handle(model: &CollisionModel) -> ShapeHandle3<f64> {
+    match *model {
+        CollisionModel::Sphere(ref sphere) => ShapeHandle3::new(Ball::new(sphere.radius)),
+        CollisionModel::Cuboid(ref cuboid) => ShapeHandle3::new(Cuboid::new(cuboid.half_size)),
+        CollisionModel::Capsule(ref capsule) =>
+            ShapeHandle3::new(Capsule::new(capsule.half_height, capsule.radius)),
+        CollisionModel::Cylinder(ref cylinder) =>
+            ShapeHandle3::new(Cylinder::new(cylinder.half_height, cylinder.radius)),
+        CollisionModel::Cone(ref cone) =>
+            ShapeHandle3::new(Cone::new(cone.half_height, cone.radius)),
+        CollisionModel::Plane(ref plane) => ShapeHandle3::new(Plane::new(plane.normal)),
+        CollisionModel::ConvexHull(ref mesh) => {
+            let points: Vec<Point3<f64>> = mesh.vertices().iter()
+                                               .map(interop::cgmath_point3_to_nalgebra)
+                                               .collect();
+            ShapeHandle3::new(ConvexHull::new(points))
+        },
+        CollisionModel::TriMesh(ref mesh) => {
+            let vertices: Vec<Point3<f64>> = mesh.vertices().iter()
+                                                 .map(interop::cgmath_point3_to_nalgebra)
+                                                 .collect();
+            let indices: Vec<Point3<usize>> = mesh.triangle_indices().iter()
+                .map(|t| Point3::new(t.indices[0], t.indices[1], t.indices[2]))
+                .collect();
+            ShapeHandle3::new(TriMesh::new(Arc::new(vertices), Arc::new(indices), None, None))
+        },
+        CollisionModel::Compound(ref children) => {
+            let shapes = children.iter()
+                .map(|&(ref isometry, ref child)| (isometry * local_pose(child), shape_handle(child)))
+                .collect();
+            ShapeHandle3::new(Compound3::new(shapes))
+        }
+    }
 }
 
-// As a quick hack, this is merely copy-pasted from physics_component.rs.
-// Need to find a better way to deal with this
-fn world_inverse_inertia(local_inertia_inv: &Matrix3<f64>, orientation: UnitQuaternion<f64>)
-    -> Matrix3<f64> {
-    let body_to_world = orientation.to_rotation_matrix();
-    let world_to_body = orientation.inverse().to_rotation_matrix();
-    body_to_world * (local_inertia_inv * world_to_body)
+fn collision_groups(filter: &CollisionFilter) -> CollisionGroups {
+    let mut groups = CollisionGroups::new();
+    for group in 0 .. NUM_COLLISION_GROUPS {
+        let bit = 1 << group;
+        groups.modify_membership(group, filter.membership & bit != 0);
+        groups.modify_whitelist(group, filter.whitelist & bit != 0);
+        groups.modify_blacklist(group, filter.blacklist & bit != 0);
+    }
+    groups
 }
 
 impl CollisionEngine {
     pub fn new() -> CollisionEngine {
         CollisionEngine {
-            world: CollisionWorld3::new(0.02, false)
+            world: CollisionWorld3::new(0.02, false),
+            solver: ContactSolver::new(),
+            contacts: Vec::new(),
+            touching: Vec::new(),
+            triggered: Vec::new(),
+            sensors: HashSet::new(),
+            added_with: HashMap::new(),
+            messages: Vec::new(),
+            disabled_pairs: HashSet::new(),
+            jointed_pairs: HashSet::new(),
+            island_pairs: Vec::new()
         }
     }
 
+    /// Prevents the two entities from colliding with each other,
+    /// regardless of their collision filters.
+    pub fn disable_collision_between(&mut self, a: Entity, b: Entity) {
+        self.disabled_pairs.insert(ordered_pair(a, b));
+    }
+
+    /// Reverts the effect of `disable_collision_between`.
+    #[allow(dead_code)]
+    pub fn enable_collision_between(&mut self, a: Entity, b: Entity) {
+        self.disabled_pairs.remove(&ordered_pair(a, b));
+    }
+
+    /// Whether collisions between an ordered pair of entities are ignored.
+    fn is_disabled(&self, pair: &(Entity, Entity)) -> bool {
+        self.disabled_pairs.contains(pair) || self.jointed_pairs.contains(pair)
+    }
+
+    /// Casts a ray against all collision models that interact with the given filter,
+    /// and returns the nearest intersection at most `max_toi` from the origin.
+    /// The direction need not be normalized, as the time of impact is measured
+    /// in units of distance. Rays starting inside a model hit it at the origin,
+    /// and rays without a direction or with non-finite coordinates hit nothing.
+    ///
+    /// Like all queries, this operates on the collision models as they were positioned
+    /// during the last step, and sensors are ignored.
+    #[allow(dead_code)]
+    pub fn raycast(&self,
+        origin: Point3<f64>,
+        direction: Vector3<f64>,
+        max_toi: f64,
+        filter: &CollisionFilter) -> Option<RayHit>
+    {
+        self.raycast_all(origin, direction, max_toi, filter).into_iter().next()
+    }
+
+    /// Like `raycast`, but returns all intersections ordered by increasing distance.
+    #[allow(dead_code)]
+    pub fn raycast_all(&self,
+        origin: Point3<f64>,
+        direction: Vector3<f64>,
+        max_toi: f64,
+        filter: &CollisionFilter) -> Vec<RayHit>
+    {
+        let length = norm(&direction);
+        let finite_origin = origin.x.is_finite() && origin.y.is_finite() && origin.z.is_finite();
+        if !(length > 0.0) || !length.is_finite() || !finite_origin {
+            return Vec::new();
+        }
+
+        let ray = Ray::new(origin, direction / length);
+        let groups = collision_groups(filter);
+        let mut hits: Vec<RayHit> = self.world.interferences_with_ray(&ray, &groups)
+            .filter(|&(object, ref intersection)| {
+                intersection.toi.is_finite() && intersection.toi <= max_toi
+                    && !self.sensors.contains(&object.data)
+            })
+            .map(|(object, intersection)| RayHit {
+                entity: object.data,
+                point: origin + intersection.toi * ray.dir,
+                normal: intersection.normal,
+                distance: intersection.toi
+            })
+            .collect();
+        hits.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap_or(Ordering::Equal));
+        hits
+    }
+
+    /// Returns the entities whose collision models contain the given point.
+    #[allow(dead_code)]
+    pub fn entities_containing_point(&self, point: Point3<f64>, filter: &CollisionFilter)
+        -> Vec<Entity>
+    {
+        let groups = collision_groups(filter);
+        self.world.interferences_with_point(&point, &groups)
+            .map(|object| object.data)
+            .filter(|entity| !self.sensors.contains(entity))
+            .collect()
+    }
+
+    /// Returns the entities whose collision models intersect the given sphere.
+    #[allow(dead_code)]
+    pub fn entities_overlapping_sphere(&self,
+        center: Point3<f64>,
+        radius: f64,
+        filter: &CollisionFilter) -> Vec<Entity>
+    {
+        let position = Isometry3::from_parts(Translation3::from_vector(center.coords),
+                                             UnitQuaternion::identity());
+        self.entities_overlapping(&position, ShapeHandle3::new(Ball::new(radius)), filter)
+    }
+
+    /// Returns the entities whose collision models intersect the axis-aligned box
+    /// spanned by the given corners.
+    #[allow(dead_code)]
+    pub fn entities_overlapping_box(&self,
+        min: Point3<f64>,
+        max: Point3<f64>,
+        filter: &CollisionFilter) -> Vec<Entity>
+    {
+        let half_extents = 0.5 * (max - min);
+        let position = Isometry3::from_parts(Translation3::from_vector(min.coords + half_extents),
+                                             UnitQuaternion::identity());
+        self.entities_overlapping(&position, ShapeHandle3::new(Cuboid::new(half_extents)), filter)
+    }
+
+    fn entities_overlapping(&self,
+        position: &Isometry3<f64>,
+        shape: ShapeHandle3<f64>,
+        filter: &CollisionFilter) -> Vec<Entity>
+    {
+        // The broad phase only finds candidates whose bounding boxes overlap,
+        // so the exact shapes must be tested for intersection afterwards
+        let groups = collision_groups(filter);
+        let aabb = shape.as_ref().aabb(position);
+        self.world.interferences_with_aabb(&aabb, &groups)
+            .filter(|object| !self.sensors.contains(&object.data))
+            .filter(|object| {
+                let proximity = query::proximity(position, shape.as_ref(),
+                                                 &object.position, object.shape.as_ref(), 0.0);
+                match proximity {
+                    Proximity::Intersecting => true,
+                    _ => false
+                }
+            })
+            .map(|object| object.data)
+            .collect()
+    }
+
     pub fn detect_and_resolve(&mut self,
+        dt: f64,
         rigid_bodies: &mut LinearComponentStorage<RigidBody>,
-        collision_store: &CollisionComponentStore)
+        collision_store: &CollisionComponentStore,
+        materials: &LinearComponentStorage<PhysicsMaterial>,
+        joints: &LinearComponentStorage<Joint>)
     {
+        self.jointed_pairs = joints.components().iter()
+            .filter(|&&(ref joint, _)| !joint.collide_connected)
+            .map(|&(ref joint, _)| ordered_pair(joint.body1, joint.body2))
+            .collect();
+
         self.sync_shapes_and_positions(rigid_bodies, collision_store);
         self.world.update();
-        self.resolve_collisions(rigid_bodies, collision_store);
+        self.gather_contacts();
+        self.detect_continuous_collisions(rigid_bodies);
+
+        // Sleeping islands touched by awake or moving bodies
+        // must be woken before they can be pushed around
+        self.island_pairs.clear();
+        self.island_pairs.extend(self.contacts.iter().map(|contact| (contact.entity1, contact.entity2)));
+        self.island_pairs.extend(joints.components().iter().map(|&(ref joint, _)| (joint.body1, joint.body2)));
+        wake_disturbed_islands(rigid_bodies, &self.island_pairs);
+
+        // Apart from the bodies stopped by continuous collision detection,
+        // penetration is corrected by the solver through the velocities,
+        // so positions are left untouched here
+        self.solver.solve(dt, &self.contacts, joints, rigid_bodies, materials);
+        self.publish_collision_events();
+        self.publish_trigger_events();
+    }
+
+    /// The pairs of entities which were connected by contacts or joints in the last step.
+    pub fn island_pairs(&self) -> &[(Entity, Entity)] {
+        &self.island_pairs
+    }
+
+    /// The state which is carried over to the next step, apart from the collision world.
+    pub fn state(&self) -> PhysicsState {
+        let (contact_impulses, joint_impulses) = self.solver.warm_start_impulses();
+        let mut disabled_pairs: Vec<_> = self.disabled_pairs.iter().cloned().collect();
+        disabled_pairs.sort();
+        PhysicsState {
+            contact_impulses: contact_impulses,
+            joint_impulses: joint_impulses,
+            touching: self.touching.clone(),
+            triggered: self.triggered.clone(),
+            disabled_pairs: disabled_pairs
+        }
+    }
+
+    /// Replaces the state carried over to the next step. The collision world is rebuilt
+    /// from the collision models in the next step, so any contact manifolds which ncollide
+    /// accumulates over several steps start afresh.
+    pub fn restore_state(&mut self, state: &PhysicsState) {
+        *self = CollisionEngine::new();
+        self.solver.set_warm_start_impulses(&state.contact_impulses, &state.joint_impulses);
+        self.touching = state.touching.clone();
+        self.triggered = state.triggered.clone();
+        self.disabled_pairs = state.disabled_pairs.iter().cloned().collect();
+    }
+
+    /// Removes and returns all messages published since the last call.
+    pub fn drain_messages(&mut self) -> Vec<Message> {
+        std::mem::replace(&mut self.messages, Vec::new())
     }
 
     fn sync_shapes_and_positions(&mut self,
@@ -46,41 +357,60 @@ impl CollisionEngine {
         // shapes
         let entities = collision_store.entities();
         let models = collision_store.models();
-        for (entity, model) in izip!(entities, models) {
+        let filters = collision_store.filters();
+        let sensors = collision_store.sensors();
+        let poses = collision_store.poses();
+
+        // The collision groups and query type of an object are fixed when it is added
+        // to the world, so objects whose filter or sensor flag has changed since then
+        // are removed here, and added again with their new settings below
+        let mut removed_any = false;
+        for (entity, &filter, &is_sensor) in izip!(entities, filters, sensors) {
+            let changed = self.added_with.get(entity).map_or(false, |&added| added != (filter, is_sensor));
+            if changed {
+                self.world.deferred_remove(entity.clone().into());
+                self.added_with.remove(entity);
+                self.sensors.remove(entity);
+                removed_any = true;
+            }
+        }
+        if removed_any {
+            self.world.perform_additions_removals_and_broad_phase();
+        }
+
+        for (entity, model, filter, &is_sensor, pose) in izip!(entities, models, filters, sensors, poses) {
             let entity_uid: usize = entity.clone().into();
 
             let rb = bodies.lookup_component_for_entity(entity.clone());
 
             // At the moment we only allow collisions between rigid bodies,
-            // so an associated rigid body component must belong to the entity
-            if let Some(rb) = rb {
-                let (center, rotation) = match model {
-                    &CollisionModel::Sphere(sphere) =>
-                        (sphere.center, UnitQuaternion::identity()),
-                    &CollisionModel::Cuboid(cuboid) =>
-                        (cuboid.center, cuboid.rotation)
-                };
-                let translation = Translation3::from_vector(center.coords + rb.position().coords);
-                let rotation = rb.orientation() * rotation;
-                let position = Isometry3::from_parts(translation, rotation);
+            // so an associated rigid body component must belong to the entity.
+            // Sensors without a rigid body remain fixed at the pose of their component.
+            let body_isometry = match rb {
+                Some(rb) => Some(Isometry3::from_parts(
+                    Translation3::from_vector(rb.position().coords), rb.orientation())),
+                None if is_sensor => Some(pose.clone()),
+                None => None
+            };
+
+            if let Some(body_isometry) = body_isometry {
+                let position = body_isometry * local_pose(model);
 
                 if self.world.collision_object(entity_uid).is_none() {
-                    let shape_handle = match model {
-                        &CollisionModel::Sphere(sphere) => {
-                                let ball = Ball::new(sphere.radius);
-                                ShapeHandle3::new(ball)
-                            },
-                            &CollisionModel::Cuboid(cuboid) => {
-                                let half_extents = cuboid.half_size;
-                                let cuboid = Cuboid::new(half_extents);
-                                ShapeHandle3::new(cuboid)
-                            }
+                    let shape_handle = shape_handle(model);
+                    // Sensors only detect whether other objects intersect them
+                    let query_type = if is_sensor {
+                        self.sensors.insert(entity.clone());
+                        GeometricQueryType::Proximity(0.0)
+                    } else {
+                        GeometricQueryType::Contacts(0.0)
                     };
+                    self.added_with.insert(entity.clone(), (filter.clone(), is_sensor));
                     self.world.deferred_add(entity_uid,
                         position,
                         shape_handle,
-                        CollisionGroups::new(),
-                        GeometricQueryType::Contacts(0.0),
+                        collision_groups(filter),
+                        query_type,
                         entity.clone());
                 } else {
                     self.world.deferred_set_position(entity_uid, position);
@@ -90,259 +420,221 @@ impl CollisionEngine {
         }
     }
 
-    pub fn resolve_collisions(&mut self,
-        bodies: &mut LinearComponentStorage<RigidBody>,
-        collision_store: &CollisionComponentStore)
-    {
-        self.resolve_interpenetrations(bodies);
-        self.sync_shapes_and_positions(bodies, collision_store);
-        self.world.update();
-        self.resolve_velocities(bodies);
+    fn gather_contacts(&mut self) {
+        // Every contact in every contact manifold becomes a separate constraint.
+        // We take the midpoint between the two witness points as the point of contact.
+        self.contacts.clear();
+        for (obj1, obj2, contact) in self.world.contacts() {
+            // Order the entities of each pair consistently, so that
+            // the same pair can be recognized in later steps
+            let (entity1, entity2) = ordered_pair(obj1.data, obj2.data);
+            let normal = if entity1 == obj1.data { contact.normal } else { - contact.normal };
+            if self.is_disabled(&(entity1, entity2)) {
+                continue;
+            }
+
+            let point = Point3::from_coordinates(0.5 * (contact.world1.coords + contact.world2.coords));
+            self.contacts.push(ContactPoint {
+                entity1: entity1,
+                entity2: entity2,
+                point: point,
+                normal: normal,
+                depth: contact.depth
+            });
+        }
     }
 
-    fn resolve_velocities(&mut self,
-        bodies: &mut LinearComponentStorage<RigidBody>)
-    {
-        // Let the most significant contact between two rigid bodies
-        // be defined as the contact with the greatest penetration
-        // depth. Our rudimentary collision resolution
-        // method currently only processes the most significant
-        // contact.
-
-        let significant_contacts: Vec<_> = self.world
-            .contacts()
-            .group_by(|&(ref obj1, ref obj2, _)| {
-                let (entity1, entity2) = (obj1.data, obj2.data);
-                (entity1, entity2)
-            })
-            .map(|((entity1, entity2), ref contacts_for_pair)| {
-                let significant_contact =
-                    contacts_for_pair.iter()
-                                        .map(|&(_, _, ref contacts)| contacts)
-                                        .max_by_key(|contact| OrderedFloat(contact.depth))
-                                        .expect("Every pair has at least one contact");
-                (entity1, entity2, significant_contact.clone())
-            }).collect();
-
-        for (entity1, entity2, contact) in significant_contacts {
-            let rb1 = bodies.lookup_component_for_entity(entity1).cloned();
-            let rb2 = bodies.lookup_component_for_entity(entity2).cloned();
-
-            if let (Some(rb1), Some(rb2)) = (rb1, rb2) {
-                use RigidBody::{Dynamic, Static};
-                match (rb1, rb2) {
-                    (Dynamic(rb1), Dynamic(rb2)) => {
-                            let (rb1, rb2) = resolve_dynamic_dynamic_velocity(
-                                rb1, rb2, &contact);
-                            bodies.set_component_for_entity(entity1, RigidBody::Dynamic(rb1));
-                            bodies.set_component_for_entity(entity2, RigidBody::Dynamic(rb2));
-                        },
-                    (Static(_), Dynamic(rb)) => {
-                        let rb = resolve_static_dynamic_velocity(rb,
-                                    contact.world1,
-                                    contact.normal);
-                        bodies.set_component_for_entity(entity2, Dynamic(rb));
-                    },
-                    (Dynamic(rb), Static(_)) => {
-                        // Because we define the contact point to be on the static
-                        // body, we must flip the normal and use the contact point
-                        // of the static body
-                        let rb = resolve_static_dynamic_velocity(rb,
-                                        contact.world2,
-                                    - contact.normal);
-                        bodies.set_component_for_entity(entity1, Dynamic(rb));
-                    },
-                    (Static(_), Static(_)) => {
-                        // We don't handle static-static collisions
+    /// Sweeps the bodies that have continuous collision detection enabled along their
+    /// linear motion during the last step, against all other collision models at their
+    /// current positions. A body that would have passed into another model is moved back
+    /// to the time of impact, and its contacts with that model are replaced by the contact
+    /// found there, so that the solver stops it at the surface. Its other contacts are
+    /// kept, moved back along with the body. Rotation during the step is ignored.
+    fn detect_continuous_collisions(&mut self, rigid_bodies: &mut LinearComponentStorage<RigidBody>) {
+        // Kinematic bodies are swept along with the bodies they may hit, so that
+        // fast bodies do not pass through moving paddles or platforms
+        let kinematic_motions: HashMap<Entity, Vector3<f64>> = rigid_bodies.components().iter()
+            .filter_map(|&(ref rb, entity)| rb.as_kinematic().map(|rb| (entity, rb.position - rb.prev_position)))
+            .collect();
+        let largest_kinematic_motion = kinematic_motions.values().map(|motion| norm(motion)).fold(0.0, f64::max);
+
+        let mut moved_back = Vec::new();
+        for &mut (ref mut rb, entity) in rigid_bodies.components_mut() {
+            let rb = match rb.as_dynamic_mut() {
+                Some(rb) => rb,
+                None => continue
+            };
+            let entity_uid: usize = entity.into();
+            let object = match self.world.collision_object(entity_uid) {
+                Some(object) => object,
+                None => continue
+            };
+            if !rb.ccd || self.sensors.contains(&entity) {
+                continue;
+            }
+
+            // A body must move further than its smallest extent relative to another
+            // model to pass through it entirely, and slower bodies are handled by the
+            // regular contacts, so we only sweep bodies that may move faster than this
+            let motion = rb.state.position - rb.prev_state.position;
+            let end_aabb = object.shape.as_ref().aabb(&object.position);
+            let half_extents = 0.5 * (*end_aabb.maxs() - *end_aabb.mins());
+            let smallest_half_extent = half_extents.x.min(half_extents.y).min(half_extents.z);
+            if norm(&motion) + largest_kinematic_motion <= smallest_half_extent {
+                continue;
+            }
+
+            // The swept bounds are loosened by the motion of the kinematic bodies,
+            // which may have been in the way at the start of the step but not at its end
+            let mut start = object.position;
+            start.translation.vector -= motion;
+            let start_aabb = AABB::new(*end_aabb.mins() - motion, *end_aabb.maxs() - motion);
+            let swept_aabb = end_aabb.merged(&start_aabb).loosened(largest_kinematic_motion);
+
+            let mut earliest_impact = None;
+            for other in self.world.interferences_with_aabb(&swept_aabb, &object.collision_groups) {
+                let other_entity = other.data;
+                if other_entity == entity || self.sensors.contains(&other_entity)
+                    || self.is_disabled(&ordered_pair(entity, other_entity)) {
+                    continue;
+                }
+
+                // Bodies that are already in contact at the start of the step
+                // have a time of impact of zero, and are left to the regular contacts
+                let other_motion = kinematic_motions.get(&other_entity).cloned().unwrap_or(zero());
+                let mut other_start = other.position;
+                other_start.translation.vector -= other_motion;
+                let toi = query::time_of_impact(&start, &motion, object.shape.as_ref(),
+                                                &other_start, &other_motion, other.shape.as_ref());
+                if let Some(toi) = toi {
+                    let is_earlier = earliest_impact.as_ref().map_or(true, |&(t, _, _)| toi < t);
+                    if toi > 0.0 && toi < 1.0 && is_earlier {
+                        let mut other_impact = other_start;
+                        other_impact.translation.vector += toi * other_motion;
+                        earliest_impact = Some((toi, other, other_impact));
                     }
                 }
             }
+
+            if let Some((toi, other, other_impact)) = earliest_impact {
+                let mut impact = start;
+                impact.translation.vector += toi * motion;
+                rb.state.position = rb.prev_state.position + toi * motion;
+
+                // The bodies are only just touching at the time of impact, so we look for
+                // a contact up to the distance the body would otherwise have travelled
+                let prediction = norm(&motion);
+                let contact = query::contact(&impact, object.shape.as_ref(),
+                                             &other_impact, other.shape.as_ref(), prediction);
+
+                // Contacts with other models, such as the floor the body slides on, still hold,
+                // but their points must follow the body to keep its lever arms unchanged
+                let (entity1, entity2) = ordered_pair(entity, other.data);
+                let displacement = - (1.0 - toi) * motion;
+                self.contacts.retain(|c| (c.entity1, c.entity2) != (entity1, entity2));
+                for c in self.contacts.iter_mut().filter(|c| c.entity1 == entity || c.entity2 == entity) {
+                    c.point += displacement;
+                }
+                moved_back.push((entity_uid, impact));
+
+                if let Some(contact) = contact {
+                    let normal = if entity1 == entity { contact.normal } else { - contact.normal };
+                    let point = Point3::from_coordinates(0.5 * (contact.world1.coords + contact.world2.coords));
+                    self.contacts.push(ContactPoint {
+                        entity1: entity1,
+                        entity2: entity2,
+                        point: point,
+                        normal: normal,
+                        // The bodies touch at the time of impact by definition, even if the
+                        // contact found there is separated by a tiny gap
+                        depth: contact.depth.max(0.0)
+                    });
+                }
+            }
+        }
+
+        // Queries made before the next step must see the bodies where they were stopped
+        if !moved_back.is_empty() {
+            for (entity_uid, pose) in moved_back {
+                self.world.deferred_set_position(entity_uid, pose);
+            }
+            self.world.perform_position_update();
+            self.world.perform_additions_removals_and_broad_phase();
         }
     }
 
-    fn resolve_interpenetrations(&mut self,
-        bodies: &mut LinearComponentStorage<RigidBody>)
-    {
-        let significant_contacts: Vec<_> = self.world
-            .contacts()
-            .group_by(|&(ref obj1, ref obj2, _)| {
-                let (entity1, entity2) = (obj1.data, obj2.data);
-                (entity1, entity2)
-            })
-            .map(|((entity1, entity2), ref contacts_for_pair)| {
-                let significant_contact =
-                    contacts_for_pair.iter()
-                                        .map(|&(_, _, ref contacts)| contacts)
-                                        .max_by_key(|contact| OrderedFloat(contact.depth))
-                                        .expect("Every pair has at least one contact");
-                (entity1, entity2, significant_contact.clone())
-            }).collect();
-        for (entity1, entity2, contact) in significant_contacts {
-            let rb1 = bodies.lookup_component_for_entity(entity1).cloned();
-            let rb2 = bodies.lookup_component_for_entity(entity2).cloned();
-
-            if let (Some(rb1), Some(rb2)) = (rb1, rb2) {
-                use RigidBody::{Static, Dynamic};
-                match (rb1, rb2) {
-                    (Dynamic(mut rb1), Dynamic(mut rb2)) => {
-                        let m1 = rb1.mass.value();
-                        let m2 = rb2.mass.value();
-                        let total_mass = m1 + m2;
-
-                        // Move the two objects linearly away from each other along the contact normal.
-                        // The distance to move is determined by the relative masses of the two objects,
-                        // and the penetration depth.
-                        let obj1_move_dist = (m2 / total_mass) * contact.depth;
-                        let obj2_move_dist = (m1 / total_mass) * contact.depth;
-
-                        rb1.state.position -= obj1_move_dist * contact.normal;
-                        rb2.state.position += obj2_move_dist * contact.normal;
-
-                        bodies.set_component_for_entity(entity1, RigidBody::Dynamic(rb1));
-                        bodies.set_component_for_entity(entity2, RigidBody::Dynamic(rb2));
-                    },
-                    (Static(_), Dynamic(mut rb)) => {
-                        rb.state.position += contact.depth * contact.normal;
-                        bodies.set_component_for_entity(entity2, RigidBody::Dynamic(rb));
-                    },
-                    (Dynamic(mut rb), Static(_)) => {
-                        // Note the sign here! Normal points from dynamic to static,
-                        // so we must move the opposite direction of the normal
-                        rb.state.position -= contact.depth * contact.normal;
-                        bodies.set_component_for_entity(entity1, RigidBody::Dynamic(rb));
-                    },
-                    (Static(_), Static(_)) => {
-                        // Ignored
+    fn publish_collision_events(&mut self) {
+        let mut touching = Vec::new();
+        let mut deepest_contacts = HashMap::new();
+        for contact in self.contacts.iter().filter(|c| c.depth >= 0.0) {
+            let pair = (contact.entity1, contact.entity2);
+            match deepest_contacts.entry(pair) {
+                Entry::Vacant(entry) => {
+                    touching.push(pair);
+                    entry.insert(contact.clone());
+                },
+                Entry::Occupied(mut entry) => {
+                    if contact.depth > entry.get().depth {
+                        entry.insert(contact.clone());
                     }
                 }
             }
         }
-    }
-}
 
-fn resolve_dynamic_dynamic_velocity(
-    mut rb1: DynamicRigidBody,
-    mut rb2: DynamicRigidBody,
-    contact: &Contact<Point3<f64>>)
-    -> (DynamicRigidBody, DynamicRigidBody)
-{
-    // Use the following terminology (suffixed by 1 or 2):
-    // v: linear velocity (i.e. velocity of mass center)
-    // m: mass
-    // w: angular velocity
-    // r: contact point relative to center of mass
-    // i_inv: inverse inertia tensor (in world coordinates)
-    // v_p: velocity at point of contact (includes angular contribution)
-    //
-    // The mathematics here are based on the following Wikipedia article:
-    // https://en.wikipedia.org/wiki/Collision_response#Impulse-based_reaction_model
-    let restitution = 1.0;
-
-    let contact_point = contact.world1;
-    let orientation1 = rb1.state.orientation;
-    let orientation2 = rb2.state.orientation;
-    let v1 = rb1.state.velocity;
-    let v2 = rb2.state.velocity;
-    let m1 = rb1.mass.value();
-    let m2 = rb2.mass.value();
-    let r1 = contact_point - rb1.state.position;
-    let r2 = contact_point - rb2.state.position;
-    let i_inv1 = world_inverse_inertia(&rb1.inv_inertia_body,
-                                        orientation1);
-    let i_inv2 = world_inverse_inertia(&rb2.inv_inertia_body,
-                                        orientation2);
-    let w1 = i_inv1 * rb1.state.angular_momentum;
-    let w2 = i_inv2 * rb2.state.angular_momentum;
-    let v_p1 = v1 + w1.cross(&r1);
-    let v_p2 = v2 + w2.cross(&r2);
-
-    // Let n denote the contact normal
-    let n = contact.normal;
-
-    // Define the "relative velocity" at the point of impact
-    let v_r = v_p2 - v_p1;
-
-    // The separating velocity is the projection of the relative velocity
-    // onto the contact normal.
-    let v_separating = v_r.dot(&n);
-
-    // If v_separating is non-negative, the objects are not moving
-    // towards each other, and we do not need to add any corrective impulse.
-    if v_separating < 0.0 {
-        // j_r denotes the relative (reaction) impulse
-        let j_r = {
-            let linear_denominator = 1.0 / m1 + 1.0 / m2;
-            let angular_denominator1 = (i_inv1 * r1.cross(&n)).cross(&r1);
-            let angular_denominator2 = (i_inv2 * r2.cross(&n)).cross(&r2);
-            let angular_denominator = (angular_denominator1 + angular_denominator2).dot(&n);
-            let numerator = -(1.0 + restitution) * v_separating;
-            numerator / (linear_denominator + angular_denominator)
-        };
-
-        // Compute post-collision velocities
-        let v1_post = v1 - j_r / m1 * n;
-        let v2_post = v2 + j_r / m2 * n;
-        let w1_post = w1 - j_r * i_inv1 * r1.cross(&n);
-        let w2_post = w2 + j_r * i_inv2 * r2.cross(&n);
-        rb1.state.velocity = v1_post;
-        rb2.state.velocity = v2_post;
-
-        // TODO: Avoid the inversions here
-        use interop::try_3x3_inverse;
-        rb1.state.angular_momentum = try_3x3_inverse(i_inv1).unwrap() * w1_post;
-        rb2.state.angular_momentum = try_3x3_inverse(i_inv2).unwrap() * w2_post;
-    }
+        {
+            let previously_touching: HashSet<_> = self.touching.iter().cloned().collect();
+            for pair in touching.iter().filter(|pair| !previously_touching.contains(*pair)) {
+                let &(a, b) = pair;
+                let contact = deepest_contacts[pair];
+                self.messages.push(Message::CollisionStarted {
+                    a: a,
+                    b: b,
+                    point: contact.point,
+                    normal: contact.normal,
+                    impulse: self.solver.normal_impulse_between(a, b)
+                });
+            }
+        }
 
-    (rb1, rb2)
-}
+        for &(a, b) in self.touching.iter().filter(|pair| !deepest_contacts.contains_key(*pair)) {
+            self.messages.push(Message::CollisionEnded { a: a, b: b });
+        }
 
-fn resolve_static_dynamic_velocity(
-    mut rb: DynamicRigidBody,
-    point: Point3<f64>,
-    normal: Vector3<f64>)
-    -> DynamicRigidBody
-{
-    let restitution = 1.0;
-
-    let orientation2 = rb.state.orientation;
-    let v2 = rb.state.velocity;
-    let m2 = rb.mass.value();
-    let r2 = point - rb.state.position;
-    let i_inv2 = world_inverse_inertia(&rb.inv_inertia_body,
-                                        orientation2);
-    let w2 = i_inv2 * rb.state.angular_momentum;
-    let v_p2 = v2 + w2.cross(&r2);
-
-    let n = normal;
-
-    // Define the "relative velocity" v_r at the point of impact
-    let v_r = v_p2;
-
-    // The separating velocity is the projection of the relative velocity
-    // onto the contact normal.
-    let v_separating = v_r.dot(&n);
-
-    // If v_separating is non-negative, the objects are not moving
-    // towards each other, and we do not need to add any corrective impulse.
-    if v_separating < 0.0 {
-        // j_r denotes the relative (reaction) impulse
-        let j_r = {
-            let linear_denominator = 1.0 / m2;
-            let angular_denominator2 = (i_inv2 * r2.cross(&n)).cross(&r2);
-            let angular_denominator = (angular_denominator2).dot(&n);
-            let numerator = -(1.0 + restitution) * v_separating;
-            numerator / (linear_denominator + angular_denominator)
-        };
-
-        // Compute post-collision velocities
-        let v2_post = v2 + j_r / m2 * n;
-        let w2_post = w2 + j_r * i_inv2 * r2.cross(&n);
-        rb.state.velocity = v2_post;
-
-        // TODO: Avoid the inversions here
-        use interop::try_3x3_inverse;
-        rb.state.angular_momentum = try_3x3_inverse(i_inv2).unwrap() * w2_post;
+        self.touching = touching;
     }
 
-    rb
+    fn publish_trigger_events(&mut self) {
+        let mut triggered = Vec::new();
+        for (obj1, obj2, detector) in self.world.proximity_pairs() {
+            let (entity1, entity2) = ordered_pair(obj1.data, obj2.data);
+            let intersecting = match detector.proximity() {
+                Proximity::Intersecting => true,
+                _ => false
+            };
+            if !intersecting || self.is_disabled(&(entity1, entity2)) {
+                continue;
+            }
+
+            // If both objects are sensors, the first one is considered the sensor
+            let pair = if self.sensors.contains(&entity1) {
+                (entity1, entity2)
+            } else {
+                (entity2, entity1)
+            };
+            triggered.push(pair);
+        }
+
+        {
+            let previously_triggered = &self.triggered;
+            let messages = &mut self.messages;
+            for &(sensor, entity) in triggered.iter().filter(|pair| !previously_triggered.contains(*pair)) {
+                messages.push(Message::TriggerEntered { sensor: sensor, entity: entity });
+            }
+            for &(sensor, entity) in previously_triggered.iter().filter(|pair| !triggered.contains(*pair)) {
+                messages.push(Message::TriggerExited { sensor: sensor, entity: entity });
+            }
+        }
+
+        self.triggered = triggered;
+    }
 }
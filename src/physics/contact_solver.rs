@@ -0,0 +1,712 @@
+use physics::{RigidBody, PhysicsMaterial, ContactMaterial, Joint};
+use physics::angular_integrator::world_inverse_inertia;
+use physics::joint::{JointRow, StepParameters, prepare_joint_rows};
+use entity::{Entity, LinearComponentStorage};
+use nalgebra::{zero, norm, norm_squared, Point3, Vector3, Matrix3, UnitQuaternion};
+use std::collections::HashMap;
+
+/// A single point of contact between two bodies.
+#[derive(Copy, Clone, Debug)]
+pub struct ContactPoint {
+    pub entity1: Entity,
+    pub entity2: Entity,
+
+    /// The point of contact, in world coordinates.
+    pub point: Point3<f64>,
+
+    /// The unit contact normal, pointing from the first towards the second body.
+    pub normal: Vector3<f64>,
+
+    /// The penetration depth along the normal. Negative for bodies which
+    /// are not yet in contact, but are predicted to be.
+    pub depth: f64
+}
+
+/// The velocity state of a body taking part in contact or joint resolution.
+/// Static bodies have zero inverse mass and inertia.
+pub struct SolverBody {
+    pub entity: Entity,
+    pub position: Point3<f64>,
+    pub orientation: UnitQuaternion<f64>,
+    pub velocity: Vector3<f64>,
+    pub angular_velocity: Vector3<f64>,
+    pub angular_momentum: Vector3<f64>,
+    pub inv_mass: f64,
+    pub inv_inertia: Matrix3<f64>
+}
+
+struct ContactConstraint {
+    body1: usize,
+    body2: usize,
+    point: Point3<f64>,
+
+    // Contact point relative to the center of mass of each body
+    r1: Vector3<f64>,
+    r2: Vector3<f64>,
+
+    normal: Vector3<f64>,
+    tangent1: Vector3<f64>,
+    tangent2: Vector3<f64>,
+
+    // Effective masses along the normal and tangent directions
+    normal_mass: f64,
+    tangent_mass1: f64,
+    tangent_mass2: f64,
+
+    // Target separating velocity, accounting for restitution and penetration
+    bias: f64,
+    friction: f64,
+
+    // Impulses accumulated over all iterations
+    normal_impulse: f64,
+    tangent_impulse1: f64,
+    tangent_impulse2: f64
+}
+
+#[derive(Copy, Clone, Debug)]
+struct CachedImpulse {
+    point: Point3<f64>,
+    normal: f64,
+    tangent1: f64,
+    tangent2: f64
+}
+
+/// The impulses accumulated at a contact point in the last step,
+/// with which the solver is warm started in the next step.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ContactImpulse {
+    pub entity1: Entity,
+    pub entity2: Entity,
+    pub point: Point3<f64>,
+    pub normal: f64,
+    pub tangent1: f64,
+    pub tangent2: f64
+}
+
+/// An iterative sequential impulse solver for contact and joint constraints.
+///
+/// Every contact point is treated as a separate constraint, and so is every scalar
+/// row of a joint. Joints are solved before contacts in each iteration, so that
+/// contacts take precedence. The constraints
+/// are solved one at a time for a fixed number of iterations. Rather than clamping
+/// each incremental impulse, the total impulse accumulated over all iterations
+/// is clamped, so that a constraint may undo excessive impulses from earlier
+/// iterations. Accumulated impulses are retained between steps, and used as the
+/// initial guess for matching contacts in the next step (warm starting), which
+/// lets resting contacts, such as stacks of bodies, converge over several steps.
+///
+/// Penetration is corrected by Baumgarte stabilization, in which a separating
+/// velocity proportional to the penetration depth is added to the target velocity.
+///
+/// See Erin Catto, "Iterative Dynamics with Temporal Coherence" (2005).
+pub struct ContactSolver {
+    /// The number of iterations over all constraints in each step.
+    pub iterations: usize,
+
+    /// The fraction of the penetration depth corrected for in each step.
+    pub baumgarte: f64,
+
+    /// Penetration depth which is left uncorrected, so that
+    /// resting contacts are not lost between steps.
+    pub slop: f64,
+
+    /// Approach speeds below this threshold do not give rise to any bounce,
+    /// so that bodies resting under gravity stay at rest.
+    pub restitution_threshold: f64,
+
+    /// Contacts between the same pair of bodies which lie within this distance
+    /// of a contact in the previous step are considered to be the same contact.
+    pub warm_start_distance: f64,
+
+    /// Static friction applies when the relative tangential speed at
+    /// the contact point is below this threshold.
+    pub sliding_threshold: f64,
+
+    bodies: Vec<SolverBody>,
+    body_indices: HashMap<Entity, usize>,
+    constraints: Vec<ContactConstraint>,
+    cache: HashMap<(Entity, Entity), Vec<CachedImpulse>>,
+
+    joint_rows: Vec<JointRow>,
+    // The range of rows belonging to each joint entity
+    joint_ranges: Vec<(Entity, usize, usize)>,
+    joint_cache: HashMap<Entity, Vec<f64>>
+}
+
+impl SolverBody {
+    fn from_rigid_body(entity: Entity, rb: &RigidBody) -> SolverBody {
+        match rb {
+            &RigidBody::Static(ref rb) => SolverBody {
+                entity: entity,
+                position: rb.position,
+                orientation: rb.orientation,
+                velocity: zero(),
+                angular_velocity: zero(),
+                angular_momentum: zero(),
+                inv_mass: 0.0,
+                inv_inertia: zero()
+            },
+            // Sleeping bodies are only in contact with static or sleeping bodies,
+            // since anything else would have woken them, so they stay at rest
+            &RigidBody::Dynamic(ref rb) if rb.sleeping => SolverBody {
+                entity: entity,
+                position: rb.state.position,
+                orientation: rb.state.orientation,
+                velocity: zero(),
+                angular_velocity: zero(),
+                angular_momentum: zero(),
+                inv_mass: 0.0,
+                inv_inertia: zero()
+            },
+            &RigidBody::Dynamic(ref rb) => {
+                let inv_inertia = world_inverse_inertia(&rb.inv_inertia_body, rb.state.orientation);
+                SolverBody {
+                    entity: entity,
+                    position: rb.state.position,
+                    orientation: rb.state.orientation,
+                    velocity: rb.state.velocity,
+                    angular_velocity: inv_inertia * rb.state.angular_momentum,
+                    angular_momentum: rb.state.angular_momentum,
+                    inv_mass: 1.0 / rb.mass.value(),
+                    inv_inertia: inv_inertia
+                }
+            },
+            // Kinematic bodies move, but have infinite mass like static bodies
+            &RigidBody::Kinematic(ref rb) => SolverBody {
+                entity: entity,
+                position: rb.position,
+                orientation: rb.orientation,
+                velocity: rb.velocity,
+                angular_velocity: rb.angular_velocity,
+                angular_momentum: zero(),
+                inv_mass: 0.0,
+                inv_inertia: zero()
+            }
+        }
+    }
+
+    fn velocity_at(&self, r: &Vector3<f64>) -> Vector3<f64> {
+        self.velocity + self.angular_velocity.cross(r)
+    }
+
+    fn apply_impulse(&mut self, impulse: Vector3<f64>, r: &Vector3<f64>) {
+        self.apply_generalized_impulse(impulse, r.cross(&impulse));
+    }
+
+    /// Applies a linear impulse through the center of mass, along with an angular impulse.
+    pub fn apply_generalized_impulse(&mut self, impulse: Vector3<f64>, angular_impulse: Vector3<f64>) {
+        self.velocity += self.inv_mass * impulse;
+        self.angular_momentum += angular_impulse;
+        self.angular_velocity += self.inv_inertia * angular_impulse;
+    }
+}
+
+impl ContactConstraint {
+    fn relative_velocity(&self, bodies: &[SolverBody]) -> Vector3<f64> {
+        bodies[self.body2].velocity_at(&self.r2) - bodies[self.body1].velocity_at(&self.r1)
+    }
+
+    fn apply_impulse(&self, bodies: &mut [SolverBody], impulse: Vector3<f64>) {
+        bodies[self.body1].apply_impulse(- impulse, &self.r1);
+        bodies[self.body2].apply_impulse(impulse, &self.r2);
+    }
+}
+
+impl ContactSolver {
+    pub fn new() -> Self {
+        ContactSolver {
+            iterations: 10,
+            baumgarte: 0.2,
+            slop: 0.01,
+            restitution_threshold: 0.5,
+            warm_start_distance: 0.05,
+            sliding_threshold: 0.01,
+            bodies: Vec::new(),
+            body_indices: HashMap::new(),
+            constraints: Vec::new(),
+            cache: HashMap::new(),
+            joint_rows: Vec::new(),
+            joint_ranges: Vec::new(),
+            joint_cache: HashMap::new()
+        }
+    }
+
+    /// Computes and applies contact impulses to the velocities of the bodies involved
+    /// in the given contacts, such that the bodies no longer approach each other
+    /// at any of the contact points, along with joint impulses which keep the bodies
+    /// connected by joints from moving apart.
+    pub fn solve(&mut self,
+                 dt: f64,
+                 contacts: &[ContactPoint],
+                 joints: &LinearComponentStorage<Joint>,
+                 rigid_bodies: &mut LinearComponentStorage<RigidBody>,
+                 materials: &LinearComponentStorage<PhysicsMaterial>)
+    {
+        assert!(dt >= 0.0);
+        self.prepare_constraints(dt, contacts, rigid_bodies, materials);
+        self.prepare_joints(dt, joints, rigid_bodies);
+        self.warm_start();
+        for _ in 0 .. self.iterations {
+            self.solve_joints();
+            self.solve_velocities();
+        }
+        self.store_impulses();
+        self.write_back(rigid_bodies);
+    }
+
+    /// The total normal impulse applied between the two entities in the last step.
+    pub fn normal_impulse_between(&self, entity1: Entity, entity2: Entity) -> f64 {
+        self.cache.get(&(entity1, entity2))
+                  .map(|cached| cached.iter().map(|c| c.normal).sum::<f64>())
+                  .unwrap_or(0.0)
+    }
+
+    /// The contact impulses accumulated in the last step, ordered by the pair of entities,
+    /// along with the impulses of the rows of every joint, ordered by joint entity.
+    pub fn warm_start_impulses(&self) -> (Vec<ContactImpulse>, Vec<(Entity, Vec<f64>)>) {
+        let mut pairs: Vec<_> = self.cache.keys().cloned().collect();
+        pairs.sort();
+        let contacts = pairs.iter()
+            .flat_map(|&(entity1, entity2)| self.cache[&(entity1, entity2)].iter().map(move |c| ContactImpulse {
+                entity1: entity1,
+                entity2: entity2,
+                point: c.point,
+                normal: c.normal,
+                tangent1: c.tangent1,
+                tangent2: c.tangent2
+            }))
+            .collect();
+
+        let mut joints: Vec<_> = self.joint_cache.iter()
+            .map(|(&entity, impulses)| (entity, impulses.clone()))
+            .collect();
+        joints.sort_by_key(|&(entity, _)| entity);
+        (contacts, joints)
+    }
+
+    /// Replaces the impulses with which the solver is warm started in the next step.
+    pub fn set_warm_start_impulses(&mut self, contacts: &[ContactImpulse], joints: &[(Entity, Vec<f64>)]) {
+        self.cache.clear();
+        for c in contacts {
+            self.cache.entry((c.entity1, c.entity2)).or_insert_with(Vec::new).push(CachedImpulse {
+                point: c.point,
+                normal: c.normal,
+                tangent1: c.tangent1,
+                tangent2: c.tangent2
+            });
+        }
+        self.joint_cache = joints.iter().cloned().collect();
+    }
+
+    fn prepare_constraints(&mut self,
+                           dt: f64,
+                           contacts: &[ContactPoint],
+                           rigid_bodies: &LinearComponentStorage<RigidBody>,
+                           materials: &LinearComponentStorage<PhysicsMaterial>)
+    {
+        self.bodies.clear();
+        self.body_indices.clear();
+        self.constraints.clear();
+
+        let inv_dt = if dt > 0.0 { 1.0 / dt } else { 0.0 };
+
+        for contact in contacts {
+            let body1 = body_index(&mut self.bodies, &mut self.body_indices, rigid_bodies, contact.entity1);
+            let body2 = body_index(&mut self.bodies, &mut self.body_indices, rigid_bodies, contact.entity2);
+
+            if let (Some(body1), Some(body2)) = (body1, body2) {
+                let (b1, b2) = (&self.bodies[body1], &self.bodies[body2]);
+                if b1.inv_mass == 0.0 && b2.inv_mass == 0.0 {
+                    // We don't handle static-static collisions
+                    continue;
+                }
+
+                let n = contact.normal;
+                let (t1, t2) = tangent_basis(&n);
+                let r1 = contact.point - b1.position;
+                let r2 = contact.point - b2.position;
+                let material = contact_material(materials, contact.entity1, contact.entity2);
+
+                let v_r = b2.velocity_at(&r2) - b1.velocity_at(&r1);
+                let v_separating = v_r.dot(&n);
+                let v_tangential = v_r - v_separating * n;
+
+                let restitution_bias = if v_separating < - self.restitution_threshold {
+                    - material.restitution * v_separating
+                } else {
+                    0.0
+                };
+                let position_bias = if contact.depth < 0.0 {
+                    // Allow the bodies to approach each other until they touch
+                    contact.depth * inv_dt
+                } else {
+                    self.baumgarte * inv_dt * (contact.depth - self.slop).max(0.0)
+                };
+
+                let friction = if norm(&v_tangential) < self.sliding_threshold {
+                    material.static_friction
+                } else {
+                    material.dynamic_friction
+                };
+
+                let mut constraint = ContactConstraint {
+                    body1: body1,
+                    body2: body2,
+                    point: contact.point,
+                    r1: r1,
+                    r2: r2,
+                    normal: n,
+                    tangent1: t1,
+                    tangent2: t2,
+                    normal_mass: effective_mass(b1, b2, &r1, &r2, &n),
+                    tangent_mass1: effective_mass(b1, b2, &r1, &r2, &t1),
+                    tangent_mass2: effective_mass(b1, b2, &r1, &r2, &t2),
+                    bias: restitution_bias.max(position_bias),
+                    friction: friction,
+                    normal_impulse: 0.0,
+                    tangent_impulse1: 0.0,
+                    tangent_impulse2: 0.0
+                };
+
+                if let Some(cached) = self.cached_impulse(contact) {
+                    constraint.normal_impulse = cached.normal;
+                    constraint.tangent_impulse1 = cached.tangent1;
+                    constraint.tangent_impulse2 = cached.tangent2;
+                }
+
+                self.constraints.push(constraint);
+            }
+        }
+    }
+
+    fn prepare_joints(&mut self,
+                      dt: f64,
+                      joints: &LinearComponentStorage<Joint>,
+                      rigid_bodies: &LinearComponentStorage<RigidBody>)
+    {
+        self.joint_rows.clear();
+        self.joint_ranges.clear();
+
+        let step = StepParameters {
+            dt: dt,
+            inv_dt: if dt > 0.0 { 1.0 / dt } else { 0.0 },
+            baumgarte: self.baumgarte
+        };
+
+        for &(ref joint, entity) in joints.components() {
+            let body1 = body_index(&mut self.bodies, &mut self.body_indices, rigid_bodies, joint.body1);
+            let body2 = body_index(&mut self.bodies, &mut self.body_indices, rigid_bodies, joint.body2);
+
+            if let (Some(body1), Some(body2)) = (body1, body2) {
+                if self.bodies[body1].inv_mass == 0.0 && self.bodies[body2].inv_mass == 0.0 {
+                    continue;
+                }
+
+                let start = self.joint_rows.len();
+                prepare_joint_rows(joint, body1, body2, &self.bodies, &step, &mut self.joint_rows);
+                let end = self.joint_rows.len();
+
+                // The rows of a joint only change along with its limits and motors
+                if let Some(cached) = self.joint_cache.get(&entity) {
+                    if cached.len() == end - start {
+                        for (row, &impulse) in self.joint_rows[start .. end].iter_mut().zip(cached) {
+                            row.impulse = impulse;
+                        }
+                    }
+                }
+                self.joint_ranges.push((entity, start, end));
+            }
+        }
+    }
+
+    /// Finds the impulse accumulated in the previous step for the contact between
+    /// the same pair of bodies that lies closest to the given contact, if any.
+    fn cached_impulse(&self, contact: &ContactPoint) -> Option<CachedImpulse> {
+        let max_dist2 = self.warm_start_distance * self.warm_start_distance;
+        self.cache.get(&(contact.entity1, contact.entity2))
+            .and_then(|cached| {
+                cached.iter()
+                      .map(|c| (norm_squared(&(c.point - contact.point)), c))
+                      .filter(|&(dist2, _)| dist2 <= max_dist2)
+                      .fold(None, |closest: Option<(f64, &CachedImpulse)>, (dist2, c)| {
+                          match closest {
+                              Some((closest_dist2, _)) if closest_dist2 <= dist2 => closest,
+                              _ => Some((dist2, c))
+                          }
+                      })
+                      .map(|(_, c)| c.clone())
+            })
+    }
+
+    fn warm_start(&mut self) {
+        let ContactSolver { ref mut bodies, ref constraints, ref joint_rows, .. } = *self;
+        for row in joint_rows {
+            row.apply_impulse(bodies, row.impulse);
+        }
+        for c in constraints {
+            let impulse = c.normal_impulse * c.normal
+                        + c.tangent_impulse1 * c.tangent1
+                        + c.tangent_impulse2 * c.tangent2;
+            c.apply_impulse(bodies, impulse);
+        }
+    }
+
+    fn solve_joints(&mut self) {
+        let ContactSolver { ref mut bodies, ref mut joint_rows, .. } = *self;
+        for row in joint_rows.iter_mut() {
+            row.solve(bodies);
+        }
+    }
+
+    fn solve_velocities(&mut self) {
+        let ContactSolver { ref mut bodies, ref mut constraints, .. } = *self;
+        for c in constraints.iter_mut() {
+            // Friction is solved first, since the non-penetration constraint
+            // is more important, and so should be solved last
+            let max_friction = c.friction * c.normal_impulse;
+
+            let v_r = c.relative_velocity(bodies);
+            let old_impulse = c.tangent_impulse1;
+            let delta = - c.tangent_mass1 * v_r.dot(&c.tangent1);
+            c.tangent_impulse1 = clamp(old_impulse + delta, - max_friction, max_friction);
+            c.apply_impulse(bodies, (c.tangent_impulse1 - old_impulse) * c.tangent1);
+
+            let v_r = c.relative_velocity(bodies);
+            let old_impulse = c.tangent_impulse2;
+            let delta = - c.tangent_mass2 * v_r.dot(&c.tangent2);
+            c.tangent_impulse2 = clamp(old_impulse + delta, - max_friction, max_friction);
+            c.apply_impulse(bodies, (c.tangent_impulse2 - old_impulse) * c.tangent2);
+
+            // The accumulated normal impulse must push the bodies apart
+            let v_r = c.relative_velocity(bodies);
+            let old_impulse = c.normal_impulse;
+            let delta = c.normal_mass * (c.bias - v_r.dot(&c.normal));
+            c.normal_impulse = (old_impulse + delta).max(0.0);
+            c.apply_impulse(bodies, (c.normal_impulse - old_impulse) * c.normal);
+        }
+    }
+
+    fn store_impulses(&mut self) {
+        self.cache.clear();
+        for c in &self.constraints {
+            let key = (self.bodies[c.body1].entity, self.bodies[c.body2].entity);
+            self.cache.entry(key).or_insert_with(Vec::new).push(CachedImpulse {
+                point: c.point,
+                normal: c.normal_impulse,
+                tangent1: c.tangent_impulse1,
+                tangent2: c.tangent_impulse2
+            });
+        }
+
+        self.joint_cache.clear();
+        for &(entity, start, end) in &self.joint_ranges {
+            let impulses = self.joint_rows[start .. end].iter().map(|row| row.impulse).collect();
+            self.joint_cache.insert(entity, impulses);
+        }
+    }
+
+    fn write_back(&self, rigid_bodies: &mut LinearComponentStorage<RigidBody>) {
+        for body in &self.bodies {
+            let rb = rigid_bodies.lookup_component_for_entity_mut(body.entity)
+                                 .and_then(|rb| rb.as_dynamic_mut());
+            if let Some(rb) = rb {
+                if !rb.sleeping {
+                    rb.state.velocity = body.velocity;
+                    rb.state.angular_momentum = body.angular_momentum;
+                }
+            }
+        }
+    }
+}
+
+fn body_index(bodies: &mut Vec<SolverBody>,
+              body_indices: &mut HashMap<Entity, usize>,
+              rigid_bodies: &LinearComponentStorage<RigidBody>,
+              entity: Entity) -> Option<usize>
+{
+    if let Some(index) = body_indices.get(&entity).cloned() {
+        return Some(index);
+    }
+
+    // At the moment we only allow collisions and joints between rigid bodies
+    rigid_bodies.lookup_component_for_entity(entity).map(|rb| {
+        let index = bodies.len();
+        bodies.push(SolverBody::from_rigid_body(entity, rb));
+        body_indices.insert(entity, index);
+        index
+    })
+}
+
+/// Combines the materials of the two entities in contact. Entities without
+/// a material component are assumed to have the default material.
+fn contact_material(materials: &LinearComponentStorage<PhysicsMaterial>,
+                    entity1: Entity,
+                    entity2: Entity)
+    -> ContactMaterial
+{
+    let default = PhysicsMaterial::default();
+    let material1 = materials.lookup_component_for_entity(entity1).unwrap_or(&default);
+    let material2 = materials.lookup_component_for_entity(entity2).unwrap_or(&default);
+    material1.combine(material2)
+}
+
+/// The inverse of the change in relative velocity along the direction `d`
+/// caused by a unit impulse along `d` at the contact point.
+fn effective_mass(b1: &SolverBody,
+                  b2: &SolverBody,
+                  r1: &Vector3<f64>,
+                  r2: &Vector3<f64>,
+                  d: &Vector3<f64>) -> f64 {
+    let angular1 = (b1.inv_inertia * r1.cross(d)).cross(r1);
+    let angular2 = (b2.inv_inertia * r2.cross(d)).cross(r2);
+    let k = b1.inv_mass + b2.inv_mass + (angular1 + angular2).dot(d);
+    if k > 0.0 { 1.0 / k } else { 0.0 }
+}
+
+/// Computes two unit tangents which together with the given unit normal form
+/// an orthonormal basis. The tangents depend only on the normal, so that
+/// accumulated friction impulses remain meaningful between steps.
+fn tangent_basis(n: &Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+    let t1 = if n.x.abs() > 0.57735 {
+        Vector3::new(n.y, - n.x, 0.0)
+    } else {
+        Vector3::new(0.0, n.z, - n.y)
+    };
+    let t1 = t1 / norm(&t1);
+    let t2 = n.cross(&t1);
+    (t1, t2)
+}
+
+fn clamp(value: f64, min: f64, max: f64) -> f64 {
+    value.max(min).min(max)
+}
+
+#[cfg(test)]
+mod tests {
+    use physics::solver_scene::{Scene, Shape};
+    use physics::{DynamicRigidBody, PhysicsMaterial};
+    use nalgebra::{zero, norm, Point3, Vector3};
+
+    fn incline_gravity(slope: f64) -> Vector3<f64> {
+        // Rather than tilting the ground, we tilt gravity
+        let angle = slope.atan();
+        9.81 * Vector3::new(angle.sin(), 0.0, - angle.cos())
+    }
+
+    /// Measures how far the body has tipped away from its initial orientation.
+    fn tilt(rb: &DynamicRigidBody) -> f64 {
+        let up = Vector3::new(0.0, 0.0, 1.0);
+        norm(&(rb.state.orientation * up - up))
+    }
+
+    fn contact_point_speed(rb: &DynamicRigidBody, contact_offset: Vector3<f64>) -> f64 {
+        let w = rb.inv_inertia_body * rb.state.angular_momentum;
+        norm(&(rb.state.velocity + w.cross(&contact_offset)))
+    }
+
+    #[test]
+    fn box_comes_to_rest_on_incline() {
+        let mut scene = Scene::new(incline_gravity(0.5)).with_material(PhysicsMaterial::new(0.0, 0.7, 0.6));
+        let cube = scene.add(Shape::Cube { half_size: 0.5 },
+                             Point3::new(0.0, 0.0, 0.5),
+                             Vector3::new(1.0, 0.0, 0.0));
+
+        // Sliding friction decelerates the box by g (μ cos θ - sin θ) ≈ 0.88 m/s²,
+        // so the box should stop after a little more than a second, after which
+        // static friction keeps it at rest
+        scene.simulate(1e-3, 1000);
+        assert!(scene.body(cube).state.velocity.x > 0.05);
+
+        scene.simulate(1e-3, 2000);
+        let rb = scene.body(cube);
+        assert!(norm(&rb.state.velocity) < 1e-3, "Velocity was {:?}", rb.state.velocity);
+        assert!(tilt(&rb) < 1e-2);
+    }
+
+    #[test]
+    fn box_slides_down_incline_steeper_than_friction_angle() {
+        let mut scene = Scene::new(incline_gravity(0.5)).with_material(PhysicsMaterial::new(0.0, 0.4, 0.3));
+        let cube = scene.add(Shape::Cube { half_size: 0.5 }, Point3::new(0.0, 0.0, 0.5), zero());
+
+        // The box should accelerate by g (sin θ - μ cos θ), without tipping over
+        scene.simulate(1e-3, 1000);
+        let rb = scene.body(cube);
+        let angle = 0.5f64.atan();
+        let expected_speed = 9.81 * (angle.sin() - 0.3 * angle.cos());
+        assert!((rb.state.velocity.x - expected_speed).abs() < 2e-2,
+            "Velocity was {:?}", rb.state.velocity);
+        assert!(tilt(&rb) < 1e-2);
+    }
+
+    #[test]
+    fn sliding_sphere_starts_rolling() {
+        let (r, mu, v0) = (0.5, 0.3, 2.0);
+        let mut scene = Scene::new(Vector3::new(0.0, 0.0, -9.81)).with_material(PhysicsMaterial::new(0.0, mu, mu));
+        let sphere = scene.add(Shape::Sphere { radius: r },
+                               Point3::new(0.0, 0.0, r),
+                               Vector3::new(v0, 0.0, 0.0));
+        let offset = Vector3::new(0.0, 0.0, -r);
+
+        // While sliding, friction decelerates the sphere by μ g, while its
+        // angular velocity increases until the sphere rolls without slipping
+        // at t = 2 v0 / (7 μ g) ≈ 0.19 s
+        scene.simulate(1e-3, 100);
+        let rb = scene.body(sphere);
+        assert!((rb.state.velocity.x - (v0 - mu * 9.81 * 0.1)).abs() < 1e-2);
+        assert!(contact_point_speed(&rb, offset) > 0.5);
+
+        // Once rolling, the sphere keeps 5/7 of its initial speed
+        scene.simulate(1e-3, 900);
+        let rb = scene.body(sphere);
+        assert!((rb.state.velocity.x - 5.0 / 7.0 * v0).abs() < 1e-2,
+            "Velocity was {:?}", rb.state.velocity);
+        assert!(contact_point_speed(&rb, offset) < 1e-6);
+    }
+
+    #[test]
+    fn restitution_determines_rebound_speed() {
+        let mut scene = Scene::new(zero()).with_material(PhysicsMaterial::new(0.5, 0.0, 0.0));
+        let sphere = scene.add(Shape::Sphere { radius: 0.5 },
+                               Point3::new(0.0, 0.0, 0.5),
+                               Vector3::new(0.0, 0.0, -2.0));
+        scene.step(1e-3);
+        let v = scene.body(sphere).state.velocity;
+        assert!(norm(&(v - Vector3::new(0.0, 0.0, 1.0))) < 1e-12, "Velocity was {:?}", v);
+    }
+
+    #[test]
+    fn stack_of_boxes_remains_at_rest() {
+        let mut scene = Scene::new(Vector3::new(0.0, 0.0, -9.81)).with_material(PhysicsMaterial::new(0.0, 0.6, 0.5));
+        let cubes: Vec<_> = (0 .. 5)
+            .map(|i| {
+                let position = Point3::new(0.0, 0.0, 0.5 + i as f64);
+                scene.add(Shape::Cube { half_size: 0.5 }, position, zero())
+            })
+            .collect();
+
+        scene.simulate(1.0 / 200.0, 1000);
+
+        for (i, &cube) in cubes.iter().enumerate() {
+            let rb = scene.body(cube);
+            let initial_position = Point3::new(0.0, 0.0, 0.5 + i as f64);
+            assert!(norm(&(rb.state.position - initial_position)) < 2e-2,
+                "Position of cube {} was {:?}", i, rb.state.position);
+            assert!(norm(&rb.state.velocity) < 1e-2);
+            assert!(tilt(&rb) < 1e-3);
+        }
+    }
+
+    #[test]
+    fn warm_starting_alone_keeps_resting_box_at_rest() {
+        let mut scene = Scene::new(Vector3::new(0.0, 0.0, -9.81)).with_material(PhysicsMaterial::new(0.0, 0.6, 0.5));
+        let cube = scene.add(Shape::Cube { half_size: 0.5 }, Point3::new(0.0, 0.0, 0.5), zero());
+        scene.simulate(1.0 / 200.0, 200);
+
+        // Once the contact impulses have converged, the impulses from the previous
+        // step should by themselves exactly cancel the effect of gravity
+        scene.solver.iterations = 0;
+        scene.simulate(1.0 / 200.0, 10);
+        let rb = scene.body(cube);
+        assert!(norm(&rb.state.velocity) < 1e-6, "Velocity was {:?}", rb.state.velocity);
+    }
+}
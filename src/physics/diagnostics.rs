@@ -0,0 +1,249 @@
+use physics::{RigidBody, ForceGenerator, ScopedForceGenerator};
+use physics::force_generator::GeneratorBodies;
+use physics::gravity::pairwise_potential_energy;
+use physics::angular_integrator::world_inverse_inertia;
+use entity::LinearComponentStorage;
+use nalgebra::{zero, norm, Vector3};
+use std::collections::HashMap;
+
+/// Conserved quantities of the dynamic bodies, as measured after a simulation step.
+/// Static and kinematic bodies are not included.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Diagnostics {
+    pub linear_momentum: Vector3<f64>,
+
+    /// Angular momentum about the origin, including the spin of each body about its center of mass
+    pub angular_momentum: Vector3<f64>,
+
+    pub translational_kinetic_energy: f64,
+    pub rotational_kinetic_energy: f64,
+
+    /// Potential energy of the bodies in uniform acceleration fields, relative to the origin,
+    /// and under mutual gravitation. Other force generators do not contribute.
+    pub potential_energy: f64,
+
+    pub drift: Drift
+}
+
+/// Changes in the conserved quantities over the last step, and since the first report.
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Drift {
+    pub step_energy: f64,
+    pub step_linear_momentum: f64,
+    pub step_angular_momentum: f64,
+
+    pub energy: f64,
+    pub linear_momentum: f64,
+    pub angular_momentum: f64,
+
+    /// The largest change in total energy over a single step since the first report
+    pub max_step_energy: f64,
+
+    /// The total energy at the first report
+    pub initial_energy: f64
+}
+
+impl Diagnostics {
+    /// Measures the conserved quantities of the given bodies, with no drift.
+    pub fn measure(rigid_bodies: &LinearComponentStorage<RigidBody>,
+                   force_generators: &LinearComponentStorage<ScopedForceGenerator>) -> Diagnostics
+    {
+        let mut entities = Vec::new();
+        let mut x = Vec::new();
+        let mut v = Vec::new();
+        let mut m = Vec::new();
+        let mut q = Vec::new();
+        let mut w = Vec::new();
+
+        let mut linear_momentum: Vector3<f64> = zero();
+        let mut angular_momentum: Vector3<f64> = zero();
+        let mut translational_kinetic_energy = 0.0;
+        let mut rotational_kinetic_energy = 0.0;
+
+        // Sleeping bodies are at rest, but still contribute potential energy
+        let dynamic_iter = rigid_bodies.components()
+                                .iter()
+                                .filter_map(|&(ref rb, entity)| rb.as_dynamic().map(|rb| (rb, entity)));
+
+        for (rb, entity) in dynamic_iter {
+            let mass = rb.mass.value();
+            let momentum = mass * rb.state.velocity;
+            let spin = rb.state.angular_momentum;
+            let angular_velocity = world_inverse_inertia(&rb.inv_inertia_body, rb.state.orientation) * spin;
+
+            linear_momentum += momentum;
+            angular_momentum += rb.state.position.coords.cross(&momentum) + spin;
+            translational_kinetic_energy += 0.5 * momentum.dot(&rb.state.velocity);
+            rotational_kinetic_energy += 0.5 * angular_velocity.dot(&spin);
+
+            entities.push(entity);
+            x.push(rb.state.position);
+            v.push(rb.state.velocity);
+            m.push(mass);
+            q.push(rb.state.orientation);
+            w.push(angular_velocity);
+        }
+
+        let indices = entities.iter().enumerate().map(|(i, &entity)| (entity, i)).collect();
+        let others = HashMap::new();
+
+        // Buoyancy has no potential energy here, so the volumes are not needed
+        let volumes = vec![0.0; entities.len()];
+        let bodies = GeneratorBodies {
+            entities: &entities,
+            x: &x,
+            v: &v,
+            orientations: &q,
+            angular_velocities: &w,
+            volumes: &volumes,
+            indices: &indices,
+            others: &others
+        };
+
+        Diagnostics {
+            linear_momentum: linear_momentum,
+            angular_momentum: angular_momentum,
+            translational_kinetic_energy: translational_kinetic_energy,
+            rotational_kinetic_energy: rotational_kinetic_energy,
+            potential_energy: potential_energy(&bodies, &m, force_generators),
+            drift: Drift::none()
+        }
+    }
+
+    pub fn kinetic_energy(&self) -> f64 {
+        self.translational_kinetic_energy + self.rotational_kinetic_energy
+    }
+
+    pub fn total_energy(&self) -> f64 {
+        self.kinetic_energy() + self.potential_energy
+    }
+
+    /// Fills in the drift of the report with respect to the report
+    /// of the previous step and the first report.
+    pub fn with_drift_from(mut self, previous: &Diagnostics, first: &Diagnostics) -> Diagnostics {
+        let step_energy = self.total_energy() - previous.total_energy();
+        self.drift = Drift {
+            step_energy: step_energy,
+            step_linear_momentum: norm(&(self.linear_momentum - previous.linear_momentum)),
+            step_angular_momentum: norm(&(self.angular_momentum - previous.angular_momentum)),
+            energy: self.total_energy() - first.total_energy(),
+            linear_momentum: norm(&(self.linear_momentum - first.linear_momentum)),
+            angular_momentum: norm(&(self.angular_momentum - first.angular_momentum)),
+            max_step_energy: previous.drift.max_step_energy.max(step_energy.abs()),
+            initial_energy: first.total_energy()
+        };
+        self
+    }
+}
+
+impl Drift {
+    fn none() -> Drift {
+        Drift {
+            step_energy: 0.0,
+            step_linear_momentum: 0.0,
+            step_angular_momentum: 0.0,
+            energy: 0.0,
+            linear_momentum: 0.0,
+            angular_momentum: 0.0,
+            max_step_energy: 0.0,
+            initial_energy: 0.0
+        }
+    }
+
+    /// The change in total energy since the first report relative to the initial energy,
+    /// or `None` if the initial energy is zero.
+    #[allow(dead_code)]
+    pub fn relative_energy(&self) -> Option<f64> {
+        if self.initial_energy != 0.0 {
+            Some(self.energy / self.initial_energy.abs())
+        } else {
+            None
+        }
+    }
+}
+
+fn potential_energy(bodies: &GeneratorBodies,
+                    m: &[f64],
+                    force_generators: &LinearComponentStorage<ScopedForceGenerator>) -> f64
+{
+    let num_objects = m.len();
+    let mut energy = 0.0;
+    for &(ref force, owner) in force_generators.components() {
+        let in_scope: Vec<usize> = (0 .. num_objects)
+            .filter(|&i| force.scope.contains(owner, bodies, i))
+            .collect();
+
+        match force.generator {
+            ForceGenerator::UniformAccelerationField { acceleration } => {
+                for &i in &in_scope {
+                    energy -= m[i] * acceleration.dot(&bodies.x[i].coords);
+                }
+            },
+            ForceGenerator::MutualGravitation { g, softening, .. } => {
+                // Always summed exactly, even if the force is approximated
+                let x: Vec<_> = in_scope.iter().map(|&i| bodies.x[i]).collect();
+                let m: Vec<_> = in_scope.iter().map(|&i| m[i]).collect();
+                energy += pairwise_potential_energy(g, softening, &x, &m);
+            },
+            _ => {}
+        }
+    }
+    energy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Diagnostics;
+    use physics::{RigidBody, DynamicRigidBody, DynamicBodyState, Mass, ForceGenerator,
+        ScopedForceGenerator, GravitationMethod};
+    use entity::{EntityManager, LinearComponentStorage};
+    use nalgebra::{Point3, Vector3, Matrix3};
+
+    #[test]
+    fn measures_momentum_and_energy_of_dynamic_bodies() {
+        let mut entity_manager = EntityManager::new();
+        let mut bodies = LinearComponentStorage::new();
+        let mut generators = LinearComponentStorage::new();
+
+        let body = |position: Point3<f64>, velocity: Vector3<f64>, angular_momentum: Vector3<f64>, mass: f64| {
+            RigidBody::Dynamic(DynamicRigidBody {
+                state: DynamicBodyState {
+                    position: position,
+                    velocity: velocity,
+                    angular_momentum: angular_momentum,
+                    .. DynamicBodyState::default()
+                },
+                mass: Mass::new(mass),
+                inv_inertia_body: Matrix3::identity() * 0.5,
+                .. DynamicRigidBody::default()
+            })
+        };
+        bodies.set_component_for_entity(entity_manager.create(),
+            body(Point3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 2.0, 0.0), Vector3::new(0.0, 0.0, 4.0), 1.0));
+        bodies.set_component_for_entity(entity_manager.create(),
+            body(Point3::new(-1.0, 0.0, 0.0), Vector3::new(0.0, -1.0, 0.0), Vector3::new(0.0, 0.0, 0.0), 2.0));
+
+        generators.set_component_for_entity(entity_manager.create(), ForceGenerator::UniformAccelerationField {
+            acceleration: Vector3::new(-3.0, 0.0, 0.0)
+        }.global());
+        generators.set_component_for_entity(entity_manager.create(), ForceGenerator::MutualGravitation {
+            g: 1.0,
+            softening: 0.0,
+            method: GravitationMethod::BarnesHut { theta: 0.5 }
+        }.global());
+        let generators: LinearComponentStorage<ScopedForceGenerator> = generators;
+
+        let diagnostics = Diagnostics::measure(&bodies, &generators);
+        assert_eq!(Vector3::new(0.0, 0.0, 0.0), diagnostics.linear_momentum);
+
+        // Orbital angular momentum 1 * 2 + 2 * 1, plus the spin of the first body
+        assert_eq!(Vector3::new(0.0, 0.0, 8.0), diagnostics.angular_momentum);
+        assert_eq!(0.5 * 4.0 + 0.5 * 2.0, diagnostics.translational_kinetic_energy);
+        assert_eq!(0.5 * 2.0 * 4.0, diagnostics.rotational_kinetic_energy);
+
+        // The field contributes 3 * 1 - 3 * 2, and the pair - 1 * 2 / 2
+        assert_eq!(-3.0 - 1.0, diagnostics.potential_energy);
+        assert_eq!(3.0 + 4.0 - 4.0, diagnostics.total_energy());
+    }
+}
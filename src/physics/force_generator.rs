@@ -1,7 +1,485 @@
-use nalgebra::Vector3;
+use nalgebra::{norm, Point3, Vector3, UnitQuaternion};
+use geometry::{Sphere, Cuboid, Plane};
+use entity::Entity;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::rc::Rc;
 
-pub enum ForceGenerator {
+/// A source of forces which act on the dynamic bodies within its scope. Generators
+/// which act on particular bodies refer to them by `B`, which is an entity once the
+/// generator is part of a scene.
+pub enum ForceGenerator<B = Entity> {
     UniformAccelerationField {
         acceleration: Vector3<f64>
     },
+
+    /// Newtonian gravitational attraction between every pair of bodies in scope,
+    /// following the inverse-square law with gravitational constant `g`.
+    ///
+    /// A non-zero `softening` length replaces the squared distance r² by
+    /// r² + ε², which keeps the force bounded for close encounters.
+    /// A softening of zero gives the exact inverse-square law.
+    MutualGravitation {
+        g: f64,
+        softening: f64,
+        method: GravitationMethod
+    },
+
+    /// Drag opposing the velocity of the bodies, with magnitude
+    /// `linear` |v| + `quadratic` |v|². Linear drag dominates for slow bodies
+    /// in viscous fluids, while quadratic drag dominates for fast bodies in air.
+    Drag {
+        linear: f64,
+        quadratic: f64
+    },
+
+    /// A damped spring between anchor points, given in the local coordinates of the
+    /// two bodies, which may be static or kinematic. The spring pulls or pushes the
+    /// anchors towards its rest length with the given stiffness, and the damping
+    /// opposes the relative velocity of the anchors along the spring.
+    Spring {
+        body1: B,
+        anchor1: Point3<f64>,
+        body2: B,
+        anchor2: Point3<f64>,
+        rest_length: f64,
+        stiffness: f64,
+        damping: f64
+    },
+
+    /// An acceleration towards a fixed point, whose magnitude is `strength` scaled
+    /// by the falloff with distance. A negative strength repels bodies instead.
+    PointAttractor {
+        center: Point3<f64>,
+        strength: f64,
+        falloff: Falloff
+    },
+
+    /// Quadratic drag relative to moving air, whose velocity varies with position.
+    /// Bodies at rest are pushed along with the wind, and bodies moving with the
+    /// wind feel no force at all.
+    Wind {
+        velocity: Rc<Fn(Point3<f64>) -> Vector3<f64>>,
+        coefficient: f64
+    },
+
+    /// A force which is fixed to the body, such as the thrust of an engine. The force
+    /// and its point of application are given in the local coordinates of the body.
+    /// This is usually scoped to the entity of the body it propels.
+    Thrust {
+        force: Vector3<f64>,
+        point: Point3<f64>
+    },
+
+    /// The buoyancy of bodies in a fluid which fills the space behind the plane
+    /// of its surface. Each body is approximated by a sphere with the volume of its
+    /// collision model, so bodies without one do not float. The buoyant force acts
+    /// at the center of buoyancy, given in the local coordinates of the body, which
+    /// turns the body upright if it lies above the center of mass.
+    Buoyancy {
+        center_of_buoyancy: Point3<f64>,
+        surface: Plane<f64>,
+        fluid_density: f64,
+        gravity: Vector3<f64>
+    }
+}
+
+/// Determines which dynamic bodies a force generator acts on.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ForceScope<B = Entity> {
+    /// Every dynamic body in the scene.
+    Global,
+
+    /// Only the body of the entity which the generator is attached to.
+    OwnEntity,
+
+    /// Only the given bodies.
+    Bodies(Vec<B>),
+
+    /// Only the bodies whose center of mass lies inside the region.
+    Region(Region)
+}
+
+/// A region of space, which limits a force generator to the bodies inside it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Region {
+    Sphere(Sphere<f64>),
+    Cuboid(Cuboid<f64>),
+
+    /// Everything behind the plane, as seen from the direction of its normal.
+    HalfSpace(Plane<f64>)
+}
+
+/// A force generator together with the bodies it acts on. This is the component
+/// which attaches a generator to an entity.
+pub struct ScopedForceGenerator<B = Entity> {
+    pub generator: ForceGenerator<B>,
+    pub scope: ForceScope<B>
+}
+
+/// Determines how the strength of a point attractor varies with distance.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Falloff {
+    /// The same strength at every distance.
+    Constant,
+
+    /// The strength decreases linearly to zero at the given radius,
+    /// beyond which the attractor has no effect.
+    Linear {
+        radius: f64
+    },
+
+    /// The strength is divided by the squared distance, which is softened
+    /// as for mutual gravitation.
+    InverseSquare {
+        softening: f64
+    }
+}
+
+/// Determines how the mutual gravitational attraction between bodies is computed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GravitationMethod {
+    /// Direct summation over all pairs of bodies. Exact, but O(n²).
+    Exact,
+
+    /// The Barnes–Hut approximation, which is O(n log n). Distant groups of bodies
+    /// are replaced by their center of mass whenever the ratio between the size of
+    /// the group's octree cell and its distance is less than the opening angle `theta`.
+    /// Smaller values of `theta` are more accurate, and `theta = 0` reduces to
+    /// direct summation.
+    BarnesHut {
+        theta: f64
+    }
+}
+
+/// The pose and velocities of a body which is not being integrated,
+/// such as a static, kinematic or sleeping body.
+#[derive(Copy, Clone, Debug)]
+pub struct BodyFrame {
+    pub position: Point3<f64>,
+    pub orientation: UnitQuaternion<f64>,
+    pub velocity: Vector3<f64>,
+    pub angular_velocity: Vector3<f64>
+}
+
+/// The bodies that force generators act on during a step. The dynamic bodies being
+/// integrated are indexed as in the buffers of the integrator, and their orientations
+/// and angular velocities are held fixed over the step.
+pub struct GeneratorBodies<'a> {
+    pub entities: &'a [Entity],
+    pub x: &'a [Point3<f64>],
+    pub v: &'a [Vector3<f64>],
+    pub orientations: &'a [UnitQuaternion<f64>],
+    pub angular_velocities: &'a [Vector3<f64>],
+
+    /// The volumes of the collision models of the bodies, or zero where unknown.
+    pub volumes: &'a [f64],
+
+    pub indices: &'a HashMap<Entity, usize>,
+    pub others: &'a HashMap<Entity, BodyFrame>
+}
+
+impl<'a> GeneratorBodies<'a> {
+    /// The index of the body in the integrator buffers, if it is being
+    /// integrated, along with its current pose and velocities.
+    fn frame(&self, entity: Entity) -> Option<(Option<usize>, BodyFrame)> {
+        match self.indices.get(&entity) {
+            Some(&i) => Some((Some(i), BodyFrame {
+                position: self.x[i],
+                orientation: self.orientations[i],
+                velocity: self.v[i],
+                angular_velocity: self.angular_velocities[i]
+            })),
+            None => self.others.get(&entity).map(|&frame| (None, frame))
+        }
+    }
+}
+
+impl BodyFrame {
+    /// The world position and velocity of a point given in local coordinates.
+    fn point(&self, local: &Point3<f64>) -> (Point3<f64>, Vector3<f64>) {
+        let r = self.orientation * local.coords;
+        (self.position + r, self.velocity + self.angular_velocity.cross(&r))
+    }
+}
+
+impl<B> ForceGenerator<B> {
+    /// Makes the generator act on every dynamic body in the scene.
+    pub fn global(self) -> ScopedForceGenerator<B> {
+        self.scoped(ForceScope::Global)
+    }
+
+    /// Makes the generator act only on the bodies in the given scope.
+    pub fn scoped(self, scope: ForceScope<B>) -> ScopedForceGenerator<B> {
+        ScopedForceGenerator {
+            generator: self,
+            scope: scope
+        }
+    }
+
+    /// Replaces the references to bodies, for example to turn blueprint indices into entities.
+    pub fn map_bodies<C, F>(&self, f: F) -> ForceGenerator<C> where F: Fn(&B) -> C {
+        match self {
+            &ForceGenerator::UniformAccelerationField { acceleration } =>
+                ForceGenerator::UniformAccelerationField { acceleration: acceleration },
+            &ForceGenerator::MutualGravitation { g, softening, method } =>
+                ForceGenerator::MutualGravitation { g: g, softening: softening, method: method },
+            &ForceGenerator::Drag { linear, quadratic } =>
+                ForceGenerator::Drag { linear: linear, quadratic: quadratic },
+            &ForceGenerator::Spring { ref body1, anchor1, ref body2, anchor2, rest_length, stiffness, damping } =>
+                ForceGenerator::Spring {
+                    body1: f(body1),
+                    anchor1: anchor1,
+                    body2: f(body2),
+                    anchor2: anchor2,
+                    rest_length: rest_length,
+                    stiffness: stiffness,
+                    damping: damping
+                },
+            &ForceGenerator::PointAttractor { center, strength, falloff } =>
+                ForceGenerator::PointAttractor { center: center, strength: strength, falloff: falloff },
+            &ForceGenerator::Wind { ref velocity, coefficient } =>
+                ForceGenerator::Wind { velocity: velocity.clone(), coefficient: coefficient },
+            &ForceGenerator::Thrust { force, point } =>
+                ForceGenerator::Thrust { force: force, point: point },
+            &ForceGenerator::Buoyancy { center_of_buoyancy, surface, fluid_density, gravity } =>
+                ForceGenerator::Buoyancy {
+                    center_of_buoyancy: center_of_buoyancy,
+                    surface: surface,
+                    fluid_density: fluid_density,
+                    gravity: gravity
+                }
+        }
+    }
+}
+
+impl<B> ScopedForceGenerator<B> {
+    /// Replaces the references to bodies, for example to turn blueprint indices into entities.
+    pub fn map_bodies<C, F>(&self, f: F) -> ScopedForceGenerator<C> where F: Fn(&B) -> C {
+        let scope = match self.scope {
+            ForceScope::Global => ForceScope::Global,
+            ForceScope::OwnEntity => ForceScope::OwnEntity,
+            ForceScope::Bodies(ref bodies) => ForceScope::Bodies(bodies.iter().map(&f).collect()),
+            ForceScope::Region(region) => ForceScope::Region(region)
+        };
+        ScopedForceGenerator {
+            generator: self.generator.map_bodies(f),
+            scope: scope
+        }
+    }
+}
+
+impl ForceScope {
+    /// Whether the integrated body with the given index is in the scope
+    /// of a generator which is attached to the `owner` entity.
+    pub fn contains(&self, owner: Entity, bodies: &GeneratorBodies, i: usize) -> bool {
+        match self {
+            &ForceScope::Global => true,
+            &ForceScope::OwnEntity => bodies.entities[i] == owner,
+            &ForceScope::Bodies(ref entities) => entities.contains(&bodies.entities[i]),
+            &ForceScope::Region(ref region) => region.contains(&bodies.x[i])
+        }
+    }
+}
+
+impl Region {
+    pub fn contains(&self, point: &Point3<f64>) -> bool {
+        match self {
+            &Region::Sphere(ref sphere) => norm(&(point - sphere.center)) <= sphere.radius,
+            &Region::Cuboid(ref cuboid) => {
+                let local = cuboid.rotation.inverse() * (point - cuboid.center);
+                local.x.abs() <= cuboid.half_size.x
+                    && local.y.abs() <= cuboid.half_size.y
+                    && local.z.abs() <= cuboid.half_size.z
+            },
+            &Region::HalfSpace(ref plane) => (point - plane.point).dot(&plane.normal) <= 0.0
+        }
+    }
+}
+
+impl ForceGenerator {
+    /// The pair of bodies connected by the generator, if any. Connected bodies
+    /// belong to the same simulation island.
+    pub fn connected_bodies(&self) -> Option<(Entity, Entity)> {
+        match self {
+            &ForceGenerator::Spring { body1, body2, .. } => Some((body1, body2)),
+            _ => None
+        }
+    }
+
+    /// Whether the generator pushes bodies at rest in a way that nothing in the scene
+    /// is expected to balance, so that sleeping bodies it pushes must be woken. Fields,
+    /// attractors, springs and buoyancy are left out, since resting bodies are commonly
+    /// held in equilibrium against them, and drag vanishes for bodies at rest.
+    pub fn disturbs_resting_bodies(&self) -> bool {
+        match self {
+            &ForceGenerator::Wind { .. } | &ForceGenerator::Thrust { .. } => true,
+            _ => false
+        }
+    }
+
+    /// Calls `apply` with the index, force and world point of application for every force
+    /// that the generator exerts on the integrated bodies. Generators which are better
+    /// expressed as accelerations, such as gravity, are not handled here.
+    pub fn for_each_force<F>(&self, bodies: &GeneratorBodies, mut apply: F)
+        where F: FnMut(usize, Vector3<f64>, Point3<f64>)
+    {
+        match self {
+            &ForceGenerator::Drag { linear, quadratic } => {
+                for (i, (x, v)) in bodies.x.iter().zip(bodies.v).enumerate() {
+                    apply(i, drag_force(linear, quadratic, v), *x);
+                }
+            },
+            &ForceGenerator::Wind { ref velocity, coefficient } => {
+                for (i, (x, v)) in bodies.x.iter().zip(bodies.v).enumerate() {
+                    let relative_velocity = v - velocity(*x);
+                    apply(i, drag_force(0.0, coefficient, &relative_velocity), *x);
+                }
+            },
+            &ForceGenerator::Thrust { force, point } => {
+                for (i, (x, q)) in bodies.x.iter().zip(bodies.orientations).enumerate() {
+                    apply(i, q * force, x + q * point.coords);
+                }
+            },
+            &ForceGenerator::Spring { body1, anchor1, body2, anchor2, rest_length, stiffness, damping } => {
+                if let (Some((i1, frame1)), Some((i2, frame2))) = (bodies.frame(body1), bodies.frame(body2)) {
+                    let (p1, v1) = frame1.point(&anchor1);
+                    let (p2, v2) = frame2.point(&anchor2);
+                    let force = spring_force(rest_length, stiffness, damping, p1, v1, p2, v2);
+                    if let Some(i1) = i1 {
+                        apply(i1, force, p1);
+                    }
+                    if let Some(i2) = i2 {
+                        apply(i2, -force, p2);
+                    }
+                }
+            },
+            &ForceGenerator::Buoyancy { center_of_buoyancy, surface, fluid_density, gravity } => {
+                for (i, (x, q)) in bodies.x.iter().zip(bodies.orientations).enumerate() {
+                    let volume = bodies.volumes[i];
+                    if volume > 0.0 {
+                        let radius = (3.0 * volume / (4.0 * PI)).cbrt();
+                        let point = x + q * center_of_buoyancy.coords;
+                        let depth = (surface.point - point).dot(&surface.normal);
+                        let submerged_volume = submerged_fraction(radius, depth) * volume;
+                        apply(i, - fluid_density * submerged_volume * gravity, point);
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+impl Falloff {
+    /// The factor by which the strength of an attractor is scaled at the given distance.
+    pub fn factor(&self, distance: f64) -> f64 {
+        match *self {
+            Falloff::Constant => 1.0,
+            Falloff::Linear { radius } => (1.0 - distance / radius).max(0.0),
+            Falloff::InverseSquare { softening } => 1.0 / (distance * distance + softening * softening)
+        }
+    }
+}
+
+/// The acceleration of a body at `x` due to a point attractor.
+pub fn attractor_acceleration(center: Point3<f64>, strength: f64, falloff: Falloff, x: Point3<f64>)
+    -> Vector3<f64>
+{
+    let r = center - x;
+    let distance = norm(&r);
+    if distance > 0.0 {
+        (strength * falloff.factor(distance) / distance) * r
+    } else {
+        Vector3::new(0.0, 0.0, 0.0)
+    }
+}
+
+/// The drag force on a body moving with the given velocity relative to the fluid.
+pub fn drag_force(linear: f64, quadratic: f64, velocity: &Vector3<f64>) -> Vector3<f64> {
+    - (linear + quadratic * norm(velocity)) * velocity
+}
+
+/// The force exerted by a damped spring on its first end point, which is at
+/// `p1` with velocity `v1`. The force on the second end point is the opposite.
+pub fn spring_force(rest_length: f64, stiffness: f64, damping: f64,
+                    p1: Point3<f64>, v1: Vector3<f64>,
+                    p2: Point3<f64>, v2: Vector3<f64>) -> Vector3<f64>
+{
+    let d = p2 - p1;
+    let length = norm(&d);
+    if length == 0.0 {
+        // The direction of the spring is undefined
+        return Vector3::new(0.0, 0.0, 0.0);
+    }
+    let direction = d / length;
+    let extension_rate = (v2 - v1).dot(&direction);
+    (stiffness * (length - rest_length) + damping * extension_rate) * direction
+}
+
+/// The fraction of the volume of a sphere which is submerged, when its center
+/// lies at the given depth below the surface of the fluid.
+pub fn submerged_fraction(radius: f64, depth: f64) -> f64 {
+    // The submerged part is a spherical cap of height h
+    let h = (radius + depth).max(0.0).min(2.0 * radius);
+    let cap_volume = PI * h * h * (3.0 * radius - h) / 3.0;
+    cap_volume / (4.0 / 3.0 * PI * radius * radius * radius)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Falloff, attractor_acceleration, drag_force, spring_force, submerged_fraction};
+    use nalgebra::{norm, Point3, Vector3};
+
+    #[test]
+    fn submerged_fraction_of_sphere() {
+        assert_eq!(0.0, submerged_fraction(1.0, -1.5));
+        assert_eq!(0.0, submerged_fraction(1.0, -1.0));
+        assert!((submerged_fraction(1.0, 0.0) - 0.5).abs() < 1e-12);
+        assert_eq!(1.0, submerged_fraction(1.0, 1.0));
+        assert_eq!(1.0, submerged_fraction(1.0, 3.0));
+
+        // Submerging the lower and upper halves by the same amount is symmetric
+        let lower = submerged_fraction(2.0, -1.0);
+        let upper = submerged_fraction(2.0, 1.0);
+        assert!((lower + upper - 1.0).abs() < 1e-12);
+        assert!((lower - 5.0 / 32.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn spring_force_follows_hookes_law_with_damping() {
+        let origin = Point3::origin();
+        let at_rest = Vector3::new(0.0, 0.0, 0.0);
+
+        // A stretched spring pulls the first end towards the second
+        let stretched = spring_force(1.0, 10.0, 0.0, origin, at_rest, Point3::new(3.0, 0.0, 0.0), at_rest);
+        assert!(norm(&(stretched - Vector3::new(20.0, 0.0, 0.0))) < 1e-12);
+
+        // A compressed spring pushes it away
+        let compressed = spring_force(1.0, 10.0, 0.0, origin, at_rest, Point3::new(0.0, 0.5, 0.0), at_rest);
+        assert!(norm(&(compressed - Vector3::new(0.0, -5.0, 0.0))) < 1e-12);
+
+        // Damping only opposes the rate of extension, not motion across the spring
+        let p2 = Point3::new(1.0, 0.0, 0.0);
+        let separating = spring_force(1.0, 10.0, 2.0, origin, at_rest, p2, Vector3::new(3.0, 5.0, 0.0));
+        assert!(norm(&(separating - Vector3::new(6.0, 0.0, 0.0))) < 1e-12);
+    }
+
+    #[test]
+    fn drag_and_attractors_have_expected_magnitudes() {
+        let v = Vector3::new(0.0, 3.0, 4.0);
+        assert!(norm(&(drag_force(2.0, 0.0, &v) + 2.0 * v)) < 1e-12);
+        assert!(norm(&(drag_force(0.0, 2.0, &v) + 10.0 * v)) < 1e-12);
+
+        let center = Point3::new(1.0, 0.0, 0.0);
+        let x = Point3::new(3.0, 0.0, 0.0);
+        let a = attractor_acceleration(center, 4.0, Falloff::Constant, x);
+        assert!(norm(&(a - Vector3::new(-4.0, 0.0, 0.0))) < 1e-12);
+        let a = attractor_acceleration(center, 4.0, Falloff::InverseSquare { softening: 0.0 }, x);
+        assert!(norm(&(a - Vector3::new(-1.0, 0.0, 0.0))) < 1e-12);
+        let a = attractor_acceleration(center, -4.0, Falloff::Linear { radius: 4.0 }, x);
+        assert!(norm(&(a - Vector3::new(2.0, 0.0, 0.0))) < 1e-12);
+        let a = attractor_acceleration(center, 4.0, Falloff::Linear { radius: 1.0 }, x);
+        assert_eq!(Vector3::new(0.0, 0.0, 0.0), a);
+    }
 }
@@ -0,0 +1,63 @@
+use nalgebra::{norm_squared, Point3, Vector3};
+
+/// Accumulates the gravitational acceleration of every body due to every other body
+/// into `a`, by direct summation over all pairs.
+///
+/// With r denoting the vector from body i to body j, the acceleration of body i is
+/// given by g * m_j * r / (|r|² + ε²)^(3/2), where ε is the softening length.
+/// Without softening, coincident bodies have no direction to attract each other in,
+/// so they are left out.
+pub fn accumulate_pairwise_gravitation(g: f64,
+                                       softening: f64,
+                                       x: &[Point3<f64>],
+                                       m: &[f64],
+                                       a: &mut [Vector3<f64>])
+{
+    assert!(x.len() == m.len() && m.len() == a.len());
+    assert!(softening >= 0.0);
+
+    let num_objects = x.len();
+    let eps2 = softening * softening;
+
+    for i in 0 .. num_objects {
+        for j in (i + 1) .. num_objects {
+            let r = x[j] - x[i];
+            let r2 = norm_squared(&r) + eps2;
+            if r2 == 0.0 {
+                continue;
+            }
+
+            // g / |r|^3 times r gives the inverse-square law along the unit direction
+            let scale = g / (r2 * r2.sqrt());
+            a[i] += (scale * m[j]) * r;
+            a[j] -= (scale * m[i]) * r;
+        }
+    }
+}
+
+/// Computes the gravitational potential energy of the bodies by direct summation
+/// over all pairs, consistent with the softened force of `accumulate_pairwise_gravitation`,
+/// so that each pair contributes - g * m_i * m_j / (|r|² + ε²)^(1/2). Like the force,
+/// coincident bodies without softening are left out.
+pub fn pairwise_potential_energy(g: f64,
+                                 softening: f64,
+                                 x: &[Point3<f64>],
+                                 m: &[f64]) -> f64
+{
+    assert!(x.len() == m.len());
+    assert!(softening >= 0.0);
+
+    let num_objects = x.len();
+    let eps2 = softening * softening;
+
+    let mut energy = 0.0;
+    for i in 0 .. num_objects {
+        for j in (i + 1) .. num_objects {
+            let r2 = norm_squared(&(x[j] - x[i])) + eps2;
+            if r2 > 0.0 {
+                energy -= g * m[i] * m[j] / r2.sqrt();
+            }
+        }
+    }
+    energy
+}
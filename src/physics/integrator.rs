@@ -0,0 +1,310 @@
+use nalgebra::{Point3, Vector3};
+
+/// Computes the accelerations of all bodies, given their positions and velocities.
+/// The accelerations must be written to the output slice, overwriting its contents.
+pub type AccelerationFunction<'a> =
+    FnMut(&[Point3<f64>], &[Vector3<f64>], &mut [Vector3<f64>]) + 'a;
+
+/// A time integrator for the linear motion of a system of bodies.
+pub trait Integrator {
+    /// Advances the positions `x` and velocities `v` by a single step `dt`.
+    ///
+    /// On entry, `a` holds the accelerations at the current state. On exit, `a` must
+    /// hold the accelerations at the new state, as given by `acceleration`.
+    fn integrate(&mut self,
+                 dt: f64,
+                 x: &mut [Point3<f64>],
+                 v: &mut [Vector3<f64>],
+                 a: &mut [Vector3<f64>],
+                 acceleration: &mut AccelerationFunction);
+}
+
+/// The semi-implicit (symplectic) Euler method. First order.
+pub struct SemiImplicitEuler;
+
+/// The Velocity Verlet method. Second order and symplectic.
+///
+/// See https://en.wikipedia.org/wiki/Verlet_integration#Velocity_Verlet
+pub struct VelocityVerlet {
+    v_predicted: Vec<Vector3<f64>>
+}
+
+/// The classical fourth order Runge-Kutta method. Not symplectic, so energy
+/// slowly drifts over long simulations, although very little per step.
+pub struct RungeKutta4 {
+    x_stage: Vec<Point3<f64>>,
+    v_stage: Vec<Vector3<f64>>,
+    a_stage: Vec<Vector3<f64>>,
+    dx: Vec<Vector3<f64>>,
+    dv: Vec<Vector3<f64>>
+}
+
+/// Yoshida's fourth order symplectic method, equivalent to the method of
+/// Forest and Ruth, formed by composing three Velocity Verlet steps.
+///
+/// See https://en.wikipedia.org/wiki/Leapfrog_integration#Yoshida_algorithms
+pub struct Yoshida4 {
+    verlet: VelocityVerlet
+}
+
+impl VelocityVerlet {
+    pub fn new() -> Self {
+        VelocityVerlet {
+            v_predicted: Vec::new()
+        }
+    }
+}
+
+impl RungeKutta4 {
+    pub fn new() -> Self {
+        RungeKutta4 {
+            x_stage: Vec::new(),
+            v_stage: Vec::new(),
+            a_stage: Vec::new(),
+            dx: Vec::new(),
+            dv: Vec::new()
+        }
+    }
+}
+
+impl Yoshida4 {
+    pub fn new() -> Self {
+        Yoshida4 {
+            verlet: VelocityVerlet::new()
+        }
+    }
+}
+
+fn assert_consistent_lengths(x: &[Point3<f64>], v: &[Vector3<f64>], a: &[Vector3<f64>]) {
+    assert!(x.len() == v.len() && v.len() == a.len());
+}
+
+impl Integrator for SemiImplicitEuler {
+    fn integrate(&mut self,
+                 dt: f64,
+                 x: &mut [Point3<f64>],
+                 v: &mut [Vector3<f64>],
+                 a: &mut [Vector3<f64>],
+                 acceleration: &mut AccelerationFunction)
+    {
+        assert_consistent_lengths(x, v, a);
+
+        for i in 0 .. x.len() {
+            v[i] += dt * a[i];
+            x[i] += dt * v[i];
+        }
+
+        acceleration(x, v, a);
+    }
+}
+
+impl Integrator for VelocityVerlet {
+    fn integrate(&mut self,
+                 dt: f64,
+                 x: &mut [Point3<f64>],
+                 v: &mut [Vector3<f64>],
+                 a: &mut [Vector3<f64>],
+                 acceleration: &mut AccelerationFunction)
+    {
+        assert_consistent_lengths(x, v, a);
+
+        // Update positions
+        for i in 0 .. x.len() {
+            x[i] += dt * v[i] + 0.5 * dt * dt * a[i];
+        }
+
+        // Velocity Verlet requires the acceleration at the new positions before
+        // the new velocities are known. For velocity-dependent forces, we evaluate
+        // the acceleration with a first order prediction of the new velocities.
+        self.v_predicted.clear();
+        self.v_predicted.extend(v.iter().zip(a.iter()).map(|(v, a)| v + dt * a));
+
+        // Update velocities, keeping the old accelerations around until
+        // the new accelerations have been computed
+        for i in 0 .. v.len() {
+            v[i] += 0.5 * dt * a[i];
+        }
+        acceleration(x, &self.v_predicted, a);
+        for i in 0 .. v.len() {
+            v[i] += 0.5 * dt * a[i];
+        }
+    }
+}
+
+impl Integrator for RungeKutta4 {
+    fn integrate(&mut self,
+                 dt: f64,
+                 x: &mut [Point3<f64>],
+                 v: &mut [Vector3<f64>],
+                 a: &mut [Vector3<f64>],
+                 acceleration: &mut AccelerationFunction)
+    {
+        assert_consistent_lengths(x, v, a);
+        let n = x.len();
+
+        // dx and dv accumulate the weighted sum of the stage derivatives.
+        // The first stage derivatives are simply the current velocities and accelerations.
+        self.dx.clear();
+        self.dv.clear();
+        self.dx.extend_from_slice(v);
+        self.dv.extend_from_slice(a);
+
+        self.x_stage.clear();
+        self.v_stage.clear();
+        self.x_stage.extend_from_slice(x);
+        self.v_stage.extend_from_slice(v);
+        self.a_stage.clear();
+        self.a_stage.extend_from_slice(a);
+
+        // The remaining three stages, given by (fraction of dt, weight)
+        let stages = [(0.5, 2.0), (0.5, 2.0), (1.0, 1.0)];
+        for &(fraction, weight) in stages.iter() {
+            // The state for this stage is given by the derivatives of the previous stage,
+            // which are the current stage velocities and accelerations
+            for i in 0 .. n {
+                let v_prev = self.v_stage[i];
+                self.x_stage[i] = x[i] + fraction * dt * v_prev;
+                self.v_stage[i] = v[i] + fraction * dt * self.a_stage[i];
+            }
+            acceleration(&self.x_stage, &self.v_stage, &mut self.a_stage);
+
+            for i in 0 .. n {
+                self.dx[i] += weight * self.v_stage[i];
+                self.dv[i] += weight * self.a_stage[i];
+            }
+        }
+
+        for i in 0 .. n {
+            x[i] += (dt / 6.0) * self.dx[i];
+            v[i] += (dt / 6.0) * self.dv[i];
+        }
+
+        acceleration(x, v, a);
+    }
+}
+
+impl Integrator for Yoshida4 {
+    fn integrate(&mut self,
+                 dt: f64,
+                 x: &mut [Point3<f64>],
+                 v: &mut [Vector3<f64>],
+                 a: &mut [Vector3<f64>],
+                 acceleration: &mut AccelerationFunction)
+    {
+        let cbrt2 = 2.0f64.powf(1.0 / 3.0);
+        let w1 = 1.0 / (2.0 - cbrt2);
+        let w0 = - cbrt2 / (2.0 - cbrt2);
+
+        self.verlet.integrate(w1 * dt, x, v, a, acceleration);
+        self.verlet.integrate(w0 * dt, x, v, a, acceleration);
+        self.verlet.integrate(w1 * dt, x, v, a, acceleration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Integrator, SemiImplicitEuler, VelocityVerlet, RungeKutta4, Yoshida4};
+    use nalgebra::{norm_squared, Point3, Vector3};
+    use std::f64::consts::PI;
+
+    // A unit harmonic oscillator, x'' = -x, whose period is 2π
+    fn harmonic_acceleration(x: &[Point3<f64>], _: &[Vector3<f64>], a: &mut [Vector3<f64>]) {
+        for (x, a) in x.iter().zip(a.iter_mut()) {
+            *a = - x.coords;
+        }
+    }
+
+    fn energy(x: &Point3<f64>, v: &Vector3<f64>) -> f64 {
+        0.5 * norm_squared(v) + 0.5 * norm_squared(&x.coords)
+    }
+
+    /// Integrates the harmonic oscillator for the given number of steps, and returns
+    /// (position error with respect to the exact solution, maximum energy error).
+    fn integrate_oscillator<I: Integrator>(integrator: &mut I,
+                                           steps_per_period: usize,
+                                           num_steps: usize) -> (f64, f64) {
+        let x0 = Point3::new(1.0, 0.0, 0.0);
+        let v0 = Vector3::new(0.0, 1.0, 0.0);
+        let e0 = energy(&x0, &v0);
+
+        let mut x = vec![x0];
+        let mut v = vec![v0];
+        let mut a = vec![- x0.coords];
+        let dt = 2.0 * PI / steps_per_period as f64;
+
+        let mut max_energy_error: f64 = 0.0;
+        for _ in 0 .. num_steps {
+            integrator.integrate(dt, &mut x, &mut v, &mut a, &mut harmonic_acceleration);
+            max_energy_error = max_energy_error.max((energy(&x[0], &v[0]) - e0).abs());
+        }
+
+        let t = num_steps as f64 * dt;
+        let exact = Point3::new(t.cos(), t.sin(), 0.0);
+        (norm_squared(&(x[0] - exact)).sqrt(), max_energy_error)
+    }
+
+    fn convergence_order<I: Integrator, F: Fn() -> I>(create: F) -> f64 {
+        // Compare errors after a quarter of a period, since errors of some
+        // methods happen to cancel after a full period of the oscillator
+        let (coarse_error, _) = integrate_oscillator(&mut create(), 40, 10);
+        let (fine_error, _) = integrate_oscillator(&mut create(), 80, 20);
+        (coarse_error / fine_error).log2()
+    }
+
+    #[test]
+    fn semi_implicit_euler_is_first_order() {
+        let order = convergence_order(|| SemiImplicitEuler);
+        assert!((order - 1.0).abs() < 0.2, "Order was {}", order);
+    }
+
+    #[test]
+    fn velocity_verlet_is_second_order() {
+        let order = convergence_order(VelocityVerlet::new);
+        assert!((order - 2.0).abs() < 0.2, "Order was {}", order);
+    }
+
+    #[test]
+    fn runge_kutta4_is_fourth_order() {
+        let order = convergence_order(RungeKutta4::new);
+        assert!((order - 4.0).abs() < 0.2, "Order was {}", order);
+    }
+
+    #[test]
+    fn yoshida4_is_fourth_order() {
+        let order = convergence_order(Yoshida4::new);
+        assert!((order - 4.0).abs() < 0.2, "Order was {}", order);
+    }
+
+    #[test]
+    fn symplectic_integrators_have_bounded_energy_error() {
+        // For symplectic integrators, the energy error oscillates but does not grow,
+        // so the maximum error over many periods is the same as over a single period.
+        let check = |integrator: &mut Integrator| {
+            let mut errors = Vec::new();
+            for &num_periods in [1, 1000].iter() {
+                let mut max_energy_error: f64 = 0.0;
+                let mut x = vec![Point3::new(1.0, 0.0, 0.0)];
+                let mut v = vec![Vector3::new(0.0, 1.0, 0.0)];
+                let mut a = vec![Vector3::new(-1.0, 0.0, 0.0)];
+                let dt = 2.0 * PI / 20.0;
+                for _ in 0 .. 20 * num_periods {
+                    integrator.integrate(dt, &mut x, &mut v, &mut a, &mut harmonic_acceleration);
+                    max_energy_error = max_energy_error.max((energy(&x[0], &v[0]) - 0.5).abs());
+                }
+                errors.push(max_energy_error);
+            }
+            assert!(errors[1] < 1.01 * errors[0], "Energy errors were {:?}", errors);
+        };
+
+        check(&mut SemiImplicitEuler);
+        check(&mut VelocityVerlet::new());
+        check(&mut Yoshida4::new());
+    }
+
+    #[test]
+    fn runge_kutta4_energy_drifts() {
+        let (_, short_error) = integrate_oscillator(&mut RungeKutta4::new(), 20, 20);
+        let (_, long_error) = integrate_oscillator(&mut RungeKutta4::new(), 20, 2000);
+        assert!(long_error > 10.0 * short_error);
+    }
+}
@@ -0,0 +1,326 @@
+use physics::{RigidBody, ScopedForceGenerator};
+use physics::force_generator::GeneratorBodies;
+use physics::angular_integrator::world_inverse_inertia;
+use entity::{Entity, LinearComponentStorage};
+use nalgebra::{zero, norm};
+use std::collections::HashMap;
+
+/// The time for which all bodies of an island must move slower than
+/// their sleep thresholds before the island falls asleep.
+pub const TIME_TO_SLEEP: f64 = 0.5;
+
+/// Disjoint sets of indices, with path halving and union by size.
+struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>
+}
+
+impl UnionFind {
+    fn new(n: usize) -> UnionFind {
+        UnionFind {
+            parent: (0 .. n).collect(),
+            size: vec![1; n]
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            let (small, large) = if self.size[a] < self.size[b] { (a, b) } else { (b, a) };
+            self.parent[small] = large;
+            self.size[large] += self.size[small];
+        }
+    }
+}
+
+/// Partitions the dynamic bodies into islands, which are connected through the given
+/// pairs of entities, such as bodies in contact or connected by joints. Static and
+/// kinematic bodies do not connect islands, since they are unaffected by the bodies
+/// they touch. Returns the islands, indexed by the index of each body in the storage,
+/// along with the index of every dynamic body.
+fn find_islands(rigid_bodies: &LinearComponentStorage<RigidBody>, pairs: &[(Entity, Entity)])
+    -> (UnionFind, HashMap<Entity, usize>)
+{
+    let components = rigid_bodies.components();
+    let indices: HashMap<Entity, usize> = components.iter()
+        .enumerate()
+        .filter(|&(_, &(ref rb, _))| rb.as_dynamic().is_some())
+        .map(|(index, &(_, entity))| (entity, index))
+        .collect();
+
+    let mut islands = UnionFind::new(components.len());
+    for &(a, b) in pairs {
+        if let (Some(&a), Some(&b)) = (indices.get(&a), indices.get(&b)) {
+            islands.union(a, b);
+        }
+    }
+    (islands, indices)
+}
+
+/// Wakes every sleeping island which is disturbed by one of the given pairs, because
+/// it connects the island to an awake dynamic body or to a moving kinematic body.
+pub fn wake_disturbed_islands(rigid_bodies: &mut LinearComponentStorage<RigidBody>,
+                              pairs: &[(Entity, Entity)])
+{
+    let (mut islands, indices) = find_islands(rigid_bodies, pairs);
+    let mut disturbed = vec![false; rigid_bodies.components().len()];
+    {
+        let components = rigid_bodies.components();
+        for (index, &(ref rb, _)) in components.iter().enumerate() {
+            if rb.as_dynamic().map_or(false, |rb| !rb.sleeping) {
+                disturbed[islands.find(index)] = true;
+            }
+        }
+
+        // Kinematic bodies do not belong to any island, so they disturb
+        // the islands of the dynamic bodies they touch directly
+        for &(a, b) in pairs {
+            for &(kinematic, other) in &[(a, b), (b, a)] {
+                if let Some(&other_index) = indices.get(&other) {
+                    let moving = rigid_bodies.lookup_component_for_entity(kinematic)
+                        .and_then(|rb| rb.as_kinematic())
+                        .map_or(false, |rb| norm(&rb.velocity) > 0.0 || norm(&rb.angular_velocity) > 0.0);
+                    if moving {
+                        disturbed[islands.find(other_index)] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    for (index, &mut (ref mut rb, _)) in rigid_bodies.components_mut().iter_mut().enumerate() {
+        if let Some(rb) = rb.as_dynamic_mut() {
+            if rb.sleeping && disturbed[islands.find(index)] {
+                rb.wake_up();
+            }
+        }
+    }
+}
+
+/// Wakes the sleeping bodies which are pushed by generators that disturb resting bodies,
+/// such as thrust or wind. The rest of their islands are woken along with them once
+/// the disturbed islands are woken.
+pub fn wake_bodies_pushed_by_generators(rigid_bodies: &mut LinearComponentStorage<RigidBody>,
+                                        force_generators: &LinearComponentStorage<ScopedForceGenerator>)
+{
+    let disturbing = force_generators.components().iter()
+        .any(|&(ref force, _)| force.generator.disturbs_resting_bodies());
+    if !disturbing {
+        return;
+    }
+
+    // The generators act on the sleeping bodies as if they were being integrated
+    let mut entities = Vec::new();
+    let mut x = Vec::new();
+    let mut v = Vec::new();
+    let mut q = Vec::new();
+    for &(ref rb, entity) in rigid_bodies.components() {
+        if let Some(rb) = rb.as_dynamic() {
+            if rb.sleeping {
+                entities.push(entity);
+                x.push(rb.state.position);
+                v.push(rb.state.velocity);
+                q.push(rb.state.orientation);
+            }
+        }
+    }
+    let w = vec![zero(); entities.len()];
+    let indices = entities.iter().enumerate().map(|(i, &entity)| (entity, i)).collect();
+    let others = HashMap::new();
+
+    // Neither thrust nor wind depends on the volumes of the bodies
+    let volumes = vec![0.0; entities.len()];
+    let bodies = GeneratorBodies {
+        entities: &entities,
+        x: &x,
+        v: &v,
+        orientations: &q,
+        angular_velocities: &w,
+        volumes: &volumes,
+        indices: &indices,
+        others: &others
+    };
+
+    let mut pushed = vec![false; entities.len()];
+    for &(ref force, owner) in force_generators.components() {
+        if force.generator.disturbs_resting_bodies() {
+            force.generator.for_each_force(&bodies, |i, generated_force, _| {
+                if norm(&generated_force) > 0.0 && force.scope.contains(owner, &bodies, i) {
+                    pushed[i] = true;
+                }
+            });
+        }
+    }
+
+    for (&entity, _) in entities.iter().zip(pushed).filter(|&(_, pushed)| pushed) {
+        if let Some(rb) = rigid_bodies.lookup_component_for_entity_mut(entity).and_then(|rb| rb.as_dynamic_mut()) {
+            rb.wake_up();
+        }
+    }
+}
+
+/// Advances the sleep timers of the awake dynamic bodies, and puts to sleep every island
+/// in which all bodies have moved slower than their sleep thresholds for long enough.
+pub fn update_sleep(dt: f64,
+                    rigid_bodies: &mut LinearComponentStorage<RigidBody>,
+                    pairs: &[(Entity, Entity)],
+                    allow_sleep: bool)
+{
+    let (mut islands, _) = find_islands(rigid_bodies, pairs);
+    let num_bodies = rigid_bodies.components().len();
+
+    // An island may only fall asleep if all of its bodies are ready to sleep
+    let mut restless = vec![false; num_bodies];
+    for (index, &mut (ref mut rb, _)) in rigid_bodies.components_mut().iter_mut().enumerate() {
+        if let Some(rb) = rb.as_dynamic_mut() {
+            if rb.sleeping {
+                continue;
+            }
+
+            let angular_velocity = world_inverse_inertia(&rb.inv_inertia_body, rb.state.orientation)
+                                 * rb.state.angular_momentum;
+            let slow = rb.sleep_thresholds.map_or(false, |thresholds| {
+                norm(&rb.state.velocity) < thresholds.linear
+                    && norm(&angular_velocity) < thresholds.angular
+            });
+            rb.sleep_timer = if slow { rb.sleep_timer + dt } else { 0.0 };
+            if !allow_sleep || rb.sleep_timer < TIME_TO_SLEEP {
+                restless[islands.find(index)] = true;
+            }
+        }
+    }
+
+    for (index, &mut (ref mut rb, _)) in rigid_bodies.components_mut().iter_mut().enumerate() {
+        if let Some(rb) = rb.as_dynamic_mut() {
+            if !rb.sleeping && !restless[islands.find(index)] {
+                rb.fall_asleep();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{wake_disturbed_islands, update_sleep, TIME_TO_SLEEP};
+    use physics::{RigidBody, DynamicRigidBody, StaticRigidBody, KinematicRigidBody, KinematicMotion, Mass};
+    use entity::{Entity, EntityManager, LinearComponentStorage};
+    use nalgebra::{Point3, Vector3, UnitQuaternion};
+
+    struct Scene {
+        bodies: LinearComponentStorage<RigidBody>,
+        entities: Vec<Entity>
+    }
+
+    impl Scene {
+        /// A static body, a kinematic body at rest and four dynamic bodies at rest.
+        fn new() -> Scene {
+            let mut entity_manager = EntityManager::new();
+            let mut bodies = LinearComponentStorage::new();
+            let mut entities = Vec::new();
+
+            let mut add = |rb: RigidBody| {
+                let entity = entity_manager.create();
+                bodies.set_component_for_entity(entity, rb);
+                entities.push(entity);
+            };
+            add(RigidBody::Static(StaticRigidBody {
+                position: Point3::origin(),
+                orientation: UnitQuaternion::identity()
+            }));
+            add(RigidBody::Kinematic(KinematicRigidBody::new(Point3::origin(), UnitQuaternion::identity(),
+                                                             KinematicMotion::Velocity)));
+            for _ in 0 .. 4 {
+                add(RigidBody::Dynamic(DynamicRigidBody {
+                    mass: Mass::new(1.0),
+                    .. DynamicRigidBody::default()
+                }));
+            }
+
+            Scene {
+                bodies: bodies,
+                entities: entities
+            }
+        }
+
+        fn body_mut(&mut self, index: usize) -> &mut DynamicRigidBody {
+            self.bodies.components_mut()[index].0.as_dynamic_mut().unwrap()
+        }
+
+        fn sleeping(&self) -> Vec<bool> {
+            self.bodies.components().iter()
+                .filter_map(|&(ref rb, _)| rb.as_dynamic())
+                .map(|rb| rb.sleeping)
+                .collect()
+        }
+
+        fn pairs(&self, pairs: &[(usize, usize)]) -> Vec<(Entity, Entity)> {
+            pairs.iter().map(|&(a, b)| (self.entities[a], self.entities[b])).collect()
+        }
+    }
+
+    #[test]
+    fn islands_fall_asleep_when_all_bodies_are_at_rest() {
+        let mut scene = Scene::new();
+
+        // Bodies 2 and 3 are stacked on the static body, while 4 and 5 are only
+        // connected through the static body, so they form separate islands
+        let pairs = scene.pairs(&[(0, 2), (2, 3), (0, 4), (0, 5)]);
+        scene.body_mut(3).state.velocity = Vector3::new(0.1, 0.0, 0.0);
+        scene.body_mut(5).sleep_thresholds = None;
+
+        let dt = 0.01;
+        let num_steps = (TIME_TO_SLEEP / dt).ceil() as usize + 1;
+        for _ in 0 .. num_steps {
+            update_sleep(dt, &mut scene.bodies, &pairs, true);
+        }
+        assert_eq!(vec![false, false, true, false], scene.sleeping());
+
+        // Once the moving body slows down, its whole island falls asleep
+        scene.body_mut(3).state.velocity = Vector3::new(0.01, 0.0, 0.0);
+        for _ in 0 .. num_steps {
+            update_sleep(dt, &mut scene.bodies, &pairs, true);
+        }
+        assert_eq!(vec![true, true, true, false], scene.sleeping());
+        assert_eq!(Vector3::new(0.0, 0.0, 0.0), scene.body_mut(3).state.velocity);
+
+        // Unless sleep is disallowed altogether
+        let mut scene = Scene::new();
+        for _ in 0 .. num_steps {
+            update_sleep(dt, &mut scene.bodies, &pairs, false);
+        }
+        assert_eq!(vec![false; 4], scene.sleeping());
+    }
+
+    #[test]
+    fn sleeping_islands_are_woken_by_awake_or_moving_bodies() {
+        let mut scene = Scene::new();
+        let pairs = scene.pairs(&[(2, 3), (4, 5)]);
+        for _ in 0 .. 100 {
+            update_sleep(0.01, &mut scene.bodies, &pairs, true);
+        }
+        assert_eq!(vec![true; 4], scene.sleeping());
+
+        // Contact with the static body or the kinematic body at rest does not disturb
+        let resting = scene.pairs(&[(2, 3), (4, 5), (0, 2), (1, 4)]);
+        wake_disturbed_islands(&mut scene.bodies, &resting);
+        assert_eq!(vec![true; 4], scene.sleeping());
+
+        // An awake body touching one body of an island wakes the whole island
+        scene.body_mut(2).wake_up();
+        wake_disturbed_islands(&mut scene.bodies, &resting);
+        assert_eq!(vec![false, false, true, true], scene.sleeping());
+
+        // Once the kinematic body moves, it wakes the island it touches
+        scene.bodies.components_mut()[1].0.as_kinematic_mut().unwrap().velocity = Vector3::new(1.0, 0.0, 0.0);
+        wake_disturbed_islands(&mut scene.bodies, &resting);
+        assert_eq!(vec![false; 4], scene.sleeping());
+    }
+}
@@ -0,0 +1,498 @@
+use physics::contact_solver::SolverBody;
+use entity::Entity;
+use nalgebra::{zero, norm, Point3, Vector3, Isometry3, Translation3, UnitQuaternion, Unit};
+use std::f64::consts::PI;
+
+/// Drives a hinge towards a target angular velocity.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct JointMotor {
+    /// The target angular velocity of the second body relative to the first,
+    /// about the hinge axis.
+    pub target_velocity: f64,
+
+    /// The largest torque the motor can exert.
+    pub max_torque: f64
+}
+
+/// The relative motion permitted by a joint, expressed in terms of the joint frames.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum JointKind {
+    /// Keeps the origins of the two frames together, leaving rotation free.
+    BallSocket,
+
+    /// Keeps the origins of the two frames together, and only permits rotation about
+    /// their common x axis. The angle is measured from the y axis of the first frame
+    /// to the y axis of the second, and the limits must lie within [-π, π].
+    Hinge {
+        limits: Option<(f64, f64)>,
+        motor: Option<JointMotor>
+    },
+
+    /// Locks the relative orientation of the frames, and only permits translation of
+    /// the second frame along the x axis of the first, with the given limits on the
+    /// distance between their origins.
+    Slider {
+        limits: Option<(f64, f64)>
+    },
+
+    /// Keeps the distance between the origins of the frames within [min, max].
+    /// Equal bounds give a rigid rod, and a lower bound of zero gives a rope.
+    Distance {
+        min: f64,
+        max: f64
+    },
+
+    /// Locks all relative motion, welding the bodies together.
+    Fixed
+}
+
+/// A constraint between two rigid bodies.
+///
+/// Each body carries a joint frame, given relative to its center of mass and orientation.
+/// Every kind of joint is satisfied when the frames coincide in world coordinates,
+/// and permits some relative motion away from this configuration.
+///
+/// The bodies are identified by entities, except in blueprints, where they refer to
+/// other blueprints of the same scene by index.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Joint<B = Entity> {
+    pub body1: B,
+    pub body2: B,
+    pub frame1: Isometry3<f64>,
+    pub frame2: Isometry3<f64>,
+    pub kind: JointKind,
+
+    /// Whether the two bodies collide with each other. Off by default, since
+    /// the collision models of connected bodies often overlap at the joint.
+    pub collide_connected: bool
+}
+
+impl<B> Joint<B> {
+    pub fn new(body1: B, frame1: Isometry3<f64>, body2: B, frame2: Isometry3<f64>, kind: JointKind)
+        -> Self
+    {
+        Joint {
+            body1: body1,
+            body2: body2,
+            frame1: frame1,
+            frame2: frame2,
+            kind: kind,
+            collide_connected: false
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_collision_between_bodies(mut self) -> Self {
+        self.collide_connected = true;
+        self
+    }
+
+    /// Replaces the references to the bodies, keeping everything else.
+    pub fn map_bodies<C, F>(&self, f: F) -> Joint<C> where F: Fn(&B) -> C {
+        Joint {
+            body1: f(&self.body1),
+            body2: f(&self.body2),
+            frame1: self.frame1,
+            frame2: self.frame2,
+            kind: self.kind,
+            collide_connected: self.collide_connected
+        }
+    }
+}
+
+/// Constructs a joint frame with the given origin, whose x axis points along the given axis.
+pub fn joint_frame(origin: Point3<f64>, axis: &Vector3<f64>) -> Isometry3<f64> {
+    let x = Vector3::new(1.0, 0.0, 0.0);
+    let rotation = UnitQuaternion::rotation_between(&x, axis).unwrap_or_else(|| {
+        // The axis points along the negative x axis
+        UnitQuaternion::from_axis_angle(&Unit::new_normalize(Vector3::new(0.0, 0.0, 1.0)), PI)
+    });
+    Isometry3::from_parts(Translation3::from_vector(origin.coords), rotation)
+}
+
+/// A single scalar velocity constraint between two bodies, given by its Jacobian.
+/// The linear part acts on the second body and, with opposite sign, on the first,
+/// while the angular parts act on the respective angular velocities.
+pub struct JointRow {
+    body1: usize,
+    body2: usize,
+    linear: Vector3<f64>,
+    angular1: Vector3<f64>,
+    angular2: Vector3<f64>,
+    mass: f64,
+
+    // Target velocity along the Jacobian, accounting for position errors
+    bias: f64,
+
+    // Bounds on the impulse accumulated over all iterations
+    min_impulse: f64,
+    max_impulse: f64,
+
+    pub impulse: f64
+}
+
+impl JointRow {
+    fn new(bodies: &[SolverBody],
+           body1: usize,
+           body2: usize,
+           linear: Vector3<f64>,
+           angular1: Vector3<f64>,
+           angular2: Vector3<f64>) -> JointRow
+    {
+        let (b1, b2) = (&bodies[body1], &bodies[body2]);
+        let k = (b1.inv_mass + b2.inv_mass) * linear.dot(&linear)
+              + angular1.dot(&(b1.inv_inertia * angular1))
+              + angular2.dot(&(b2.inv_inertia * angular2));
+        JointRow {
+            body1: body1,
+            body2: body2,
+            linear: linear,
+            angular1: angular1,
+            angular2: angular2,
+            mass: if k > 0.0 { 1.0 / k } else { 0.0 },
+            bias: 0.0,
+            min_impulse: - ::std::f64::INFINITY,
+            max_impulse: ::std::f64::INFINITY,
+            impulse: 0.0
+        }
+    }
+
+    fn velocity(&self, bodies: &[SolverBody]) -> f64 {
+        let (b1, b2) = (&bodies[self.body1], &bodies[self.body2]);
+        self.linear.dot(&(b2.velocity - b1.velocity))
+            + self.angular1.dot(&b1.angular_velocity)
+            + self.angular2.dot(&b2.angular_velocity)
+    }
+
+    pub fn apply_impulse(&self, bodies: &mut [SolverBody], impulse: f64) {
+        bodies[self.body1].apply_generalized_impulse(- impulse * self.linear, impulse * self.angular1);
+        bodies[self.body2].apply_generalized_impulse(impulse * self.linear, impulse * self.angular2);
+    }
+
+    pub fn solve(&mut self, bodies: &mut [SolverBody]) {
+        let old_impulse = self.impulse;
+        let delta = self.mass * (self.bias - self.velocity(bodies));
+        self.impulse = (old_impulse + delta).max(self.min_impulse).min(self.max_impulse);
+        self.apply_impulse(bodies, self.impulse - old_impulse);
+    }
+}
+
+/// Parameters shared by all joint rows in a single step.
+pub struct StepParameters {
+    pub dt: f64,
+    pub inv_dt: f64,
+
+    /// The fraction of the position error corrected for in each step.
+    pub baumgarte: f64
+}
+
+impl StepParameters {
+    /// The target velocity which corrects the position error `c` of an equality constraint.
+    fn equality_bias(&self, c: f64) -> f64 {
+        - self.baumgarte * self.inv_dt * c
+    }
+
+    /// The target velocity for an inequality constraint `c >= 0`. Like speculative contacts,
+    /// a constraint which is not yet active permits approaching the limit until it is reached.
+    fn inequality_bias(&self, c: f64) -> f64 {
+        if c < 0.0 { self.equality_bias(c) } else { - c * self.inv_dt }
+    }
+}
+
+/// Appends the rows which make up the given joint between the two solver bodies.
+/// The rows of a joint always appear in the same order, so that their accumulated
+/// impulses can be carried over to the next step.
+pub fn prepare_joint_rows(joint: &Joint,
+                          body1: usize,
+                          body2: usize,
+                          bodies: &[SolverBody],
+                          step: &StepParameters,
+                          rows: &mut Vec<JointRow>)
+{
+    let (b1, b2) = (&bodies[body1], &bodies[body2]);
+    let q1 = b1.orientation * joint.frame1.rotation;
+    let q2 = b2.orientation * joint.frame2.rotation;
+    let p1 = b1.position + b1.orientation * joint.frame1.translation.vector;
+    let p2 = b2.position + b2.orientation * joint.frame2.translation.vector;
+    let d = p2 - p1;
+
+    // Both origins are measured relative to the first body's center of mass,
+    // which accounts for the rotation of the first frame in constraints
+    // along the axes of the first frame
+    let r1 = p2 - b1.position;
+    let r2 = p2 - b2.position;
+    let axes1 = [q1 * Vector3::new(1.0, 0.0, 0.0),
+                 q1 * Vector3::new(0.0, 1.0, 0.0),
+                 q1 * Vector3::new(0.0, 0.0, 1.0)];
+    let world_axes = [Vector3::new(1.0, 0.0, 0.0),
+                      Vector3::new(0.0, 1.0, 0.0),
+                      Vector3::new(0.0, 0.0, 1.0)];
+
+    // Constrains the relative velocity of the origins along the given direction
+    let linear_row = |n: Vector3<f64>| {
+        JointRow::new(bodies, body1, body2, n, - r1.cross(&n), r2.cross(&n))
+    };
+    // Constrains the relative angular velocity about the given axis
+    let angular_row = |axis: Vector3<f64>| {
+        JointRow::new(bodies, body1, body2, zero(), - axis, axis)
+    };
+    let limit_rows = |rows: &mut Vec<JointRow>, row: &Fn(Vector3<f64>) -> JointRow,
+                      axis: Vector3<f64>, value: f64, limits: (f64, f64)| {
+        let (lower, upper) = limits;
+        let mut lower_row = row(axis);
+        lower_row.bias = step.inequality_bias(value - lower);
+        lower_row.min_impulse = 0.0;
+        rows.push(lower_row);
+
+        let mut upper_row = row(- axis);
+        upper_row.bias = step.inequality_bias(upper - value);
+        upper_row.min_impulse = 0.0;
+        rows.push(upper_row);
+    };
+    let lock_position = |rows: &mut Vec<JointRow>| {
+        for &n in &world_axes {
+            let mut row = linear_row(n);
+            row.bias = step.equality_bias(d.dot(&n));
+            rows.push(row);
+        }
+    };
+    let lock_rotation = |rows: &mut Vec<JointRow>| {
+        let error = (q2 * q1.inverse()).scaled_axis();
+        for &axis in &world_axes {
+            let mut row = angular_row(axis);
+            row.bias = step.equality_bias(error.dot(&axis));
+            rows.push(row);
+        }
+    };
+
+    match joint.kind {
+        JointKind::BallSocket => lock_position(rows),
+        JointKind::Hinge { limits, motor } => {
+            lock_position(rows);
+
+            // The x axis of the second frame must remain perpendicular to
+            // the y and z axes of the first
+            let hinge_axis2 = q2 * Vector3::new(1.0, 0.0, 0.0);
+            for &perpendicular in &axes1[1 ..] {
+                let direction = hinge_axis2.cross(&perpendicular);
+                let mut row = angular_row(direction);
+                row.bias = step.equality_bias(hinge_axis2.dot(&perpendicular));
+                rows.push(row);
+            }
+
+            let y2 = q2 * Vector3::new(0.0, 1.0, 0.0);
+            let angle = axes1[1].cross(&y2).dot(&axes1[0]).atan2(axes1[1].dot(&y2));
+            if let Some(limits) = limits {
+                limit_rows(rows, &angular_row, axes1[0], angle, limits);
+            }
+            if let Some(motor) = motor {
+                let mut row = angular_row(axes1[0]);
+                row.bias = motor.target_velocity;
+                row.min_impulse = - motor.max_torque * step.dt;
+                row.max_impulse = motor.max_torque * step.dt;
+                rows.push(row);
+            }
+        },
+        JointKind::Slider { limits } => {
+            lock_rotation(rows);
+            for &n in &axes1[1 ..] {
+                let mut row = linear_row(n);
+                row.bias = step.equality_bias(d.dot(&n));
+                rows.push(row);
+            }
+            if let Some(limits) = limits {
+                limit_rows(rows, &linear_row, axes1[0], d.dot(&axes1[0]), limits);
+            }
+        },
+        JointKind::Distance { min, max } => {
+            // The direction is undefined when the origins coincide, in which
+            // case we can only hope that the bodies separate by themselves
+            let distance = norm(&d);
+            if distance > 1e-9 {
+                let n = d / distance;
+                if min == max {
+                    let mut row = linear_row(n);
+                    row.bias = step.equality_bias(distance - min);
+                    rows.push(row);
+                } else {
+                    limit_rows(rows, &linear_row, n, distance, (min, max));
+                }
+            }
+        },
+        JointKind::Fixed => {
+            lock_position(rows);
+            lock_rotation(rows);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Joint, JointKind, JointMotor, joint_frame};
+    use physics::solver_scene::Scene;
+    use nalgebra::{zero, norm, Point3, Vector3, Isometry3};
+    use std::f64::consts::PI;
+
+    fn at(x: f64, y: f64, z: f64) -> Isometry3<f64> {
+        joint_frame(Point3::new(x, y, z), &Vector3::new(1.0, 0.0, 0.0))
+    }
+
+    #[test]
+    fn ball_socket_pendulum_has_expected_period() {
+        // A body hanging from a ball socket at a distance l above its center of mass
+        // is a physical pendulum. For small amplitudes, its period is
+        // 2π sqrt((I + m l²) / (m g l)), where I is the moment of inertia of the body.
+        let (l, g) = (2.0, 9.81);
+        let mut scene = Scene::new(Vector3::new(0.0, 0.0, - g));
+        let angle: f64 = 0.05;
+        let bob = scene.add_point_mass(Point3::new(l * angle.sin(), 0.0, - l * angle.cos()));
+        let anchor = scene.anchor;
+        scene.join(Joint::new(anchor, at(0.0, 0.0, 0.0), bob,
+                              at(- l * angle.sin(), 0.0, l * angle.cos()), JointKind::BallSocket));
+
+        // After half a period, the bob should be on the other side
+        let period = 2.0 * PI * ((1.0 + l * l) / (g * l)).sqrt();
+        let dt = 1e-3;
+        scene.simulate(dt, (0.5 * period / dt).round() as usize);
+        let x = scene.body(bob).state.position;
+        assert!((x.x + l * angle.sin()).abs() < 5e-3, "Position was {:?}", x);
+        assert!((norm(&x.coords) - l).abs() < 1e-3);
+    }
+
+    #[test]
+    fn chain_hangs_with_links_at_fixed_distances() {
+        let mut scene = Scene::new(Vector3::new(0.0, 0.0, -9.81));
+        let links: Vec<_> = (1 .. 6).map(|i| scene.add_point_mass(Point3::new(i as f64, 0.0, 0.0))).collect();
+
+        let anchor = scene.anchor;
+        scene.join(Joint::new(anchor, at(0.0, 0.0, 0.0), links[0], at(-0.5, 0.0, 0.0), JointKind::BallSocket));
+        for pair in links.windows(2) {
+            scene.join(Joint::new(pair[0], at(0.5, 0.0, 0.0), pair[1], at(-0.5, 0.0, 0.0),
+                                  JointKind::BallSocket));
+        }
+
+        // The chain starts out horizontal and swings down, eventually coming
+        // to hang vertically once the angular momentum is damped away
+        for _ in 0 .. 2000 {
+            scene.step(1.0 / 200.0);
+            for &link in &links {
+                let rb = scene.body_mut(link);
+                rb.state.velocity *= 0.99;
+                rb.state.angular_momentum *= 0.99;
+            }
+        }
+
+        let mut previous = Point3::origin();
+        for &link in &links {
+            let rb = scene.body(link);
+            let orientation = rb.state.orientation;
+            let top = rb.state.position + orientation * Vector3::new(-0.5, 0.0, 0.0);
+            assert!(norm(&(top - previous)) < 2e-2, "Link was at {:?}", rb.state.position);
+            previous = rb.state.position + orientation * Vector3::new(0.5, 0.0, 0.0);
+        }
+        assert!(previous.x.abs() < 0.1 && (previous.z + 5.0).abs() < 0.1,
+            "End of chain was at {:?}", previous);
+    }
+
+    #[test]
+    fn hinge_motor_drives_body_at_target_speed_within_limits() {
+        let mut scene = Scene::new(zero());
+        let wheel = scene.add_point_mass(Point3::origin());
+        let anchor = scene.anchor;
+        let motor = JointMotor { target_velocity: 2.0, max_torque: 10.0 };
+        scene.join(Joint::new(anchor, at(0.0, 0.0, 0.0), wheel, at(0.0, 0.0, 0.0), JointKind::Hinge {
+            limits: None,
+            motor: Some(motor)
+        }));
+
+        // Any angular momentum about other axes is removed by the hinge
+        scene.body_mut(wheel).state.angular_momentum = Vector3::new(0.0, 1.0, 1.0);
+        scene.simulate(1e-2, 100);
+        let l = scene.body(wheel).state.angular_momentum;
+        assert!(norm(&(l - Vector3::new(2.0, 0.0, 0.0))) < 1e-6, "Angular momentum was {:?}", l);
+
+        // With limits, the motor pushes the wheel against the upper limit
+        let mut scene = Scene::new(zero());
+        let wheel = scene.add_point_mass(Point3::origin());
+        scene.join(Joint::new(anchor, at(0.0, 0.0, 0.0), wheel, at(0.0, 0.0, 0.0), JointKind::Hinge {
+            limits: Some((- 0.5, 0.5)),
+            motor: Some(motor)
+        }));
+        scene.simulate(1e-2, 100);
+        let rb = scene.body(wheel);
+        let y = rb.state.orientation * Vector3::new(0.0, 1.0, 0.0);
+        let angle = y.z.atan2(y.y);
+        assert!((angle - 0.5).abs() < 1e-2, "Angle was {}", angle);
+        assert!(norm(&rb.state.angular_momentum) < 1e-6);
+    }
+
+    #[test]
+    fn slider_only_permits_translation_along_axis() {
+        let mut scene = Scene::new(Vector3::new(0.0, 0.0, -9.81));
+        let carriage = scene.add_point_mass(Point3::origin());
+        let anchor = scene.anchor;
+        let axis = Vector3::new(1.0, 0.0, -1.0);
+        scene.join(Joint::new(anchor, joint_frame(Point3::origin(), &axis),
+                              carriage, joint_frame(Point3::origin(), &axis),
+                              JointKind::Slider { limits: Some((- 1.0, 2.0)) }));
+        scene.body_mut(carriage).state.angular_momentum = Vector3::new(0.3, -0.2, 0.1);
+
+        // The carriage slides down the incline with acceleration g / sqrt(2),
+        // until it reaches the lower end of the slider after
+        // sqrt(2 · 2 sqrt(2) / 9.81) ≈ 0.76 seconds
+        scene.simulate(1e-3, 500);
+        let rb = scene.body(carriage);
+        let distance = 0.5 * 9.81 / 2.0f64.sqrt() * 0.5 * 0.5;
+        let expected = Point3::from_coordinates(distance * axis.normalize());
+        assert!(norm(&(rb.state.position - expected)) < 1e-2, "Position was {:?}", rb.state.position);
+        assert!(norm(&rb.state.angular_momentum) < 1e-6);
+
+        scene.simulate(1e-3, 1000);
+        let rb = scene.body(carriage);
+        let expected = Point3::from_coordinates(2.0 * axis.normalize());
+        assert!(norm(&(rb.state.position - expected)) < 1e-2, "Position was {:?}", rb.state.position);
+        assert!(norm(&rb.state.velocity) < 1e-2);
+    }
+
+    #[test]
+    fn rope_only_pulls_when_taut() {
+        let mut scene = Scene::new(Vector3::new(0.0, 0.0, -9.81));
+        let weight = scene.add_point_mass(Point3::new(0.0, 0.0, 1.0));
+        let anchor = scene.anchor;
+        scene.join(Joint::new(anchor, at(0.0, 0.0, 2.0), weight, at(0.0, 0.0, 0.0),
+                              JointKind::Distance { min: 0.0, max: 2.0 }));
+
+        // The weight falls freely for one metre until the rope is taut
+        scene.simulate(1e-3, 400);
+        assert!((scene.body(weight).state.velocity.z + 9.81 * 0.4).abs() < 1e-9);
+
+        scene.simulate(1e-3, 600);
+        let rb = scene.body(weight);
+        assert!(rb.state.position.z.abs() < 1e-2, "Position was {:?}", rb.state.position);
+        assert!(norm(&rb.state.velocity) < 1e-2);
+    }
+
+    #[test]
+    fn fixed_joint_welds_bodies_together() {
+        let mut scene = Scene::new(zero());
+        let a = scene.add_point_mass(Point3::new(-1.0, 0.0, 0.0));
+        let b = scene.add_point_mass(Point3::new(1.0, 0.0, 0.0));
+        scene.join(Joint::new(a, at(1.0, 0.0, 0.0), b, at(-1.0, 0.0, 0.0), JointKind::Fixed));
+
+        // The pair spins about its common center of mass as a single rigid body
+        scene.body_mut(a).state.velocity = Vector3::new(0.0, 1.0, 0.0);
+        scene.body_mut(b).state.velocity = Vector3::new(0.0, -1.0, 0.0);
+        scene.simulate(1e-3, 2000);
+
+        let (rb_a, rb_b) = (scene.body(a), scene.body(b));
+        let separation = rb_b.state.position - rb_a.state.position;
+        assert!((norm(&separation) - 2.0).abs() < 1e-2);
+        assert!(norm(&(rb_a.state.position.coords + rb_b.state.position.coords)) < 1e-9);
+        let relative_rotation = rb_a.state.orientation.inverse() * rb_b.state.orientation;
+        assert!(relative_rotation.angle() < 1e-2);
+
+        // Both bodies rotate along with the line between them
+        let direction = rb_a.state.orientation * Vector3::new(1.0, 0.0, 0.0);
+        assert!(norm(&(direction - separation / 2.0)) < 1e-2, "Direction was {:?}", direction);
+    }
+}
@@ -0,0 +1,225 @@
+use physics::angular_integrator::exp_rotation;
+use nalgebra::{zero, Point3, Vector3, Isometry3, Translation3, UnitQuaternion};
+use std::fmt;
+use std::rc::Rc;
+
+/// A pose which a kinematic body passes through at the given time.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Keyframe {
+    pub time: f64,
+    pub pose: Isometry3<f64>
+}
+
+/// Determines how a kinematic body moves.
+#[derive(Clone)]
+pub enum KinematicMotion {
+    /// The body moves with its current velocity and angular velocity,
+    /// which may be changed between steps, for example by game logic.
+    Velocity,
+
+    /// The pose is interpolated between keyframes, which must be sorted by time.
+    /// The body rests at the first and last keyframes before and after the path,
+    /// unless the path is looping, in which case it restarts from the first keyframe
+    /// once the last one is reached. Smooth loops end with the pose they start with.
+    Keyframes {
+        keyframes: Vec<Keyframe>,
+        looping: bool
+    },
+
+    /// The pose is given as a function of the time elapsed since the motion started.
+    Scripted(Rc<Fn(f64) -> Isometry3<f64>>)
+}
+
+/// A body which follows a prescribed motion, rather than being moved by forces.
+/// It pushes dynamic bodies as if it had infinite mass, and is unaffected by them.
+#[derive(Clone, Debug)]
+pub struct KinematicRigidBody {
+    pub position: Point3<f64>,
+    pub orientation: UnitQuaternion<f64>,
+
+    /// The velocities over the last step. Unless the body moves by velocity,
+    /// they are recomputed from the change in pose in every step.
+    pub velocity: Vector3<f64>,
+    pub angular_velocity: Vector3<f64>,
+
+    pub prev_position: Point3<f64>,
+    pub prev_orientation: UnitQuaternion<f64>,
+
+    pub motion: KinematicMotion,
+
+    /// The time elapsed since the motion started.
+    pub time: f64
+}
+
+impl fmt::Debug for KinematicMotion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            KinematicMotion::Velocity => write!(f, "Velocity"),
+            KinematicMotion::Keyframes { ref keyframes, looping } =>
+                f.debug_struct("Keyframes")
+                 .field("keyframes", keyframes)
+                 .field("looping", &looping)
+                 .finish(),
+            KinematicMotion::Scripted(_) => write!(f, "Scripted")
+        }
+    }
+}
+
+impl KinematicRigidBody {
+    /// A body at rest in the given pose, which starts following the motion
+    /// once the simulation starts.
+    pub fn new(position: Point3<f64>, orientation: UnitQuaternion<f64>, motion: KinematicMotion) -> Self {
+        KinematicRigidBody {
+            position: position,
+            orientation: orientation,
+            velocity: zero(),
+            angular_velocity: zero(),
+            prev_position: position,
+            prev_orientation: orientation,
+            motion: motion,
+            time: 0.0
+        }
+    }
+
+    /// Moves the body along its motion by a single step.
+    pub fn advance(&mut self, dt: f64) {
+        self.prev_position = self.position;
+        self.prev_orientation = self.orientation;
+        self.time += dt;
+
+        let pose = match self.motion {
+            KinematicMotion::Velocity => None,
+            KinematicMotion::Keyframes { ref keyframes, looping } =>
+                Some(interpolate_keyframes(keyframes, looping, self.time)),
+            KinematicMotion::Scripted(ref script) => Some(script(self.time))
+        };
+
+        match pose {
+            Some(pose) => {
+                let position = Point3::from_coordinates(pose.translation.vector);
+                if dt > 0.0 {
+                    self.velocity = (position - self.position) / dt;
+                    self.angular_velocity = (pose.rotation * self.orientation.inverse()).scaled_axis() / dt;
+                }
+                self.position = position;
+                self.orientation = pose.rotation;
+            },
+            None => {
+                self.position += dt * self.velocity;
+                self.orientation = exp_rotation(dt * self.angular_velocity) * self.orientation;
+            }
+        }
+    }
+}
+
+/// Linearly interpolates the position, and spherically interpolates the orientation,
+/// between the keyframes surrounding the given time.
+fn interpolate_keyframes(keyframes: &[Keyframe], looping: bool, time: f64) -> Isometry3<f64> {
+    assert!(!keyframes.is_empty(), "A keyframe path needs at least one keyframe.");
+    let first = keyframes[0];
+    let last = keyframes[keyframes.len() - 1];
+    let duration = last.time - first.time;
+
+    let t = if looping && duration > 0.0 {
+        first.time + ((time - first.time) % duration + duration) % duration
+    } else {
+        time
+    };
+
+    if t <= first.time {
+        return first.pose;
+    }
+    let next = match keyframes.iter().position(|keyframe| keyframe.time > t) {
+        Some(next) => next,
+        None => return last.pose
+    };
+
+    let (a, b) = (keyframes[next - 1], keyframes[next]);
+    let s = (t - a.time) / (b.time - a.time);
+    let translation = a.pose.translation.vector + s * (b.pose.translation.vector - a.pose.translation.vector);
+    let relative_rotation = a.pose.rotation.inverse() * b.pose.rotation;
+    let rotation = a.pose.rotation * exp_rotation(s * relative_rotation.scaled_axis());
+    Isometry3::from_parts(Translation3::from_vector(translation), rotation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KinematicRigidBody, KinematicMotion, Keyframe};
+    use physics::angular_integrator::exp_rotation;
+    use nalgebra::{norm, Point3, Vector3, Isometry3, Translation3, UnitQuaternion};
+    use std::rc::Rc;
+
+    fn pose(x: f64, angle: f64) -> Isometry3<f64> {
+        Isometry3::from_parts(Translation3::from_vector(Vector3::new(x, 0.0, 0.0)),
+                              exp_rotation(Vector3::new(0.0, 0.0, angle)))
+    }
+
+    fn path(looping: bool) -> KinematicRigidBody {
+        let keyframes = vec![
+            Keyframe { time: 0.0, pose: pose(0.0, 0.0) },
+            Keyframe { time: 1.0, pose: pose(2.0, 1.0) },
+            Keyframe { time: 2.0, pose: pose(0.0, 0.0) }
+        ];
+        KinematicRigidBody::new(Point3::origin(), UnitQuaternion::identity(),
+                                KinematicMotion::Keyframes { keyframes: keyframes, looping: looping })
+    }
+
+    #[test]
+    fn keyframe_path_is_interpolated_with_matching_velocities() {
+        let mut body = path(false);
+        for _ in 0 .. 50 {
+            body.advance(0.01);
+        }
+
+        assert!(norm(&(body.position - Point3::new(1.0, 0.0, 0.0))) < 1e-12);
+        assert!((body.orientation.angle() - 0.5).abs() < 1e-12);
+        assert!(norm(&(body.velocity - Vector3::new(2.0, 0.0, 0.0))) < 1e-9);
+        assert!(norm(&(body.angular_velocity - Vector3::new(0.0, 0.0, 1.0))) < 1e-9);
+        assert!(norm(&(body.prev_position - Point3::new(0.98, 0.0, 0.0))) < 1e-12);
+
+        // After the last keyframe, the body comes to rest
+        for _ in 0 .. 200 {
+            body.advance(0.01);
+        }
+        assert!(norm(&body.position.coords) < 1e-12);
+        assert!(norm(&body.velocity) < 1e-12);
+    }
+
+    #[test]
+    fn looping_keyframe_path_restarts_from_first_keyframe() {
+        let mut body = path(true);
+        for _ in 0 .. 250 {
+            body.advance(0.01);
+        }
+
+        // Half way through the first half of the second loop
+        assert!(norm(&(body.position - Point3::new(1.0, 0.0, 0.0))) < 1e-9);
+        assert!(norm(&(body.velocity - Vector3::new(2.0, 0.0, 0.0))) < 1e-9);
+    }
+
+    #[test]
+    fn scripted_and_velocity_motions_agree() {
+        let circle = |t: f64| pose(t.sin(), t);
+        let mut scripted = KinematicRigidBody::new(Point3::origin(), UnitQuaternion::identity(),
+                                                   KinematicMotion::Scripted(Rc::new(circle)));
+        let mut driven = KinematicRigidBody::new(Point3::origin(), UnitQuaternion::identity(),
+                                                 KinematicMotion::Velocity);
+
+        // Driving the body by the derivative of the script should
+        // approximately reproduce the scripted motion
+        let dt = 1e-3;
+        for step in 0 .. 1000 {
+            let t = (step as f64 + 0.5) * dt;
+            driven.velocity = Vector3::new(t.cos(), 0.0, 0.0);
+            driven.angular_velocity = Vector3::new(0.0, 0.0, 1.0);
+            driven.advance(dt);
+            scripted.advance(dt);
+        }
+
+        assert!(norm(&(driven.position - scripted.position)) < 1e-6);
+        assert!((driven.orientation.angle() - 1.0).abs() < 1e-9);
+        assert!((scripted.orientation.angle() - 1.0).abs() < 1e-9);
+        assert!(norm(&(scripted.velocity - driven.velocity)) < 1e-6);
+        assert!(norm(&(scripted.angular_velocity - driven.angular_velocity)) < 1e-9);
+    }
+}
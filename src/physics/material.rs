@@ -0,0 +1,135 @@
+/// Determines how a property of two materials in contact is combined
+/// into a single value.
+///
+/// When the two materials use different rules, the rule that comes last
+/// in the order `Average`, `Min`, `Multiply`, `Max` takes precedence.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CombineRule {
+    Average,
+    Min,
+    Multiply,
+    Max
+}
+
+/// Surface properties of a body, which determine the response to collisions.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PhysicsMaterial {
+    /// The coefficient of restitution, where 0 gives perfectly inelastic
+    /// and 1 gives perfectly elastic collisions.
+    pub restitution: f64,
+
+    /// The coefficient of friction that must be overcome for
+    /// a body at rest to start sliding.
+    pub static_friction: f64,
+
+    /// The coefficient of friction for sliding bodies.
+    pub dynamic_friction: f64,
+
+    pub restitution_combine: CombineRule,
+    pub friction_combine: CombineRule
+}
+
+/// The effective material properties of a contact between two bodies.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ContactMaterial {
+    pub restitution: f64,
+    pub static_friction: f64,
+    pub dynamic_friction: f64
+}
+
+impl CombineRule {
+    pub fn apply(&self, a: f64, b: f64) -> f64 {
+        match *self {
+            CombineRule::Average => 0.5 * (a + b),
+            CombineRule::Min => a.min(b),
+            CombineRule::Multiply => a * b,
+            CombineRule::Max => a.max(b)
+        }
+    }
+}
+
+impl Default for PhysicsMaterial {
+    /// Perfectly elastic and frictionless.
+    fn default() -> Self {
+        PhysicsMaterial {
+            restitution: 1.0,
+            static_friction: 0.0,
+            dynamic_friction: 0.0,
+            restitution_combine: CombineRule::Average,
+            friction_combine: CombineRule::Average
+        }
+    }
+}
+
+impl PhysicsMaterial {
+    pub fn new(restitution: f64, static_friction: f64, dynamic_friction: f64) -> Self {
+        assert!(restitution >= 0.0 && restitution <= 1.0,
+            "Restitution must be in the interval [0, 1].");
+        assert!(static_friction >= 0.0 && dynamic_friction >= 0.0,
+            "Friction coefficients must be non-negative.");
+        PhysicsMaterial {
+            restitution: restitution,
+            static_friction: static_friction,
+            dynamic_friction: dynamic_friction,
+            .. PhysicsMaterial::default()
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn with_restitution_combine(mut self, rule: CombineRule) -> Self {
+        self.restitution_combine = rule;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_friction_combine(mut self, rule: CombineRule) -> Self {
+        self.friction_combine = rule;
+        self
+    }
+
+    /// Combines the properties of two materials in contact.
+    pub fn combine(&self, other: &PhysicsMaterial) -> ContactMaterial {
+        let restitution_rule = self.restitution_combine.max(other.restitution_combine);
+        let friction_rule = self.friction_combine.max(other.friction_combine);
+        ContactMaterial {
+            restitution: restitution_rule.apply(self.restitution, other.restitution),
+            static_friction: friction_rule.apply(self.static_friction, other.static_friction),
+            dynamic_friction: friction_rule.apply(self.dynamic_friction, other.dynamic_friction)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PhysicsMaterial, CombineRule};
+
+    #[test]
+    fn combine_uses_rule_of_highest_precedence() {
+        let a = PhysicsMaterial::new(0.2, 0.6, 0.4);
+        let b = PhysicsMaterial::new(0.8, 0.3, 0.2);
+
+        let average = a.combine(&b);
+        assert_eq!(0.5, average.restitution);
+        assert!((average.static_friction - 0.45).abs() < 1e-12);
+
+        let min = a.with_restitution_combine(CombineRule::Min).combine(&b);
+        assert_eq!(0.2, min.restitution);
+        assert!((min.static_friction - 0.45).abs() < 1e-12);
+
+        let multiply = a.with_friction_combine(CombineRule::Multiply)
+                        .combine(&b.with_friction_combine(CombineRule::Min));
+        assert!((multiply.static_friction - 0.18).abs() < 1e-12);
+        assert!((multiply.dynamic_friction - 0.08).abs() < 1e-12);
+
+        let max = a.with_restitution_combine(CombineRule::Min)
+                   .combine(&b.with_restitution_combine(CombineRule::Max));
+        assert_eq!(0.8, max.restitution);
+    }
+
+    #[test]
+    fn combine_is_symmetric() {
+        let a = PhysicsMaterial::new(0.1, 0.9, 0.5).with_friction_combine(CombineRule::Max);
+        let b = PhysicsMaterial::new(0.7, 0.2, 0.1).with_restitution_combine(CombineRule::Multiply);
+        assert_eq!(a.combine(&b), b.combine(&a));
+    }
+}
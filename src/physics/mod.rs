@@ -4,17 +4,63 @@ pub use self::physics_component::{
     DynamicBodyState,
     StaticRigidBody,
     DynamicRigidBody,
+    SleepThresholds,
     RigidBody
 };
 
+mod kinematic;
+pub use self::kinematic::{KinematicRigidBody, KinematicMotion, Keyframe};
+
 mod physics_engine;
-pub use self::physics_engine::PhysicsEngine;
+pub use self::physics_engine::{PhysicsEngine, PhysicsState};
 
 mod collision_component;
 pub use self::collision_component::*;
 
+mod contact_solver;
+pub use self::contact_solver::ContactImpulse;
+#[cfg(test)]
+mod solver_scene;
+mod islands;
+
+mod diagnostics;
+pub use self::diagnostics::{Diagnostics, Drift};
+
+mod joint;
+pub use self::joint::{Joint, JointKind, JointMotor, joint_frame};
+
 mod collision_engine;
 pub use self::collision_engine::*;
 
+mod material;
+pub use self::material::{PhysicsMaterial, ContactMaterial, CombineRule};
+
 mod force_generator;
-pub use self::force_generator::ForceGenerator;
+pub use self::force_generator::{
+    ForceGenerator,
+    ScopedForceGenerator,
+    ForceScope,
+    Region,
+    GravitationMethod,
+    Falloff
+};
+
+mod gravity;
+mod barnes_hut;
+
+mod integrator;
+pub use self::integrator::{
+    Integrator,
+    AccelerationFunction,
+    SemiImplicitEuler,
+    VelocityVerlet,
+    RungeKutta4,
+    Yoshida4
+};
+
+mod angular_integrator;
+pub use self::angular_integrator::{
+    AngularIntegrator,
+    ExplicitEulerRotation,
+    ImplicitMidpointRotation
+};
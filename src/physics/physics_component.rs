@@ -1,5 +1,6 @@
 use nalgebra;
 use nalgebra::{Point3, Vector3, Matrix3, UnitQuaternion};
+use physics::KinematicRigidBody;
 
 #[derive(Copy, Clone, Debug)]
 pub struct Mass {
@@ -26,7 +27,7 @@ impl Mass {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct DynamicBodyState {
     pub position: Point3<f64>,
     pub orientation: UnitQuaternion<f64>,
@@ -46,13 +47,50 @@ pub struct DynamicRigidBody {
     pub state: DynamicBodyState,
     pub prev_state: DynamicBodyState,
     pub mass: Mass,
-    pub inv_inertia_body: Matrix3<f64>
+    pub inv_inertia_body: Matrix3<f64>,
+
+    /// Force accumulated since the last simulation step, in world coordinates.
+    /// Cleared by the physics engine after each step.
+    pub accumulated_force: Vector3<f64>,
+
+    /// Torque (about the center of mass) accumulated since the last
+    /// simulation step, in world coordinates. Cleared by the physics
+    /// engine after each step.
+    pub accumulated_torque: Vector3<f64>,
+
+    /// Enables continuous collision detection, which prevents the body from
+    /// passing through thin collision models when it moves fast. This is more
+    /// expensive than the regular collision detection, so it is off by default.
+    ///
+    /// The body is swept along with any kinematic bodies in its way. Other dynamic
+    /// bodies are only tested at their current poses, and rotations are not swept,
+    /// so fast bodies may still pass through each other or through spinning models.
+    pub ccd: bool,
+
+    /// The velocities below which the body may fall asleep,
+    /// or `None` if the body should never fall asleep.
+    pub sleep_thresholds: Option<SleepThresholds>,
+
+    /// Whether the body is asleep, in which case it is neither integrated nor moved
+    /// by collisions until its island is disturbed.
+    pub sleeping: bool,
+
+    /// The time for which the body has moved slower than its sleep thresholds.
+    pub sleep_timer: f64
+}
+
+/// The linear and angular speeds below which a dynamic body is considered at rest.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SleepThresholds {
+    pub linear: f64,
+    pub angular: f64
 }
 
 #[derive(Clone, Debug)]
 pub enum RigidBody {
     Static(StaticRigidBody),
-    Dynamic(DynamicRigidBody)
+    Dynamic(DynamicRigidBody),
+    Kinematic(KinematicRigidBody)
 }
 
 impl Default for DynamicBodyState {
@@ -71,14 +109,16 @@ impl RigidBody {
     pub fn position(&self) -> Point3<f64> {
         match self {
             &RigidBody::Static(ref rb) => { rb.position },
-            &RigidBody::Dynamic(ref rb) => { rb.state.position }
+            &RigidBody::Dynamic(ref rb) => { rb.state.position },
+            &RigidBody::Kinematic(ref rb) => { rb.position }
         }
     }
 
     pub fn orientation(&self) -> UnitQuaternion<f64> {
         match self {
             &RigidBody::Static(ref rb) => { rb.orientation },
-            &RigidBody::Dynamic(ref rb) => { rb.state.orientation }
+            &RigidBody::Dynamic(ref rb) => { rb.state.orientation },
+            &RigidBody::Kinematic(ref rb) => { rb.orientation }
         }
     }
 
@@ -95,6 +135,20 @@ impl RigidBody {
             _ => None
         }
     }
+
+    pub fn as_kinematic<'a>(&'a self) -> Option<&'a KinematicRigidBody> {
+        match self {
+            &RigidBody::Kinematic(ref rb) => Some(rb),
+            _ => None
+        }
+    }
+
+    pub fn as_kinematic_mut<'a>(&'a mut self) -> Option<&'a mut KinematicRigidBody> {
+        match self {
+            &mut RigidBody::Kinematic(ref mut rb) => Some(rb),
+            _ => None
+        }
+    }
 }
 
 impl Default for DynamicRigidBody {
@@ -103,7 +157,69 @@ impl Default for DynamicRigidBody {
             state: DynamicBodyState::default(),
             prev_state: DynamicBodyState::default(),
             mass: Mass::zero(),
-            inv_inertia_body: Matrix3::identity()
+            inv_inertia_body: Matrix3::identity(),
+            accumulated_force: nalgebra::zero::<Vector3<_>>(),
+            accumulated_torque: nalgebra::zero::<Vector3<_>>(),
+            ccd: false,
+            sleep_thresholds: Some(SleepThresholds::default()),
+            sleeping: false,
+            sleep_timer: 0.0
         }
     }
 }
+
+impl Default for SleepThresholds {
+    fn default() -> Self {
+        SleepThresholds {
+            linear: 0.05,
+            angular: 0.05
+        }
+    }
+}
+
+impl DynamicRigidBody {
+    /// Applies the given force (in world coordinates) through the center of mass
+    /// of the body for the duration of the next simulation step.
+    pub fn apply_force(&mut self, force: Vector3<f64>) {
+        self.wake_up();
+        self.accumulated_force += force;
+    }
+
+    /// Applies the given force at the given point, both in world coordinates,
+    /// for the duration of the next simulation step. Unless the line of action
+    /// passes through the center of mass, this also gives rise to a torque.
+    pub fn apply_force_at_point(&mut self, force: Vector3<f64>, point: Point3<f64>) {
+        let r = point - self.state.position;
+        self.wake_up();
+        self.accumulated_force += force;
+        self.accumulated_torque += r.cross(&force);
+    }
+
+    /// Applies a pure torque (in world coordinates) to the body
+    /// for the duration of the next simulation step.
+    pub fn apply_torque(&mut self, torque: Vector3<f64>) {
+        self.wake_up();
+        self.accumulated_torque += torque;
+    }
+
+    /// Wakes the body, for example after changing its state directly. The rest of
+    /// its island is woken as well once the body touches it in the next step.
+    pub fn wake_up(&mut self) {
+        self.sleeping = false;
+        self.sleep_timer = 0.0;
+    }
+
+    /// Brings the body to rest, and stops it from being simulated until it is woken.
+    pub fn fall_asleep(&mut self) {
+        self.sleeping = true;
+        self.state.velocity = nalgebra::zero::<Vector3<_>>();
+        self.state.angular_momentum = nalgebra::zero::<Vector3<_>>();
+        self.state.acceleration = nalgebra::zero::<Vector3<_>>();
+        self.prev_state = self.state.clone();
+    }
+
+    pub fn clear_accumulators(&mut self) {
+        self.accumulated_force = nalgebra::zero::<Vector3<_>>();
+        self.accumulated_torque = nalgebra::zero::<Vector3<_>>();
+    }
+}
@@ -1,54 +1,254 @@
-use physics::{Mass, RigidBody, CollisionEngine,
-    CollisionComponentStore, ForceGenerator};
-use nalgebra::{zero, norm_squared, Point3, Vector3, Matrix3, Quaternion, UnitQuaternion};
-use entity::LinearComponentStorage;
+use physics::{Mass, RigidBody, CollisionEngine, CollisionComponentStore,
+    ForceGenerator, ScopedForceGenerator, ForceScope, GravitationMethod, PhysicsMaterial, Joint};
+use physics::contact_solver::ContactImpulse;
+use physics::force_generator::{BodyFrame, GeneratorBodies, attractor_acceleration};
+use physics::gravity::accumulate_pairwise_gravitation;
+use physics::barnes_hut::Octree;
+use physics::islands::{update_sleep, wake_disturbed_islands, wake_bodies_pushed_by_generators};
+use physics::diagnostics::Diagnostics;
+use physics::integrator::{Integrator, VelocityVerlet};
+use physics::angular_integrator::{AngularIntegrator, ImplicitMidpointRotation, world_inverse_inertia};
+use nalgebra::{zero, Point3, Vector3, UnitQuaternion};
+use entity::{Entity, LinearComponentStorage};
+use message::Message;
+use std::collections::HashMap;
 
 pub struct PhysicsEngine {
     // Buffers for intermediate computations
-    // TODO: Move into structs with specialized responsibility,
-    // i.e. LinearMotionIntegrator
+    e: Vec<Entity>,
     x: Vec<Point3<f64>>,
     v: Vec<Vector3<f64>>,
     a: Vec<Vector3<f64>>,
-    a_next: Vec<Vector3<f64>>,
     m: Vec<f64>,
+    f: Vec<Vector3<f64>>,
 
+    // Volumes of the collision models of the bodies being integrated, for buoyancy
+    volumes: Vec<f64>,
+
+    // The bodies integrated in the last step and the forces accumulated on them then,
+    // for which the accelerations stored at the end of the step were computed
+    prev_e: Vec<Entity>,
+    prev_f: Vec<Vector3<f64>>,
+
+    // Orientations, angular velocities and torques from force generators
+    // of the bodies being integrated, which are constant over a step
+    q: Vec<UnitQuaternion<f64>>,
+    w: Vec<Vector3<f64>>,
+    tau: Vec<Vector3<f64>>,
+
+    // Buffer indices of the bodies being integrated, and
+    // the frames of all other bodies, for force generators
+    indices: HashMap<Entity, usize>,
+    others: HashMap<Entity, BodyFrame>,
+
+    // Retained between steps to avoid reallocation
+    octree: Octree,
+    scoped: ScopedBuffers,
+    island_pairs: Vec<(Entity, Entity)>,
+
+    integrator: Box<Integrator>,
+    angular_integrator: Box<AngularIntegrator>,
     collision_engine: CollisionEngine,
+
+    // The latest and the first diagnostics report, while diagnostics are enabled
+    diagnostics_enabled: bool,
+    diagnostics: Option<Diagnostics>,
+    first_diagnostics: Option<Diagnostics>
 }
 
-fn world_inverse_inertia(local_inertia_inv: &Matrix3<f64>, orientation: UnitQuaternion<f64>)
-    -> Matrix3<f64> {
-    let body_to_world = orientation.to_rotation_matrix();
-    let world_to_body = orientation.inverse().to_rotation_matrix();
-    body_to_world * (local_inertia_inv * world_to_body)
+/// The state which the physics engine carries over between steps,
+/// apart from the state of the bodies themselves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhysicsState {
+    /// Impulses from the last step, with which the solver is warm started
+    pub contact_impulses: Vec<ContactImpulse>,
+    pub joint_impulses: Vec<(Entity, Vec<f64>)>,
+
+    /// Pairs of entities in contact, and pairs of (sensor, entity) where the entity was
+    /// inside the sensor, after the last step. Events are published when these change.
+    pub touching: Vec<(Entity, Entity)>,
+    pub triggered: Vec<(Entity, Entity)>,
+
+    /// Pairs of entities which never collide, ordered
+    pub disabled_pairs: Vec<(Entity, Entity)>
 }
 
 impl PhysicsEngine {
     pub fn new() -> Self {
         PhysicsEngine {
+            e: Vec::new(),
             x: Vec::new(),
             v: Vec::new(),
             a: Vec::new(),
-            a_next: Vec::new(),
             m: Vec::new(),
+            f: Vec::new(),
+
+            volumes: Vec::new(),
+
+            prev_e: Vec::new(),
+            prev_f: Vec::new(),
 
+            q: Vec::new(),
+            w: Vec::new(),
+            tau: Vec::new(),
+
+            indices: HashMap::new(),
+            others: HashMap::new(),
+
+            octree: Octree::new(),
+            scoped: ScopedBuffers::new(),
+            island_pairs: Vec::new(),
+
+            integrator: Box::new(VelocityVerlet::new()),
+            angular_integrator: Box::new(ImplicitMidpointRotation::new()),
             collision_engine: CollisionEngine::new(),
+
+            diagnostics_enabled: false,
+            diagnostics: None,
+            first_diagnostics: None
+        }
+    }
+
+    /// Replaces the integrator used for linear motion, which by default is Velocity Verlet.
+    pub fn set_integrator(&mut self, integrator: Box<Integrator>) {
+        self.integrator = integrator;
+    }
+
+    /// Replaces the integrator used for rotational motion,
+    /// which by default is the implicit midpoint rule.
+    pub fn set_angular_integrator(&mut self, integrator: Box<AngularIntegrator>) {
+        self.angular_integrator = integrator;
+    }
+
+    /// Discards the state carried over between steps, such as contacts, warm starting
+    /// impulses and diagnostics, so that a new scene can be simulated. The integrators
+    /// and whether diagnostics are enabled are kept.
+    pub fn reset(&mut self) {
+        self.collision_engine = CollisionEngine::new();
+        self.prev_e.clear();
+        self.reset_diagnostics();
+    }
+
+    /// Removes and returns all messages published by the physics engine,
+    /// such as collision events, since the last call.
+    pub fn drain_messages(&mut self) -> Vec<Message> {
+        self.collision_engine.drain_messages()
+    }
+
+    /// Prevents the two entities from colliding with each other,
+    /// regardless of their collision filters.
+    pub fn disable_collision_between(&mut self, a: Entity, b: Entity) {
+        self.collision_engine.disable_collision_between(a, b);
+    }
+
+    /// Reverts the effect of `disable_collision_between`.
+    #[allow(dead_code)]
+    pub fn enable_collision_between(&mut self, a: Entity, b: Entity) {
+        self.collision_engine.enable_collision_between(a, b);
+    }
+
+    /// Provides access to geometric queries against the collision models.
+    #[allow(dead_code)]
+    pub fn collision_engine(&self) -> &CollisionEngine {
+        &self.collision_engine
+    }
+
+    /// Enables or disables the diagnostics report, which is produced after every
+    /// simulated step while enabled. Since the potential energy of mutual gravitation
+    /// is summed over all pairs of bodies, this may be expensive for large scenes.
+    #[allow(dead_code)]
+    pub fn set_diagnostics_enabled(&mut self, enabled: bool) {
+        self.diagnostics_enabled = enabled;
+        if !enabled {
+            self.reset_diagnostics();
         }
     }
 
+    /// Discards the reports so far, so that drift is measured from the next step onwards.
+    #[allow(dead_code)]
+    pub fn reset_diagnostics(&mut self) {
+        self.diagnostics = None;
+        self.first_diagnostics = None;
+    }
+
+    /// The diagnostics report of the last simulated step, if diagnostics are enabled.
+    #[allow(dead_code)]
+    pub fn diagnostics(&self) -> Option<&Diagnostics> {
+        self.diagnostics.as_ref()
+    }
+
+    /// Captures the state which is carried over between steps, so that a simulation
+    /// may later be resumed from the same point along with the state of its bodies.
+    pub fn save_state(&self) -> PhysicsState {
+        self.collision_engine.state()
+    }
+
+    /// Resumes from a state captured by `save_state`. Diagnostics are reset,
+    /// so that drift is measured from the restored state.
+    pub fn restore_state(&mut self, state: &PhysicsState) {
+        self.collision_engine.restore_state(state);
+        self.prev_e.clear();
+        self.reset_diagnostics();
+    }
+
     pub fn simulate(&mut self,
                     dt: f64,
                     rigid_bodies: &mut LinearComponentStorage<RigidBody>,
                     collision_store: &CollisionComponentStore,
-                    force_generators: &LinearComponentStorage<ForceGenerator>)
+                    force_generators: &LinearComponentStorage<ScopedForceGenerator>,
+                    materials: &LinearComponentStorage<PhysicsMaterial>,
+                    joints: &LinearComponentStorage<Joint>)
     {
         assert!(dt >= 0.0);
+
+        // Bodies connected by force generators, such as springs, belong to the same island
+        wake_bodies_pushed_by_generators(rigid_bodies, force_generators);
+        self.island_pairs.clear();
+        self.island_pairs.extend(force_generators.components().iter()
+                                     .filter_map(|&(ref force, _)| force.generator.connected_bodies()));
+        wake_disturbed_islands(rigid_bodies, &self.island_pairs);
+
         self.populate_buffers(rigid_bodies);
+        self.populate_volumes(collision_store, force_generators);
+        self.compute_generator_torques(force_generators);
         self.integrate_linear_motion(dt, force_generators);
         self.integrate_angular_motion(dt, rigid_bodies);
         self.sync_components_from_buffers(rigid_bodies);
+        clear_accumulators(rigid_bodies);
+        advance_kinematic_bodies(dt, rigid_bodies);
+
+        // Joints are solved along with the contacts
+        self.collision_engine.detect_and_resolve(dt, rigid_bodies, collision_store, materials, joints);
+
+        // Under mutual gravitation, every body pulls on every other body, so a body
+        // at rest may still be needed to attract the others, and none may sleep
+        let allow_sleep = !force_generators.components().iter().any(|&(ref force, _)| {
+            match force.generator {
+                ForceGenerator::MutualGravitation { .. } => true,
+                _ => false
+            }
+        });
+        self.island_pairs.extend_from_slice(self.collision_engine.island_pairs());
+        update_sleep(dt, rigid_bodies, &self.island_pairs, allow_sleep);
+
+        if self.diagnostics_enabled {
+            self.update_diagnostics(rigid_bodies, force_generators);
+        }
+    }
 
-        self.collision_engine.detect_and_resolve(rigid_bodies, collision_store);
+    fn update_diagnostics(&mut self,
+        rigid_bodies: &LinearComponentStorage<RigidBody>,
+        force_generators: &LinearComponentStorage<ScopedForceGenerator>)
+    {
+        let report = Diagnostics::measure(rigid_bodies, force_generators);
+        let report = match (self.diagnostics, self.first_diagnostics) {
+            (Some(previous), Some(first)) => report.with_drift_from(&previous, &first),
+            _ => {
+                self.first_diagnostics = Some(report);
+                report
+            }
+        };
+        self.diagnostics = Some(report);
     }
 
     fn populate_buffers(&mut self, rigid_bodies: &LinearComponentStorage<RigidBody>)
@@ -56,21 +256,64 @@ impl PhysicsEngine {
         self.x.clear();
         self.v.clear();
         self.a.clear();
-        self.a_next.clear();
         self.m.clear();
+        self.f.clear();
+        self.e.clear();
+        self.q.clear();
+        self.w.clear();
+        self.indices.clear();
+        self.others.clear();
 
-        let dynamic_iter = rigid_bodies.components()
-                                  .iter()
-                                  .filter_map(|&(ref rb, _)| rb.as_dynamic());
-
-        for rb in dynamic_iter {
-            self.x.push(rb.state.position);
-            self.v.push(rb.state.velocity);
-            self.a.push(rb.state.acceleration);
-            self.m.push(rb.mass.value());
+        for &(ref rb, entity) in rigid_bodies.components() {
+            match rb.as_dynamic() {
+                Some(rb) if !rb.sleeping => {
+                    self.indices.insert(entity, self.x.len());
+                    self.e.push(entity);
+                    self.x.push(rb.state.position);
+                    self.v.push(rb.state.velocity);
+                    self.a.push(rb.state.acceleration);
+                    self.m.push(rb.mass.value());
+                    self.f.push(rb.accumulated_force);
+                    self.q.push(rb.state.orientation);
+                    self.w.push(world_inverse_inertia(&rb.inv_inertia_body, rb.state.orientation)
+                                * rb.state.angular_momentum);
+                },
+                _ => {
+                    let (velocity, angular_velocity) = match rb.as_kinematic() {
+                        Some(rb) => (rb.velocity, rb.angular_velocity),
+                        None => (zero(), zero())
+                    };
+                    self.others.insert(entity, BodyFrame {
+                        position: rb.position(),
+                        orientation: rb.orientation(),
+                        velocity: velocity,
+                        angular_velocity: angular_velocity
+                    });
+                }
+            }
         }
+    }
+
+    fn populate_volumes(&mut self,
+        collision_store: &CollisionComponentStore,
+        force_generators: &LinearComponentStorage<ScopedForceGenerator>)
+    {
+        // Only buoyancy depends on the volumes, which are costly to compute for meshes
+        let needed = force_generators.components().iter().any(|&(ref force, _)| {
+            match force.generator {
+                ForceGenerator::Buoyancy { .. } => true,
+                _ => false
+            }
+        });
 
-        self.a_next.resize(self.a.len(), zero::<Vector3<f64>>());
+        self.volumes.clear();
+        for &entity in &self.e {
+            let volume = match collision_store.lookup_model(entity) {
+                Some(model) if needed && !model.requires_static_body() => model.volume(),
+                _ => 0.0
+            };
+            self.volumes.push(volume);
+        }
     }
 
     fn sync_components_from_buffers(&self,
@@ -78,12 +321,13 @@ impl PhysicsEngine {
     {
         let dynamic_iter = rigid_bodies.components_mut()
                                   .iter_mut()
-                                  .filter_map(|&mut (ref mut rb, _)| rb.as_dynamic_mut());
+                                  .filter_map(|&mut (ref mut rb, _)| rb.as_dynamic_mut())
+                                  .filter(|rb| !rb.sleeping);
 
-        let iter = izip!(dynamic_iter, &self.x, &self.v, &self.a_next, &self.m);
+        let iter = izip!(dynamic_iter, &self.x, &self.v, &self.a, &self.m);
 
         let mut count = 0;
-        for (rb, x, v, a_next, m) in iter {
+        for (rb, x, v, a, m) in iter {
             rb.prev_state.position = rb.state.position;
             rb.state.position = x.clone();
 
@@ -91,7 +335,7 @@ impl PhysicsEngine {
             rb.state.velocity = v.clone();
 
             rb.prev_state.acceleration = rb.state.acceleration;
-            rb.state.acceleration = a_next.clone();
+            rb.state.acceleration = a.clone();
 
             rb.mass = Mass::new(m.clone());
 
@@ -102,41 +346,78 @@ impl PhysicsEngine {
         assert!(self.x.len() == count);
         assert!(self.v.len() == count);
         assert!(self.a.len() == count);
-        assert!(self.a_next.len() == count);
         assert!(self.m.len() == count);
+        assert!(self.f.len() == count);
     }
 
     fn integrate_linear_motion(&mut self,
         dt: f64,
-        force_generators: &LinearComponentStorage<ForceGenerator>)
+        force_generators: &LinearComponentStorage<ScopedForceGenerator>)
     {
-        // The following is an implementation of Velocity Verlet.
-        // See https://en.wikipedia.org/wiki/Verlet_integration#Velocity_Verlet
-
         assert!(self.x.len() == self.v.len()
             && self.v.len() == self.a.len()
-            && self.a.len() == self.a_next.len()
-            && self.a_next.len() == self.m.len());
+            && self.a.len() == self.m.len()
+            && self.m.len() == self.f.len());
 
-        let num_components = self.x.len();
+        let PhysicsEngine {
+            ref e, ref mut x, ref mut v, ref mut a, ref m, ref f, ref volumes, ref mut prev_e, ref mut prev_f,
+            ref q, ref w, ref indices, ref others, ref mut octree, ref mut scoped, ref mut integrator, ..
+        } = *self;
 
-        // Update positions
-        for i in 0 .. num_components {
-            let ref mut x = self.x[i];
-            let v = self.v[i];
-            let a = self.a[i];
-            *x += dt * v + 0.5 * dt * dt * a;
+        let mut acceleration = |x: &[Point3<f64>], v: &[Vector3<f64>], a: &mut [Vector3<f64>]| {
+            let bodies = GeneratorBodies {
+                entities: e,
+                x: x,
+                v: v,
+                orientations: q,
+                angular_velocities: w,
+                volumes: volumes,
+                indices: indices,
+                others: others
+            };
+            compute_acceleration(&bodies, m, f, force_generators, octree, scoped, a);
+        };
+
+        // The accelerations stored at the end of the previous step still hold, apart from
+        // the change in the accumulated forces, unless other bodies were integrated then,
+        // such as in the first step or when a body has just woken up
+        if prev_e == e {
+            for i in 0 .. a.len() {
+                a[i] += (f[i] - prev_f[i]) / m[i];
+            }
+        } else {
+            acceleration(&x[..], &v[..], &mut a[..]);
         }
+        integrator.integrate(dt, x, v, a, &mut acceleration);
+        prev_e.clone_from(e);
+        prev_f.clone_from(f);
+    }
 
-        // Update acceleration
-        self.compute_acceleration(force_generators);
+    /// Computes the torques exerted by force generators acting off-center,
+    /// which are assumed to be constant over the step, like the accumulated torque.
+    fn compute_generator_torques(&mut self, force_generators: &LinearComponentStorage<ScopedForceGenerator>) {
+        let PhysicsEngine {
+            ref e, ref x, ref v, ref q, ref w, ref volumes, ref indices, ref others, ref mut tau, ..
+        } = *self;
+        let bodies = GeneratorBodies {
+            entities: e,
+            x: x,
+            v: v,
+            orientations: q,
+            angular_velocities: w,
+            volumes: volumes,
+            indices: indices,
+            others: others
+        };
 
-        // Update velocities
-        for i in 0 .. num_components {
-            let ref mut v = self.v[i];
-            let a = self.a[i];
-            let a_next = self.a_next[i];
-            *v += 0.5 * dt * (a + a_next);
+        tau.clear();
+        tau.resize(x.len(), zero());
+        for &(ref force, owner) in force_generators.components() {
+            force.generator.for_each_force(&bodies, |i, generated_force, point| {
+                if force.scope.contains(owner, &bodies, i) {
+                    tau[i] += (point - x[i]).cross(&generated_force);
+                }
+            });
         }
     }
 
@@ -144,66 +425,1328 @@ impl PhysicsEngine {
         dt: f64,
         rigid_bodies: &mut LinearComponentStorage<RigidBody>)
     {
-        // The integration for angular motion is a lot more complicated in general,
-        // so we can't easily apply something similar to the Velocity Verlet algorithm
-        // for linear motion. For now, we just use simple euler integrators instead.
-
-        // TODO: Implement torque accumulators
-
         let dynamic_iter = rigid_bodies.components_mut()
                                 .iter_mut()
-                                .filter_map(|&mut (ref mut rb, _)| rb.as_dynamic_mut());
+                                .filter_map(|&mut (ref mut rb, _)| rb.as_dynamic_mut())
+                                .filter(|rb| !rb.sleeping);
 
-        for rb in dynamic_iter {
+        for (rb, generator_torque) in dynamic_iter.zip(&self.tau) {
             rb.prev_state.orientation = rb.state.orientation;
 
-            let orientation = rb.state.orientation;
-            let inv_inertia_body = rb.inv_inertia_body;
-            let inverse_world_inertia = world_inverse_inertia(&inv_inertia_body, orientation);
-            let angular_momentum = rb.state.angular_momentum;
-            let angular_velocity = inverse_world_inertia * angular_momentum;
-            let angular_velocity_quat = Quaternion::from_parts(0.0, angular_velocity);
+            // The accumulated torque is assumed to be constant over the step
+            self.angular_integrator.integrate(dt,
+                &mut rb.state.orientation,
+                &mut rb.state.angular_momentum,
+                &rb.inv_inertia_body,
+                &(rb.accumulated_torque + generator_torque));
+        }
+    }
+}
+
+/// The bodies in scope of a scoped mutual gravitation generator, gathered for every
+/// evaluation of the accelerations.
+struct ScopedBuffers {
+    indices: Vec<usize>,
+    x: Vec<Point3<f64>>,
+    m: Vec<f64>,
+    a: Vec<Vector3<f64>>
+}
 
-            // The orientation update first makes the quaternion non-unit.
-            // This means that we need to:
-            // 1. Turn the UnitQuaternion into Quaternion by unwrapping
-            // 2. Update the Quaternion instance
-            // 3. Normalize the updated Quaternion into a new UnitQuaternion
-            let orientation = orientation.unwrap();
-            let new_orientation = orientation + 0.5 * dt * angular_velocity_quat * orientation;
-            rb.state.orientation = UnitQuaternion::new_normalize(new_orientation);
+impl ScopedBuffers {
+    fn new() -> Self {
+        ScopedBuffers {
+            indices: Vec::new(),
+            x: Vec::new(),
+            m: Vec::new(),
+            a: Vec::new()
         }
     }
 
-    fn compute_acceleration(&mut self,
-        force_generators: &LinearComponentStorage<ForceGenerator>)
-    {
+    fn clear(&mut self) {
+        self.indices.clear();
+        self.x.clear();
+        self.m.clear();
+        self.a.clear();
+    }
+}
 
-        // TODO: This only takes into account gravity, so perhaps move into a gravity-only function.
-        let num_objects = self.a.len();
+fn compute_acceleration(bodies: &GeneratorBodies,
+                        m: &[f64],
+                        f: &[Vector3<f64>],
+                        force_generators: &LinearComponentStorage<ScopedForceGenerator>,
+                        octree: &mut Octree,
+                        scoped: &mut ScopedBuffers,
+                        a: &mut [Vector3<f64>])
+{
+    let num_objects = a.len();
+    let x = bodies.x;
 
-        const G: f64 = 6.674e-11;
-        for i in 0 .. num_objects {
-            for &(ref gen, _) in force_generators.components() {
-                match gen {
-                    &ForceGenerator::UniformAccelerationField { acceleration } => {
-                        self.a_next[i] += acceleration;
+    // External forces accumulated on the body are assumed to be
+    // constant over the step
+    for i in 0 .. num_objects {
+        a[i] = f[i] / m[i];
+    }
+
+    for &(ref force, owner) in force_generators.components() {
+        let in_scope = |i: usize| force.scope.contains(owner, bodies, i);
+        match force.generator {
+            ForceGenerator::UniformAccelerationField { acceleration } => {
+                for i in 0 .. num_objects {
+                    if in_scope(i) {
+                        a[i] += acceleration;
+                    }
+                }
+            },
+            ForceGenerator::MutualGravitation { g, softening, method } => {
+                if let ForceScope::Global = force.scope {
+                    accumulate_mutual_gravitation(g, softening, method, x, m, octree, a);
+                } else {
+                    // Bodies out of scope neither attract nor are attracted
+                    scoped.clear();
+                    for i in (0 .. num_objects).filter(|&i| in_scope(i)) {
+                        scoped.indices.push(i);
+                        scoped.x.push(x[i]);
+                        scoped.m.push(m[i]);
+                        scoped.a.push(zero());
+                    }
+                    accumulate_mutual_gravitation(g, softening, method, &scoped.x, &scoped.m, octree, &mut scoped.a);
+                    for (&i, a_i) in scoped.indices.iter().zip(&scoped.a) {
+                        a[i] += *a_i;
+                    }
+                }
+            },
+            ForceGenerator::PointAttractor { center, strength, falloff } => {
+                for i in 0 .. num_objects {
+                    if in_scope(i) {
+                        a[i] += attractor_acceleration(center, strength, falloff, x[i]);
                     }
                 }
+            },
+            _ => {
+                force.generator.for_each_force(bodies, |i, generated_force, _| {
+                    if in_scope(i) {
+                        a[i] += generated_force / m[i];
+                    }
+                });
+            }
+        }
+    }
+}
+
+fn accumulate_mutual_gravitation(g: f64,
+                                 softening: f64,
+                                 method: GravitationMethod,
+                                 x: &[Point3<f64>],
+                                 m: &[f64],
+                                 octree: &mut Octree,
+                                 a: &mut [Vector3<f64>])
+{
+    match method {
+        GravitationMethod::Exact => {
+            accumulate_pairwise_gravitation(g, softening, x, m, a);
+        },
+        GravitationMethod::BarnesHut { theta } => {
+            octree.rebuild(x, m);
+            octree.accumulate_gravitation(g, softening, theta, x, m, a);
+        }
+    }
+}
+
+fn advance_kinematic_bodies(dt: f64, rigid_bodies: &mut LinearComponentStorage<RigidBody>) {
+    let kinematic_iter = rigid_bodies.components_mut()
+                            .iter_mut()
+                            .filter_map(|&mut (ref mut rb, _)| rb.as_kinematic_mut());
+
+    for rb in kinematic_iter {
+        rb.advance(dt);
+    }
+}
+
+fn clear_accumulators(rigid_bodies: &mut LinearComponentStorage<RigidBody>) {
+    let dynamic_iter = rigid_bodies.components_mut()
+                            .iter_mut()
+                            .filter_map(|&mut (ref mut rb, _)| rb.as_dynamic_mut());
+
+    for rb in dynamic_iter {
+        rb.clear_accumulators();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PhysicsEngine;
+    use physics::{RigidBody, DynamicRigidBody, StaticRigidBody, DynamicBodyState, Mass,
+        CollisionComponentStore, CollisionModel, CollisionFilter, ForceGenerator, ScopedForceGenerator, ForceScope,
+        Region, GravitationMethod, PhysicsMaterial, Joint, JointKind, joint_frame, KinematicRigidBody, KinematicMotion,
+        SemiImplicitEuler};
+    use entity::{Entity, EntityManager, LinearComponentStorage};
+    use geometry::{Sphere, Cuboid, Plane};
+    use message::Message;
+    use nalgebra::{zero, norm, Point3, Vector3, UnitQuaternion, Unit, Isometry3, Translation3};
+    use std::f64::consts::PI;
+    use std::rc::Rc;
+
+    fn point_mass(position: Point3<f64>,
+                  velocity: Vector3<f64>,
+                  acceleration: Vector3<f64>,
+                  mass: f64) -> RigidBody {
+        let state = DynamicBodyState {
+            position: position,
+            velocity: velocity,
+            acceleration: acceleration,
+            .. DynamicBodyState::default()
+        };
+        RigidBody::Dynamic(DynamicRigidBody {
+            state: state.clone(),
+            prev_state: state,
+            mass: Mass::new(mass),
+            .. DynamicRigidBody::default()
+        })
+    }
+
+    fn position_of(bodies: &LinearComponentStorage<RigidBody>, index: usize) -> Point3<f64> {
+        bodies.components()[index].0.position()
+    }
+
+    struct Scene {
+        entity_manager: EntityManager,
+        bodies: LinearComponentStorage<RigidBody>,
+        generators: LinearComponentStorage<ScopedForceGenerator>,
+        collision: CollisionComponentStore,
+        materials: LinearComponentStorage<PhysicsMaterial>,
+        joints: LinearComponentStorage<Joint>,
+        engine: PhysicsEngine
+    }
+
+    impl Scene {
+        fn new(bodies: Vec<RigidBody>, generators: Vec<ForceGenerator>) -> Scene {
+            let mut entity_manager = EntityManager::new();
+            let mut body_store = LinearComponentStorage::new();
+            let mut generator_store = LinearComponentStorage::new();
+            for body in bodies {
+                body_store.set_component_for_entity(entity_manager.create(), body);
+            }
+            for generator in generators {
+                generator_store.set_component_for_entity(entity_manager.create(), generator.global());
+            }
+
+            Scene {
+                entity_manager: entity_manager,
+                bodies: body_store,
+                generators: generator_store,
+                collision: CollisionComponentStore::new(),
+                materials: LinearComponentStorage::new(),
+                joints: LinearComponentStorage::new(),
+                engine: PhysicsEngine::new()
+            }
+        }
+
+        fn simulate(&mut self, dt: f64, num_steps: usize) {
+            for _ in 0 .. num_steps {
+                self.engine.simulate(dt, &mut self.bodies, &self.collision,
+                                     &self.generators, &self.materials, &self.joints);
+            }
+        }
+
+        fn entity_of(&self, index: usize) -> Entity {
+            self.bodies.components()[index].1
+        }
+    }
+
+    #[test]
+    fn no_mutual_gravitation_without_generator() {
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::new(0.0, 0.0, 0.0), zero(), zero(), 1e12),
+            point_mass(Point3::new(1.0, 0.0, 0.0), zero(), zero(), 1e12)
+        ], vec![]);
+
+        scene.simulate(0.01, 100);
+
+        assert_eq!(Point3::new(0.0, 0.0, 0.0), position_of(&scene.bodies, 0));
+        assert_eq!(Point3::new(1.0, 0.0, 0.0), position_of(&scene.bodies, 1));
+    }
+
+    #[test]
+    fn mutual_gravitation_follows_inverse_square_law() {
+        // A body released from rest close to a much heavier body should initially
+        // accelerate according to g * M / r². We sample the displacement after a
+        // very short time for two different distances.
+        let displacement_after_release = |r: f64| {
+            let mut scene = Scene::new(vec![
+                point_mass(Point3::origin(), zero(), zero(), 1.0),
+                point_mass(Point3::new(r, 0.0, 0.0), zero(), zero(), 1e-9)
+            ], vec![ForceGenerator::MutualGravitation {
+                g: 1.0,
+                softening: 0.0,
+                method: GravitationMethod::Exact
+            }]);
+            scene.simulate(1e-4, 10);
+            r - position_of(&scene.bodies, 1).x
+        };
+
+        let ratio = displacement_after_release(1.0) / displacement_after_release(2.0);
+        assert!((ratio - 4.0).abs() < 1e-3, "Ratio was {}", ratio);
+    }
+
+    #[test]
+    fn coincident_bodies_without_softening_do_not_attract_each_other() {
+        for &method in &[GravitationMethod::Exact, GravitationMethod::BarnesHut { theta: 0.5 }] {
+            let mut scene = Scene::new(vec![
+                point_mass(Point3::origin(), zero(), zero(), 1.0),
+                point_mass(Point3::origin(), zero(), zero(), 1.0),
+                point_mass(Point3::new(10.0, 0.0, 0.0), zero(), zero(), 1e-9)
+            ], vec![ForceGenerator::MutualGravitation { g: 1.0, softening: 0.0, method: method }]);
+            scene.engine.set_diagnostics_enabled(true);
+            scene.simulate(1e-3, 10);
+
+            // The coincident bodies stay together, and still attract the distant body
+            assert_eq!(position_of(&scene.bodies, 0), position_of(&scene.bodies, 1));
+            let x = position_of(&scene.bodies, 0);
+            assert!(norm(&x.coords) < 1e-6, "Position was {:?}", x);
+            let x = position_of(&scene.bodies, 2);
+            assert!(x.x < 10.0 && x.x > 9.99, "Position was {:?}", x);
+            let energy = scene.engine.diagnostics().unwrap().potential_energy;
+            assert!(energy.is_finite(), "Energy was {}", energy);
+        }
+    }
+
+    #[test]
+    fn two_body_circular_orbit_has_expected_period() {
+        // Two bodies in a circular orbit around their common center of mass
+        // have period T = 2π sqrt(r³ / (g (m1 + m2))), where r is their separation.
+        let g: f64 = 1.0;
+        let (m1, m2) = (1.0, 0.5);
+        let r = 2.0;
+        let total_mass = m1 + m2;
+        let relative_speed = (g * total_mass / r).sqrt();
+        let period = 2.0 * PI * (r * r * r / (g * total_mass)).sqrt();
+
+        let x1 = Point3::new(- r * m2 / total_mass, 0.0, 0.0);
+        let x2 = Point3::new(r * m1 / total_mass, 0.0, 0.0);
+        let v1 = Vector3::new(0.0, - relative_speed * m2 / total_mass, 0.0);
+        let v2 = Vector3::new(0.0, relative_speed * m1 / total_mass, 0.0);
+        let a1 = Vector3::new(g * m2 / (r * r), 0.0, 0.0);
+        let a2 = Vector3::new(- g * m1 / (r * r), 0.0, 0.0);
+
+        let mut scene = Scene::new(vec![
+            point_mass(x1, v1, a1, m1),
+            point_mass(x2, v2, a2, m2)
+        ], vec![ForceGenerator::MutualGravitation {
+            g: g,
+            softening: 0.0,
+            method: GravitationMethod::Exact
+        }]);
+
+        let num_steps = 4000;
+        let dt = period / num_steps as f64;
+        for _ in 0 .. 4 {
+            scene.simulate(dt, num_steps / 4);
+            let separation = norm(&(position_of(&scene.bodies, 1) - position_of(&scene.bodies, 0)));
+            assert!((separation - r).abs() < 1e-4, "Separation was {}", separation);
+        }
+
+        assert!(norm(&(position_of(&scene.bodies, 0) - x1)) < 1e-3);
+        assert!(norm(&(position_of(&scene.bodies, 1) - x2)) < 1e-3);
+    }
+
+    #[test]
+    fn diagnostics_report_conserved_quantities_of_eccentric_orbit() {
+        // Two bodies with zero total momentum on an eccentric orbit. The total energy
+        // is ½ m1 v1² + ½ m2 v2² - g m1 m2 / r, and Velocity Verlet should keep it
+        // close to its initial value, while conserving momentum up to rounding.
+        let g = 1.0;
+        let (m1, m2) = (1.0, 0.25);
+        let v1 = Vector3::new(0.0, -0.2, 0.0);
+        let v2 = - v1 * (m1 / m2);
+        let energy = 0.5 * m1 * v1.dot(&v1) + 0.5 * m2 * v2.dot(&v2) - g * m1 * m2 / 1.0;
+
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::new(0.0, 0.0, 0.0), v1, Vector3::new(g * m2, 0.0, 0.0), m1),
+            point_mass(Point3::new(1.0, 0.0, 0.0), v2, Vector3::new(- g * m1, 0.0, 0.0), m2)
+        ], vec![ForceGenerator::MutualGravitation {
+            g: g,
+            softening: 0.0,
+            method: GravitationMethod::Exact
+        }]);
+
+        scene.simulate(1e-3, 1);
+        assert!(scene.engine.diagnostics().is_none());
+
+        scene.engine.set_diagnostics_enabled(true);
+        scene.simulate(1e-3, 1);
+        let first = *scene.engine.diagnostics().unwrap();
+        assert_eq!(0.0, first.drift.energy);
+        assert!((first.total_energy() - energy).abs() < 1e-6, "Energy was {}", first.total_energy());
+        assert!(first.potential_energy < 0.0);
+        assert_eq!(0.0, first.rotational_kinetic_energy);
+
+        scene.simulate(1e-3, 5000);
+        let report = scene.engine.diagnostics().unwrap();
+        assert!(norm(&report.linear_momentum) < 1e-12);
+        assert!(report.drift.linear_momentum < 1e-12);
+        assert!(report.drift.angular_momentum < 1e-9, "Angular momentum drift was {}", report.drift.angular_momentum);
+        assert!(report.drift.relative_energy().unwrap().abs() < 1e-4,
+                "Relative energy drift was {:?}", report.drift.relative_energy());
+        assert!(report.drift.step_energy.abs() <= report.drift.max_step_energy);
+        assert!(report.drift.max_step_energy > 0.0);
+
+        scene.engine.set_diagnostics_enabled(false);
+        assert!(scene.engine.diagnostics().is_none());
+    }
+
+    #[test]
+    fn kepler_orbit_obeys_third_law() {
+        // A light body launched from periapsis with more than circular speed around
+        // a heavy body follows an ellipse whose semi-major axis a is given by the
+        // vis-viva equation, and whose period is T = 2π sqrt(a³ / (g M)).
+        // Half a period later it should be at apoapsis, at distance 2a - r.
+        let g: f64 = 1.0;
+        let big_mass = 1.0;
+        let r_periapsis = 1.0;
+        let speed = 1.2 * (g * big_mass / r_periapsis).sqrt();
+        let a = 1.0 / (2.0 / r_periapsis - speed * speed / (g * big_mass));
+        let period = 2.0 * PI * (a * a * a / (g * big_mass)).sqrt();
+        let r_apoapsis = 2.0 * a - r_periapsis;
+
+        let x0 = Point3::new(r_periapsis, 0.0, 0.0);
+        let v0 = Vector3::new(0.0, speed, 0.0);
+        let a0 = Vector3::new(- g * big_mass / (r_periapsis * r_periapsis), 0.0, 0.0);
+
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::origin(), zero(), zero(), big_mass),
+            point_mass(x0, v0, a0, 1e-10)
+        ], vec![ForceGenerator::MutualGravitation {
+            g: g,
+            softening: 0.0,
+            method: GravitationMethod::Exact
+        }]);
+
+        let num_steps = 20000;
+        let dt = period / num_steps as f64;
+
+        scene.simulate(dt, num_steps / 2);
+        let apoapsis = position_of(&scene.bodies, 1);
+        assert!((apoapsis.x + r_apoapsis).abs() < 1e-3, "Apoapsis was {:?}", apoapsis);
+        assert!(apoapsis.y.abs() < 1e-2);
+
+        scene.simulate(dt, num_steps / 2);
+        let periapsis = position_of(&scene.bodies, 1);
+        assert!(norm(&(periapsis - x0)) < 1e-3, "Periapsis was {:?}", periapsis);
+    }
+
+    /// Two unit spheres moving towards each other, which collide
+    /// after about a second unless collision is prevented.
+    fn approaching_spheres() -> Scene {
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::new(-2.005, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), zero(), 1.0),
+            point_mass(Point3::new(2.005, 0.0, 0.0), Vector3::new(-1.0, 0.0, 0.0), zero(), 1.0)
+        ], vec![]);
+        let sphere = CollisionModel::Sphere(Sphere { center: Point3::origin(), radius: 1.0 });
+        let (a, b) = (scene.entity_of(0), scene.entity_of(1));
+        scene.collision.set_component_model(a, sphere.clone());
+        scene.collision.set_component_model(b, sphere);
+        scene
+    }
+
+    fn assert_spheres_pass_through_each_other(mut scene: Scene) {
+        scene.simulate(0.01, 200);
+        assert!(scene.engine.drain_messages().is_empty());
+        assert!(norm(&(position_of(&scene.bodies, 0) - Point3::new(-0.005, 0.0, 0.0))) < 1e-9);
+        assert!(norm(&(position_of(&scene.bodies, 1) - Point3::new(0.005, 0.0, 0.0))) < 1e-9);
+    }
+
+    fn assert_spheres_collide(mut scene: Scene) {
+        scene.simulate(0.01, 150);
+        let messages = scene.engine.drain_messages();
+        assert!(messages.iter().any(|message| match message {
+            &Message::CollisionStarted { .. } => true,
+            _ => false
+        }), "Messages were {:?}", messages);
+
+        // The spheres bounce off each other
+        assert!(position_of(&scene.bodies, 0).x < -0.5);
+        assert!(position_of(&scene.bodies, 1).x > 0.5);
+    }
+
+    fn applied_forces_scene() -> Scene {
+        let body = point_mass(Point3::new(1.0, 2.0, 3.0), zero(), zero(), 2.0);
+        Scene::new(vec![body], vec![])
+    }
+
+    fn dynamic_body(scene: &mut Scene) -> &mut DynamicRigidBody {
+        scene.bodies.components_mut()[0].0.as_dynamic_mut().unwrap()
+    }
+
+    #[test]
+    fn force_applied_off_center_moves_and_spins_body_in_next_step() {
+        let mut scene = applied_forces_scene();
+        dynamic_body(&mut scene).apply_force_at_point(Vector3::new(0.0, 4.0, 0.0), Point3::new(2.0, 2.0, 3.0));
+        scene.simulate(0.5, 1);
+
+        // Both the linear and the angular effect of the force act over the whole step
+        let rb = dynamic_body(&mut scene).clone();
+        assert!(norm(&(rb.state.velocity - Vector3::new(0.0, 1.0, 0.0))) < 1e-12,
+            "Velocity was {:?}", rb.state.velocity);
+        assert!(norm(&(rb.state.position - Point3::new(1.0, 2.25, 3.0))) < 1e-12,
+            "Position was {:?}", rb.state.position);
+        assert!(norm(&(rb.state.angular_momentum - Vector3::new(0.0, 0.0, 2.0))) < 1e-12,
+            "Angular momentum was {:?}", rb.state.angular_momentum);
+
+        // The force only lasts for a single step
+        scene.simulate(0.5, 1);
+        let rb = dynamic_body(&mut scene).clone();
+        assert!(norm(&(rb.state.velocity - Vector3::new(0.0, 1.0, 0.0))) < 1e-12,
+            "Velocity was {:?}", rb.state.velocity);
+        assert!(norm(&(rb.state.angular_momentum - Vector3::new(0.0, 0.0, 2.0))) < 1e-12,
+            "Angular momentum was {:?}", rb.state.angular_momentum);
+    }
+
+    #[test]
+    fn force_applied_to_moving_body_adds_to_generator_acceleration() {
+        let mut scene = Scene::new(vec![point_mass(Point3::origin(), zero(), zero(), 2.0)], vec![
+            ForceGenerator::UniformAccelerationField { acceleration: Vector3::new(0.0, 0.0, -1.0) }
+        ]);
+        scene.simulate(0.5, 2);
+        dynamic_body(&mut scene).apply_force(Vector3::new(4.0, 0.0, 0.0));
+        scene.simulate(0.5, 1);
+
+        // The field keeps acting while the force is applied, and after it is gone
+        let rb = dynamic_body(&mut scene).clone();
+        assert!(norm(&(rb.state.velocity - Vector3::new(1.0, 0.0, -1.5))) < 1e-12,
+            "Velocity was {:?}", rb.state.velocity);
+        scene.simulate(0.5, 1);
+        let rb = dynamic_body(&mut scene).clone();
+        assert!(norm(&(rb.state.velocity - Vector3::new(1.0, 0.0, -2.0))) < 1e-12,
+            "Velocity was {:?}", rb.state.velocity);
+        assert!(norm(&(rb.state.acceleration - Vector3::new(0.0, 0.0, -1.0))) < 1e-12,
+            "Acceleration was {:?}", rb.state.acceleration);
+    }
+
+    #[test]
+    fn pure_torque_changes_only_angular_momentum() {
+        let mut scene = applied_forces_scene();
+        dynamic_body(&mut scene).apply_torque(Vector3::new(1.0, 0.0, -2.0));
+        scene.simulate(0.5, 1);
+
+        let rb = dynamic_body(&mut scene).clone();
+        assert!(norm(&(rb.state.angular_momentum - Vector3::new(0.5, 0.0, -1.0))) < 1e-12,
+            "Angular momentum was {:?}", rb.state.angular_momentum);
+        assert_eq!(Vector3::new(0.0, 0.0, 0.0), rb.state.velocity);
+        assert_eq!(Point3::new(1.0, 2.0, 3.0), rb.state.position);
+    }
+
+    #[test]
+    fn accumulators_are_cleared_by_simulation_step() {
+        let mut scene = applied_forces_scene();
+        dynamic_body(&mut scene).apply_force_at_point(Vector3::new(1.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0));
+        dynamic_body(&mut scene).apply_torque(Vector3::new(3.0, 2.0, 1.0));
+        scene.simulate(0.01, 1);
+
+        let rb = dynamic_body(&mut scene).clone();
+        assert_eq!(Vector3::new(0.0, 0.0, 0.0), rb.accumulated_force);
+        assert_eq!(Vector3::new(0.0, 0.0, 0.0), rb.accumulated_torque);
+    }
+
+    #[test]
+    fn reset_keeps_chosen_integrator() {
+        let mut scene = Scene::new(vec![point_mass(Point3::origin(), zero(), zero(), 1.0)], vec![
+            ForceGenerator::UniformAccelerationField { acceleration: Vector3::new(0.0, 0.0, -1.0) }
+        ]);
+        scene.engine.set_integrator(Box::new(SemiImplicitEuler));
+        scene.engine.reset();
+        scene.simulate(0.5, 1);
+
+        // Velocity Verlet would have moved the body by half as much
+        assert_eq!(Point3::new(0.0, 0.0, -0.25), position_of(&scene.bodies, 0));
+    }
+
+    #[test]
+    fn colliding_spheres_publish_collision_events() {
+        let mut scene = approaching_spheres();
+        let (a, b) = (scene.entity_of(0), scene.entity_of(1));
+
+        let mut messages = Vec::new();
+        for _ in 0 .. 200 {
+            scene.simulate(0.01, 1);
+            messages.extend(scene.engine.drain_messages());
+        }
+
+        assert_eq!(2, messages.len(), "Messages were {:?}", messages);
+        match messages[0] {
+            Message::CollisionStarted { a: first, b: second, point, normal, impulse } => {
+                assert_eq!((a, b), (first, second));
+                assert!(norm(&point.coords) < 1e-2);
+                assert!(norm(&(normal - Vector3::new(1.0, 0.0, 0.0))) < 1e-9);
+
+                // The collision is perfectly elastic, so the relative velocity is reversed
+                assert!((impulse - 2.0).abs() < 1e-9, "Impulse was {}", impulse);
+            },
+            ref message => panic!("Unexpected message {:?}", message)
+        }
+        match messages[1] {
+            Message::CollisionEnded { a: first, b: second } => assert_eq!((a, b), (first, second)),
+            ref message => panic!("Unexpected message {:?}", message)
+        }
+    }
+
+    #[test]
+    fn debris_does_not_collide_with_debris() {
+        let mut scene = approaching_spheres();
+        let debris = CollisionFilter::default().member_of(&[1]).with_blacklist(&[1]);
+        let (a, b) = (scene.entity_of(0), scene.entity_of(1));
+        scene.collision.set_component_filter(a, debris);
+        scene.collision.set_component_filter(b, debris);
+        assert_spheres_pass_through_each_other(scene);
+    }
+
+    #[test]
+    fn disabled_pairs_do_not_collide() {
+        let mut scene = approaching_spheres();
+        let (a, b) = (scene.entity_of(0), scene.entity_of(1));
+        scene.engine.disable_collision_between(b, a);
+        assert_spheres_pass_through_each_other(scene);
+    }
+
+    #[test]
+    fn filters_changed_during_simulation_take_effect() {
+        let mut scene = approaching_spheres();
+        let debris = CollisionFilter::default().member_of(&[1]).with_blacklist(&[1]);
+        let (a, b) = (scene.entity_of(0), scene.entity_of(1));
+        scene.collision.set_component_filter(a, debris);
+        scene.collision.set_component_filter(b, debris);
+        scene.simulate(0.01, 50);
+
+        // The spheres are still apart, and collide once they are no longer debris
+        scene.collision.set_component_filter(a, CollisionFilter::default());
+        scene.collision.set_component_filter(b, CollisionFilter::default());
+        assert_spheres_collide(scene);
+    }
+
+    #[test]
+    fn sensor_turned_back_into_solid_model_collides() {
+        let mut scene = approaching_spheres();
+        let b = scene.entity_of(1);
+        scene.collision.set_component_sensor(b, true);
+        scene.simulate(0.01, 50);
+
+        scene.collision.set_component_sensor(b, false);
+        assert_spheres_collide(scene);
+    }
+
+    #[test]
+    fn sensor_reports_entering_and_exiting_bodies_without_affecting_them() {
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::new(-3.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), zero(), 1.0)
+        ], vec![]);
+        let body = scene.entity_of(0);
+        let sensor = scene.entity_manager.create();
+        scene.collision.set_component_model(body,
+            CollisionModel::Sphere(Sphere { center: Point3::origin(), radius: 0.5 }));
+
+        // The sensor has no rigid body, so it stays at its pose, which
+        // rotates the center of its model onto the x axis at x = 1
+        scene.collision.set_component_model(sensor,
+            CollisionModel::Sphere(Sphere { center: Point3::new(0.0, 0.0, 0.5), radius: 1.0 }));
+        scene.collision.set_component_sensor(sensor, true);
+        let rotation = UnitQuaternion::from_axis_angle(&Unit::new_normalize(Vector3::new(0.0, 1.0, 0.0)), 0.5 * PI);
+        scene.collision.set_component_pose(sensor,
+            Isometry3::from_parts(Translation3::new(0.5, 0.0, 0.0), rotation));
+
+        // The body should enter the sensor at t = 2.5 and leave it at t = 5.5
+        let mut messages = Vec::new();
+        for step in 0 .. 800 {
+            scene.simulate(0.01, 1);
+            let t = (step + 1) as f64 * 0.01;
+            messages.extend(scene.engine.drain_messages().into_iter().map(|m| (t, m)));
+        }
+
+        assert_eq!(2, messages.len(), "Messages were {:?}", messages);
+        match messages[0] {
+            (t, Message::TriggerEntered { sensor: s, entity: e }) => {
+                assert_eq!((sensor, body), (s, e));
+                assert!((t - 2.5).abs() < 0.05, "Entered at t = {}", t);
+            },
+            ref message => panic!("Unexpected message {:?}", message)
+        }
+        match messages[1] {
+            (t, Message::TriggerExited { sensor: s, entity: e }) => {
+                assert_eq!((sensor, body), (s, e));
+                assert!((t - 5.5).abs() < 0.05, "Exited at t = {}", t);
+            },
+            ref message => panic!("Unexpected message {:?}", message)
+        }
+
+        let rb = scene.bodies.components()[0].0.as_dynamic().unwrap().clone();
+        assert!(norm(&(rb.state.velocity - Vector3::new(1.0, 0.0, 0.0))) < 1e-12);
+        assert!(norm(&(rb.state.position - Point3::new(5.0, 0.0, 0.0))) < 1e-9);
+    }
+
+    #[test]
+    fn falling_sphere_comes_to_rest_on_ground_plane() {
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::new(0.0, 0.0, 2.0), zero(), zero(), 1.0),
+            RigidBody::Static(StaticRigidBody {
+                position: Point3::origin(),
+                orientation: UnitQuaternion::identity()
+            })
+        ], vec![
+            ForceGenerator::UniformAccelerationField { acceleration: Vector3::new(0.0, 0.0, -9.81) }
+        ]);
+        let (sphere, ground) = (scene.entity_of(0), scene.entity_of(1));
+        scene.collision.set_component_model(sphere,
+            CollisionModel::Sphere(Sphere { center: Point3::origin(), radius: 0.5 }));
+        scene.collision.set_component_model(ground,
+            CollisionModel::Plane(Plane { point: Point3::origin(), normal: Vector3::new(0.0, 0.0, 1.0) }));
+        scene.materials.set_component_for_entity(sphere, PhysicsMaterial::new(0.0, 0.5, 0.5));
+
+        scene.simulate(0.01, 300);
+
+        let rb = scene.bodies.components()[0].0.as_dynamic().unwrap().clone();
+        assert!((rb.state.position.z - 0.5).abs() < 0.02, "Position was {:?}", rb.state.position);
+        assert!(norm(&rb.state.velocity) < 1e-2, "Velocity was {:?}", rb.state.velocity);
+    }
+
+    #[test]
+    fn simulation_resumes_identically_from_saved_state() {
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::new(0.0, 0.0, 1.0), Vector3::new(0.5, 0.0, 0.0), zero(), 1.0),
+            RigidBody::Static(StaticRigidBody {
+                position: Point3::origin(),
+                orientation: UnitQuaternion::identity()
+            })
+        ], vec![
+            ForceGenerator::UniformAccelerationField { acceleration: Vector3::new(0.0, 0.0, -9.81) }
+        ]);
+        let (sphere, ground) = (scene.entity_of(0), scene.entity_of(1));
+        scene.collision.set_component_model(sphere,
+            CollisionModel::Sphere(Sphere { center: Point3::origin(), radius: 0.5 }));
+        scene.collision.set_component_model(ground,
+            CollisionModel::Plane(Plane { point: Point3::origin(), normal: Vector3::new(0.0, 0.0, 1.0) }));
+
+        // Save while the sphere slides along the ground, so that the warm started
+        // impulses and the pairs in contact matter for the next step
+        scene.simulate(0.01, 50);
+        scene.engine.drain_messages();
+        let state = scene.engine.save_state();
+        assert!(!state.contact_impulses.is_empty());
+        assert_eq!(vec![(sphere, ground)], state.touching);
+        let mut saved_bodies = LinearComponentStorage::new();
+        for &(ref rb, entity) in scene.bodies.components() {
+            saved_bodies.set_component_for_entity(entity, rb.clone());
+        }
+
+        scene.simulate(0.01, 20);
+        let messages = scene.engine.drain_messages();
+
+        let mut engine = PhysicsEngine::new();
+        engine.restore_state(&state);
+        for _ in 0 .. 20 {
+            engine.simulate(0.01, &mut saved_bodies, &scene.collision,
+                            &scene.generators, &scene.materials, &scene.joints);
+        }
+
+        let original = scene.bodies.components()[0].0.as_dynamic().unwrap();
+        let resumed = saved_bodies.components()[0].0.as_dynamic().unwrap();
+        assert_eq!(original.state.position, resumed.state.position);
+        assert_eq!(original.state.velocity, resumed.state.velocity);
+        assert_eq!(original.state.angular_momentum, resumed.state.angular_momentum);
+        assert_eq!(messages.len(), engine.drain_messages().len());
+    }
+
+    #[test]
+    fn queries_find_collision_models() {
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::new(0.0, 0.0, 0.0), zero(), zero(), 1.0),
+            point_mass(Point3::new(5.0, 0.0, 0.0), zero(), zero(), 1.0)
+        ], vec![]);
+        let (near, far) = (scene.entity_of(0), scene.entity_of(1));
+        let sphere = CollisionModel::Sphere(Sphere { center: Point3::origin(), radius: 1.0 });
+        scene.collision.set_component_model(near, sphere.clone());
+        scene.collision.set_component_model(far, sphere);
+        scene.collision.set_component_filter(near, CollisionFilter::default().member_of(&[0]));
+        scene.collision.set_component_filter(far, CollisionFilter::default().member_of(&[1]));
+        scene.simulate(0.01, 1);
+
+        let queries = scene.engine.collision_engine();
+        let all = CollisionFilter::default();
+        let origin = Point3::new(-5.0, 0.0, 0.0);
+        let direction = Vector3::new(2.0, 0.0, 0.0);
+
+        let hit = queries.raycast(origin, direction, 100.0, &all).unwrap();
+        assert_eq!(near, hit.entity);
+        assert!((hit.distance - 4.0).abs() < 1e-9);
+        assert!(norm(&(hit.point - Point3::new(-1.0, 0.0, 0.0))) < 1e-9);
+        assert!(norm(&(hit.normal - Vector3::new(-1.0, 0.0, 0.0))) < 1e-9);
+
+        let hits = queries.raycast_all(origin, direction, 100.0, &all);
+        assert_eq!(vec![near, far], hits.iter().map(|hit| hit.entity).collect::<Vec<_>>());
+        assert!(queries.raycast(origin, direction, 3.9, &all).is_none());
+
+        // Rays only hit models that interact with the filter
+        let only_group_one = CollisionFilter::default().with_whitelist(&[1]);
+        assert_eq!(far, queries.raycast(origin, direction, 100.0, &only_group_one).unwrap().entity);
+
+        assert_eq!(vec![far], queries.entities_containing_point(Point3::new(5.5, 0.0, 0.0), &all));
+        assert!(queries.entities_containing_point(Point3::new(2.5, 0.0, 0.0), &all).is_empty());
+
+        let mut overlapping = queries.entities_overlapping_sphere(Point3::new(2.5, 0.0, 0.0), 1.6, &all);
+        overlapping.sort();
+        assert_eq!(vec![near, far], overlapping);
+        assert!(queries.entities_overlapping_sphere(Point3::new(2.5, 0.0, 0.0), 1.4, &all).is_empty());
+
+        // The corner of the box is close to, but outside of, the first sphere
+        let overlapping = queries.entities_overlapping_box(Point3::new(0.8, 0.8, 0.8),
+                                                           Point3::new(2.0, 2.0, 2.0), &all);
+        assert!(overlapping.is_empty());
+        let overlapping = queries.entities_overlapping_box(Point3::new(0.5, 0.5, -2.0),
+                                                           Point3::new(2.0, 2.0, 2.0), &all);
+        assert_eq!(vec![near], overlapping);
+    }
+
+    #[test]
+    fn queries_find_rotated_models_and_models_around_ray_origin() {
+        // A rod along the x axis of a body which is turned to point along the y axis
+        let mut scene = Scene::new(vec![
+            RigidBody::Static(StaticRigidBody {
+                position: Point3::origin(),
+                orientation: UnitQuaternion::from_axis_angle(&Unit::new_normalize(Vector3::new(0.0, 0.0, 1.0)), 0.5 * PI)
+            })
+        ], vec![]);
+        let rod = scene.entity_of(0);
+        scene.collision.set_component_model(rod, CollisionModel::Cuboid(Cuboid {
+            center: Point3::origin(),
+            half_size: Vector3::new(2.0, 0.1, 0.1),
+            rotation: UnitQuaternion::identity()
+        }));
+        scene.simulate(0.01, 1);
+
+        let queries = scene.engine.collision_engine();
+        let all = CollisionFilter::default();
+
+        let hit = queries.raycast(Point3::new(0.0, -5.0, 0.0), Vector3::new(0.0, 1.0, 0.0), 100.0, &all).unwrap();
+        assert_eq!(rod, hit.entity);
+        assert!((hit.distance - 3.0).abs() < 1e-9, "Distance was {}", hit.distance);
+        assert!(norm(&(hit.normal - Vector3::new(0.0, -1.0, 0.0))) < 1e-9, "Normal was {:?}", hit.normal);
+        assert!(queries.raycast(Point3::new(-5.0, 1.0, 0.0), Vector3::new(1.0, 0.0, 0.0), 100.0, &all)
+            .map_or(false, |hit| (hit.distance - 4.9).abs() < 1e-9));
+        assert_eq!(vec![rod], queries.entities_containing_point(Point3::new(0.0, 1.5, 0.0), &all));
+        assert!(queries.entities_containing_point(Point3::new(1.5, 0.0, 0.0), &all).is_empty());
+
+        // Rays starting inside the rod hit it right away, in any direction
+        for direction in &[Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, -1.0, 0.0)] {
+            let hit = queries.raycast(Point3::new(0.0, 1.0, 0.0), *direction, 100.0, &all).unwrap();
+            assert_eq!(rod, hit.entity);
+            assert_eq!(0.0, hit.distance);
+            assert!(norm(&(hit.point - Point3::new(0.0, 1.0, 0.0))) < 1e-9, "Point was {:?}", hit.point);
+        }
+
+        assert!(queries.raycast_all(Point3::new(0.0, 1.0, 0.0), zero(), 100.0, &all).is_empty());
+
+        // Rays with non-finite coordinates hit nothing, rather than failing to order the hits
+        let nan = ::std::f64::NAN;
+        assert!(queries.raycast_all(Point3::new(0.0, nan, 0.0), Vector3::new(0.0, 1.0, 0.0), 100.0, &all).is_empty());
+        assert!(queries.raycast_all(Point3::new(0.0, -5.0, 0.0), Vector3::new(0.0, nan, 0.0), 100.0, &all).is_empty());
+        assert!(queries.raycast_all(Point3::new(0.0, -5.0, 0.0), Vector3::new(0.0, ::std::f64::INFINITY, 0.0), 100.0, &all)
+            .is_empty());
+    }
+
+    #[test]
+    fn bodies_connected_by_joint_do_not_collide() {
+        // Two overlapping spheres, which would be pushed apart if they collided
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::new(-0.5, 0.0, 0.0), zero(), zero(), 1.0),
+            point_mass(Point3::new(0.5, 0.0, 0.0), zero(), zero(), 1.0)
+        ], vec![]);
+        let (a, b) = (scene.entity_of(0), scene.entity_of(1));
+        let sphere = CollisionModel::Sphere(Sphere { center: Point3::origin(), radius: 1.0 });
+        scene.collision.set_component_model(a, sphere.clone());
+        scene.collision.set_component_model(b, sphere);
+
+        let axis = Vector3::new(1.0, 0.0, 0.0);
+        let joint = scene.entity_manager.create();
+        scene.joints.set_component_for_entity(joint, Joint::new(
+            a, joint_frame(Point3::new(0.5, 0.0, 0.0), &axis),
+            b, joint_frame(Point3::new(-0.5, 0.0, 0.0), &axis),
+            JointKind::BallSocket));
+
+        scene.simulate(0.01, 100);
+        assert!(scene.engine.drain_messages().is_empty());
+        assert!(norm(&(position_of(&scene.bodies, 0) - Point3::new(-0.5, 0.0, 0.0))) < 1e-9);
+        assert!(norm(&(position_of(&scene.bodies, 1) - Point3::new(0.5, 0.0, 0.0))) < 1e-9);
+
+        // Unless collisions are explicitly enabled
+        let connected = scene.joints.components()[0].0.with_collision_between_bodies();
+        scene.joints.set_component_for_entity(joint, connected);
+        scene.simulate(0.01, 1);
+        match scene.engine.drain_messages().first() {
+            Some(&Message::CollisionStarted { .. }) => {},
+            message => panic!("Unexpected message {:?}", message)
+        }
+    }
+
+    #[test]
+    fn kinematic_platform_lifts_resting_sphere() {
+        let platform = KinematicRigidBody {
+            velocity: Vector3::new(0.0, 0.0, 1.0),
+            .. KinematicRigidBody::new(Point3::origin(), UnitQuaternion::identity(), KinematicMotion::Velocity)
+        };
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::new(0.0, 0.0, 0.6), zero(), zero(), 1.0),
+            RigidBody::Kinematic(platform)
+        ], vec![
+            ForceGenerator::UniformAccelerationField { acceleration: Vector3::new(0.0, 0.0, -9.81) }
+        ]);
+        let (sphere, platform) = (scene.entity_of(0), scene.entity_of(1));
+        scene.collision.set_component_model(sphere,
+            CollisionModel::Sphere(Sphere { center: Point3::origin(), radius: 0.5 }));
+        scene.collision.set_component_model(platform, CollisionModel::Cuboid(Cuboid {
+            center: Point3::origin(),
+            half_size: Vector3::new(2.0, 2.0, 0.1),
+            rotation: UnitQuaternion::identity()
+        }));
+        scene.materials.set_component_for_entity(sphere, PhysicsMaterial::new(0.0, 0.5, 0.5));
+
+        scene.simulate(0.01, 100);
+
+        // The platform is unaffected by the weight of the sphere
+        assert!(norm(&(position_of(&scene.bodies, 1) - Point3::new(0.0, 0.0, 1.0))) < 1e-9);
+        let rb = scene.bodies.components()[0].0.as_dynamic().unwrap().clone();
+        assert!((rb.state.position.z - 1.6).abs() < 0.02, "Position was {:?}", rb.state.position);
+        assert!(norm(&(rb.state.velocity - Vector3::new(0.0, 0.0, 1.0))) < 0.05,
+            "Velocity was {:?}", rb.state.velocity);
+    }
+
+    #[test]
+    fn resting_spheres_fall_asleep_until_disturbed() {
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::new(0.0, 0.0, 0.5), zero(), zero(), 1.0),
+            point_mass(Point3::new(-3.0, 0.0, 0.5), zero(), zero(), 1.0),
+            RigidBody::Static(StaticRigidBody {
+                position: Point3::origin(),
+                orientation: UnitQuaternion::identity()
+            })
+        ], vec![
+            ForceGenerator::UniformAccelerationField { acceleration: Vector3::new(0.0, 0.0, -9.81) }
+        ]);
+        let sphere = CollisionModel::Sphere(Sphere { center: Point3::origin(), radius: 0.5 });
+        let (target, projectile, ground) = (scene.entity_of(0), scene.entity_of(1), scene.entity_of(2));
+        scene.collision.set_component_model(target, sphere.clone());
+        scene.collision.set_component_model(projectile, sphere);
+        scene.collision.set_component_model(ground,
+            CollisionModel::Plane(Plane { point: Point3::origin(), normal: Vector3::new(0.0, 0.0, 1.0) }));
+
+        let sleeping = |scene: &Scene, index: usize| scene.bodies.components()[index].0.as_dynamic().unwrap().sleeping;
+        scene.simulate(0.01, 100);
+        assert!(sleeping(&scene, 0) && sleeping(&scene, 1));
+
+        // Sleeping bodies are not moved by the gravity they rest against
+        let target_position = position_of(&scene.bodies, 0);
+        scene.simulate(0.01, 100);
+        assert_eq!(target_position, position_of(&scene.bodies, 0));
+
+        // Pushing the projectile wakes it, but not the target it has not reached yet
+        scene.bodies.components_mut()[1].0.as_dynamic_mut().unwrap().apply_force(Vector3::new(2000.0, 0.0, 0.0));
+        scene.simulate(0.01, 1);
+        assert!(sleeping(&scene, 0) && !sleeping(&scene, 1));
+
+        // Until the projectile hits it
+        scene.simulate(0.01, 50);
+        assert!(!sleeping(&scene, 0));
+        assert!(position_of(&scene.bodies, 0).x > target_position.x + 0.1,
+            "Position was {:?}", position_of(&scene.bodies, 0));
+    }
+
+    #[test]
+    fn sleeping_bodies_are_woken_by_thrust_but_not_by_drag() {
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::origin(), zero(), zero(), 1.0),
+            point_mass(Point3::new(10.0, 0.0, 0.0), zero(), zero(), 1.0)
+        ], vec![
+            ForceGenerator::Drag { linear: 1.0, quadratic: 1.0 }
+        ]);
+        let ship = scene.entity_of(0);
+        let thruster = ForceGenerator::Thrust { force: Vector3::new(1.0, 0.0, 0.0), point: Point3::origin() };
+        scene.generators.set_component_for_entity(ship, thruster.scoped(ForceScope::OwnEntity));
+        for &mut (ref mut rb, _) in scene.bodies.components_mut().iter_mut() {
+            rb.as_dynamic_mut().unwrap().fall_asleep();
+        }
+
+        scene.simulate(0.01, 1);
+
+        let sleeping = |scene: &Scene, index: usize| scene.bodies.components()[index].0.as_dynamic().unwrap().sleeping;
+        assert!(!sleeping(&scene, 0) && sleeping(&scene, 1));
+        assert!(position_of(&scene.bodies, 0).x > 0.0);
+        assert_eq!(Point3::new(10.0, 0.0, 0.0), position_of(&scene.bodies, 1));
+    }
+
+    #[test]
+    fn scoped_generators_only_act_on_bodies_in_scope() {
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::new(0.0, 0.0, 0.0), zero(), zero(), 1.0),
+            point_mass(Point3::new(10.0, 0.0, 0.0), zero(), zero(), 1.0),
+            point_mass(Point3::new(0.0, 10.0, 0.0), zero(), zero(), 1.0)
+        ], vec![]);
+        let (ship, target) = (scene.entity_of(0), scene.entity_of(1));
+
+        // A thruster attached to the entity it propels
+        let thruster = ForceGenerator::Thrust { force: Vector3::new(1.0, 0.0, 0.0), point: Point3::origin() };
+        scene.generators.set_component_for_entity(ship, thruster.scoped(ForceScope::OwnEntity));
+
+        let generators = vec![
+            ForceGenerator::UniformAccelerationField { acceleration: Vector3::new(0.0, 0.0, -1.0) }
+                .scoped(ForceScope::Bodies(vec![target])),
+            ForceGenerator::UniformAccelerationField { acceleration: Vector3::new(0.0, 1.0, 0.0) }
+                .scoped(ForceScope::Region(Region::Sphere(Sphere { center: Point3::new(0.0, 10.0, 0.0), radius: 1.0 })))
+        ];
+        for generator in generators {
+            let entity = scene.entity_manager.create();
+            scene.generators.set_component_for_entity(entity, generator);
+        }
+
+        scene.simulate(0.01, 100);
+
+        assert!(norm(&(position_of(&scene.bodies, 0) - Point3::new(0.5, 0.0, 0.0))) < 1e-9);
+        assert!(norm(&(position_of(&scene.bodies, 1) - Point3::new(10.0, 0.0, -0.5))) < 1e-9);
+        assert!(norm(&(position_of(&scene.bodies, 2) - Point3::new(0.0, 10.5, 0.0))) < 1e-9);
+    }
+
+    #[test]
+    fn scoped_mutual_gravitation_ignores_bodies_out_of_scope() {
+        let heavy_position = Point3::new(0.0, 2.0, 0.0);
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::new(0.0, 0.0, 0.0), zero(), zero(), 1.0),
+            point_mass(Point3::new(1.0, 0.0, 0.0), zero(), zero(), 1.0),
+            point_mass(heavy_position, zero(), zero(), 1000.0)
+        ], vec![]);
+        let pair = vec![scene.entity_of(0), scene.entity_of(1)];
+        let gravitation = ForceGenerator::MutualGravitation {
+            g: 1.0,
+            softening: 0.0,
+            method: GravitationMethod::Exact
+        };
+        let generator = scene.entity_manager.create();
+        scene.generators.set_component_for_entity(generator, gravitation.scoped(ForceScope::Bodies(pair)));
+
+        scene.simulate(1e-3, 10);
+
+        // The pair only attract each other, and the heavy body is left alone
+        let (a, b) = (position_of(&scene.bodies, 0), position_of(&scene.bodies, 1));
+        assert!(a.x > 0.0 && b.x < 1.0);
+        assert_eq!(0.0, a.y);
+        assert_eq!(0.0, b.y);
+        assert_eq!(heavy_position, position_of(&scene.bodies, 2));
+    }
+
+    #[test]
+    fn damped_spring_oscillates_with_decaying_amplitude() {
+        // A unit mass released from rest at an extension x0 of a damped spring with
+        // stiffness k and damping c has displacement
+        // x0 exp(-γt) (cos ωt + γ/ω sin ωt), where γ = c / 2 and ω² = k - γ².
+        let (k, c, x0) = (10.0, 0.4, 0.5);
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::new(1.0 + x0, 0.0, 0.0), zero(), zero(), 1.0),
+            RigidBody::Static(StaticRigidBody {
+                position: Point3::origin(),
+                orientation: UnitQuaternion::identity()
+            })
+        ], vec![]);
+        let spring = ForceGenerator::Spring {
+            body1: scene.entity_of(0),
+            anchor1: Point3::origin(),
+            body2: scene.entity_of(1),
+            anchor2: Point3::origin(),
+            rest_length: 1.0,
+            stiffness: k,
+            damping: c
+        };
+        let generator = scene.entity_manager.create();
+        scene.generators.set_component_for_entity(generator, spring.global());
+
+        let (gamma, omega) = (c / 2.0, (k - c * c / 4.0).sqrt());
+        let dt = 1e-3;
+        for step in 1 .. 6 {
+            scene.simulate(dt, 1000);
+            let t = step as f64;
+            let expected = x0 * (- gamma * t).exp() * ((omega * t).cos() + gamma / omega * (omega * t).sin());
+            let displacement = position_of(&scene.bodies, 0).x - 1.0;
+            assert!((displacement - expected).abs() < 1e-3,
+                "Displacement was {}, expected {}", displacement, expected);
+        }
+    }
+
+    #[test]
+    fn spring_anchored_off_center_exerts_torque() {
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::new(2.0, 0.0, 0.0), zero(), zero(), 1.0),
+            RigidBody::Static(StaticRigidBody {
+                position: Point3::origin(),
+                orientation: UnitQuaternion::identity()
+            })
+        ], vec![]);
+        let spring = ForceGenerator::Spring {
+            body1: scene.entity_of(0),
+            anchor1: Point3::new(0.0, 1.0, 0.0),
+            body2: scene.entity_of(1),
+            anchor2: Point3::new(0.0, 1.0, 0.0),
+            rest_length: 1.0,
+            stiffness: 10.0,
+            damping: 0.0
+        };
+        let generator = scene.entity_manager.create();
+        scene.generators.set_component_for_entity(generator, spring.global());
+        scene.simulate(0.01, 1);
+
+        // The spring pulls the top of the body towards the anchor with a force of 10,
+        // which is one unit above the center of mass
+        let rb = scene.bodies.components()[0].0.as_dynamic().unwrap().clone();
+        assert!(norm(&(rb.state.angular_momentum - Vector3::new(0.0, 0.0, 0.1))) < 1e-9,
+            "Angular momentum was {:?}", rb.state.angular_momentum);
+        assert!(rb.state.velocity.x < 0.0);
+    }
+
+    #[test]
+    fn falling_body_reaches_terminal_velocity_under_drag() {
+        // At terminal velocity, drag balances gravity: b v + c v² = m g
+        let (b, c, m, g) = (1.0, 0.5, 2.0, 9.81);
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::origin(), zero(), zero(), m)
+        ], vec![
+            ForceGenerator::UniformAccelerationField { acceleration: Vector3::new(0.0, 0.0, -g) },
+            ForceGenerator::Drag { linear: b, quadratic: c }
+        ]);
+        scene.simulate(0.01, 2000);
+
+        let terminal_speed = (- b + (b * b + 4.0 * c * m * g).sqrt()) / (2.0 * c);
+        let velocity = scene.bodies.components()[0].0.as_dynamic().unwrap().state.velocity;
+        assert!(norm(&(velocity - Vector3::new(0.0, 0.0, - terminal_speed))) < 1e-6,
+            "Velocity was {:?}, expected speed {}", velocity, terminal_speed);
+    }
+
+    #[test]
+    fn bodies_are_carried_along_by_wind() {
+        // A shear flow, which blows faster higher up
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::new(0.0, 0.0, 1.0), zero(), zero(), 1.0),
+            point_mass(Point3::new(0.0, 0.0, 2.0), zero(), zero(), 1.0)
+        ], vec![
+            ForceGenerator::Wind {
+                velocity: Rc::new(|x: Point3<f64>| Vector3::new(x.z, 0.0, 0.0)),
+                coefficient: 1.0
             }
-            for j in (i + 1) .. num_objects {
-                let m_i = self.m[i];
-                let m_j = self.m[j];
-                let x_i = self.x[i];
-                let x_j = self.x[j];
-                let r = x_j - x_i;
-                let r2 = norm_squared(&r);
-                let f = G * m_i * m_j / r2;
-                // TODO: Since r isn't normalized, doesn't this give the wrong
-                // values for the acceleration? Investigate!
-                self.a_next[i] += (f / m_i) * r;
-                self.a_next[j] += - (f / m_j) * r;
+        ]);
+        scene.simulate(0.01, 2000);
+
+        // Under quadratic drag, the speed u relative to the wind
+        // decays as u0 / (1 + u0 t) for unit mass and coefficient
+        let t = 20.0;
+        for (index, wind_speed) in vec![1.0, 2.0].into_iter().enumerate() {
+            let expected_speed = wind_speed - wind_speed / (1.0 + wind_speed * t);
+            let rb = scene.bodies.components()[index].0.as_dynamic().unwrap().clone();
+            assert!(norm(&(rb.state.velocity - Vector3::new(expected_speed, 0.0, 0.0))) < 1e-3,
+                "Velocity was {:?}, expected speed {}", rb.state.velocity, expected_speed);
+        }
+    }
+
+    #[test]
+    fn buoyant_spheres_float_half_submerged() {
+        // A sphere with half the density of the fluid displaces its own weight
+        // of fluid when half of it is submerged. Linear drag settles the bobbing.
+        let (fluid_density, g) = (1000.0, 9.81);
+        let radii = [0.5, 0.25];
+        let mass = |radius: f64| 0.5 * fluid_density * 4.0 / 3.0 * PI * radius * radius * radius;
+        let mut scene = Scene::new(vec![
+            point_mass(Point3::new(0.0, 0.0, 1.0), zero(), zero(), mass(radii[0])),
+            point_mass(Point3::new(3.0, 0.0, -1.0), zero(), zero(), mass(radii[1])),
+            point_mass(Point3::new(-3.0, 0.0, -1.0), zero(), zero(), mass(radii[1]))
+        ], vec![
+            ForceGenerator::UniformAccelerationField { acceleration: Vector3::new(0.0, 0.0, -g) },
+            ForceGenerator::Drag { linear: mass(radii[0]), quadratic: 0.0 },
+            ForceGenerator::Buoyancy {
+                center_of_buoyancy: Point3::origin(),
+                surface: Plane { point: Point3::origin(), normal: Vector3::new(0.0, 0.0, 1.0) },
+                fluid_density: fluid_density,
+                gravity: Vector3::new(0.0, 0.0, -g)
             }
+        ]);
+        for (i, &radius) in radii.iter().enumerate() {
+            let entity = scene.entity_of(i);
+            scene.collision.set_component_model(entity,
+                CollisionModel::Sphere(Sphere { center: Point3::origin(), radius: radius }));
+        }
+
+        // The bobbing slows down gradually, so the spheres would fall asleep before they settle
+        for &mut (ref mut rb, _) in scene.bodies.components_mut().iter_mut() {
+            rb.as_dynamic_mut().unwrap().sleep_thresholds = None;
+        }
+        scene.simulate(0.01, 2000);
+
+        for i in 0 .. 2 {
+            let position = position_of(&scene.bodies, i);
+            assert!(position.z.abs() < 1e-3, "Position was {:?}", position);
+        }
+
+        // A body without a collision model displaces no fluid, and sinks
+        let position = position_of(&scene.bodies, 2);
+        assert!(position.z < -10.0, "Position was {:?}", position);
+    }
+
+    /// Spinning spheres which attract each other while falling onto the ground
+    /// through a viscous medium, bumping into each other on the way.
+    fn tumbling_spheres() -> Scene {
+        let mut bodies: Vec<RigidBody> = (0 .. 6).map(|i| {
+            let i = i as f64;
+            let mut body = point_mass(Point3::new(0.3 * i, 0.1 * i, 1.0 + 0.7 * i), Vector3::new(-0.2 * i, 0.1, 0.0), zero(), 1.0 + i);
+            body.as_dynamic_mut().unwrap().state.angular_momentum = Vector3::new(0.1, -0.2 * i, 0.3);
+            body
+        }).collect();
+        bodies.push(RigidBody::Static(StaticRigidBody {
+            position: Point3::origin(),
+            orientation: UnitQuaternion::identity()
+        }));
+        let mut scene = Scene::new(bodies, vec![
+            ForceGenerator::UniformAccelerationField { acceleration: Vector3::new(0.0, 0.0, -9.81) },
+            ForceGenerator::MutualGravitation { g: 0.1, softening: 0.01, method: GravitationMethod::Exact },
+            ForceGenerator::Drag { linear: 0.1, quadratic: 0.05 }
+        ]);
+        for i in 0 .. 6 {
+            let entity = scene.entity_of(i);
+            scene.collision.set_component_model(entity,
+                CollisionModel::Sphere(Sphere { center: Point3::origin(), radius: 0.3 }));
+        }
+        let ground = scene.entity_of(6);
+        scene.collision.set_component_model(ground,
+            CollisionModel::Plane(Plane { point: Point3::origin(), normal: Vector3::new(0.0, 0.0, 1.0) }));
+        scene
+    }
+
+    fn body_states(scene: &Scene) -> Vec<DynamicBodyState> {
+        scene.bodies.components().iter()
+            .filter_map(|&(ref rb, _)| rb.as_dynamic().map(|rb| rb.state.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn repeated_runs_reproduce_body_states_exactly() {
+        // Replays depend on the simulation taking the same path for the same input
+        let mut first = tumbling_spheres();
+        let mut second = tumbling_spheres();
+        for _ in 0 .. 10 {
+            first.simulate(0.005, 30);
+            second.simulate(0.005, 30);
+            assert_eq!(body_states(&first), body_states(&second));
+        }
+    }
+
+    /// A small sphere fired at a thin wall, which it crosses within a single step.
+    fn bullet_and_wall(ccd: bool) -> Scene {
+        let mut bullet = point_mass(Point3::new(-1.0, 0.0, 0.0), Vector3::new(200.0, 0.0, 0.0), zero(), 0.01);
+        bullet.as_dynamic_mut().unwrap().ccd = ccd;
+        let mut scene = Scene::new(vec![
+            bullet,
+            RigidBody::Static(StaticRigidBody {
+                position: Point3::origin(),
+                orientation: UnitQuaternion::identity()
+            })
+        ], vec![]);
+        let (bullet, wall) = (scene.entity_of(0), scene.entity_of(1));
+        scene.collision.set_component_model(bullet,
+            CollisionModel::Sphere(Sphere { center: Point3::origin(), radius: 0.05 }));
+        scene.collision.set_component_model(wall, CollisionModel::Cuboid(Cuboid {
+            center: Point3::origin(),
+            half_size: Vector3::new(0.05, 1.0, 1.0),
+            rotation: UnitQuaternion::identity()
+        }));
+        scene
+    }
+
+    #[test]
+    fn fast_bullet_tunnels_through_wall_without_ccd() {
+        let mut scene = bullet_and_wall(false);
+        scene.simulate(0.01, 10);
+        assert!(position_of(&scene.bodies, 0).x > 1.0);
+        assert!(scene.engine.drain_messages().is_empty());
+    }
+
+    #[test]
+    fn fast_bullet_is_stopped_by_wall_with_ccd() {
+        let mut scene = bullet_and_wall(true);
+
+        // The bullet should be moved back to where it first touches the wall
+        scene.simulate(0.01, 1);
+        let x = position_of(&scene.bodies, 0).x;
+        assert!((x + 0.1).abs() < 1e-3, "Position was {}", x);
+        match scene.engine.drain_messages().first() {
+            Some(&Message::CollisionStarted { .. }) => {},
+            message => panic!("Unexpected message {:?}", message)
+        }
+
+        // The collision is perfectly elastic, so the bullet bounces back
+        scene.simulate(0.01, 9);
+        let rb = scene.bodies.components()[0].0.as_dynamic().unwrap().clone();
+        assert!(rb.state.position.x < -0.1, "Position was {:?}", rb.state.position);
+        assert!((rb.state.velocity.x + 200.0).abs() < 1e-6, "Velocity was {:?}", rb.state.velocity);
+    }
+
+    #[test]
+    fn bullet_sliding_on_floor_keeps_floor_contact_when_stopped_by_wall_with_ccd() {
+        let mut scene = bullet_and_wall(true);
+        scene.bodies.components_mut()[0].0 = {
+            let mut bullet = point_mass(Point3::new(-1.0, 0.0, 0.05), zero(), zero(), 0.01);
+            bullet.as_dynamic_mut().unwrap().ccd = true;
+            bullet
+        };
+        let floor_body = RigidBody::Static(StaticRigidBody {
+            position: Point3::origin(),
+            orientation: UnitQuaternion::identity()
+        });
+        let floor = scene.entity_manager.create();
+        scene.bodies.set_component_for_entity(floor, floor_body);
+        scene.collision.set_component_model(floor,
+            CollisionModel::Plane(Plane { point: Point3::origin(), normal: Vector3::new(0.0, 0.0, 1.0) }));
+        let gravity = scene.entity_manager.create();
+        scene.generators.set_component_for_entity(gravity,
+            ForceGenerator::UniformAccelerationField { acceleration: Vector3::new(0.0, 0.0, -9.81) }.global());
+        let (bullet, wall) = (scene.entity_of(0), scene.entity_of(1));
+
+        // Let the bullet settle on the floor before firing it at the wall
+        scene.simulate(0.01, 10);
+        scene.engine.drain_messages();
+        scene.bodies.components_mut()[0].0.as_dynamic_mut().unwrap().state.velocity = Vector3::new(200.0, 0.0, 0.0);
+        scene.simulate(0.01, 1);
+
+        let rb = scene.bodies.components()[0].0.as_dynamic().unwrap().clone();
+        assert!((rb.state.position.x + 0.1).abs() < 1e-3, "Position was {:?}", rb.state.position);
+        assert!(rb.state.position.z > 0.04, "Position was {:?}", rb.state.position);
+        let messages = scene.engine.drain_messages();
+        assert_eq!(1, messages.len(), "Messages were {:?}", messages);
+        match messages[0] {
+            Message::CollisionStarted { a, b, .. } => assert!((a, b) == (bullet, wall) || (a, b) == (wall, bullet)),
+            ref message => panic!("Unexpected message {:?}", message)
+        }
+
+        // The bullet is seen where it was stopped, not beyond the wall
+        let queries = scene.engine.collision_engine();
+        assert_eq!(vec![bullet], queries.entities_containing_point(rb.state.position, &CollisionFilter::default()));
+    }
+
+    #[test]
+    fn fast_bullet_is_stopped_by_paddle_crossing_its_path_with_ccd() {
+        let mut scene = bullet_and_wall(true);
+
+        // The paddle only blocks the path of the bullet midway through the step
+        let paddle = KinematicRigidBody {
+            velocity: Vector3::new(0.0, 300.0, 0.0),
+            .. KinematicRigidBody::new(Point3::new(0.0, -1.5, 0.0), UnitQuaternion::identity(), KinematicMotion::Velocity)
+        };
+        scene.bodies.components_mut()[1].0 = RigidBody::Kinematic(paddle);
+
+        scene.simulate(0.01, 1);
+
+        let x = position_of(&scene.bodies, 0).x;
+        assert!(x < -0.05, "Position was {}", x);
+        match scene.engine.drain_messages().first() {
+            Some(&Message::CollisionStarted { .. }) => {},
+            message => panic!("Unexpected message {:?}", message)
         }
     }
 }
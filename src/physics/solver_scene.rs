@@ -0,0 +1,202 @@
+use physics::contact_solver::{ContactSolver, ContactPoint};
+use physics::{RigidBody, DynamicRigidBody, StaticRigidBody, Mass, PhysicsMaterial, Joint,
+    AngularIntegrator, ImplicitMidpointRotation};
+use entity::{Entity, EntityManager, LinearComponentStorage};
+use nalgebra::{zero, Point3, Vector3, Matrix3, UnitQuaternion};
+
+/// The collision shape of a body, for the simplified contact detection of the scene.
+#[derive(Copy, Clone)]
+pub enum Shape {
+    Cube { half_size: f64 },
+    Sphere { radius: f64 }
+}
+
+/// Dynamic bodies under uniform gravity, stepped by the contact and joint solver alone,
+/// without collision detection or force generators. A static anchor at the origin
+/// holds joints in place and serves as the ground plane z = 0.
+///
+/// Bodies with a shape rest on the ground. Cubes are assumed to stay roughly
+/// axis-aligned, and each cube rests on the ground or on the previous cube.
+pub struct Scene {
+    pub entity_manager: EntityManager,
+    pub bodies: LinearComponentStorage<RigidBody>,
+    pub materials: LinearComponentStorage<PhysicsMaterial>,
+    pub joints: LinearComponentStorage<Joint>,
+    pub dynamic: Vec<Entity>,
+    pub shapes: Vec<(Entity, Shape)>,
+    pub anchor: Entity,
+    pub gravity: Vector3<f64>,
+    pub solver: ContactSolver,
+    pub contacts: Vec<ContactPoint>
+}
+
+impl Scene {
+    pub fn new(gravity: Vector3<f64>) -> Scene {
+        let mut entity_manager = EntityManager::new();
+        let mut bodies = LinearComponentStorage::new();
+        let anchor = entity_manager.create();
+        bodies.set_component_for_entity(anchor, RigidBody::Static(StaticRigidBody {
+            position: Point3::origin(),
+            orientation: UnitQuaternion::identity()
+        }));
+
+        Scene {
+            entity_manager: entity_manager,
+            bodies: bodies,
+            materials: LinearComponentStorage::new(),
+            joints: LinearComponentStorage::new(),
+            dynamic: Vec::new(),
+            shapes: Vec::new(),
+            anchor: anchor,
+            gravity: gravity,
+            solver: ContactSolver::new(),
+            contacts: Vec::new()
+        }
+    }
+
+    /// Gives the ground, and every body added with a shape, the given material.
+    pub fn with_material(mut self, material: PhysicsMaterial) -> Scene {
+        self.materials.set_component_for_entity(self.anchor, material);
+        self
+    }
+
+    /// Adds a unit point mass with unit inertia, which does not touch anything.
+    pub fn add_point_mass(&mut self, position: Point3<f64>) -> Entity {
+        let mut rb = DynamicRigidBody {
+            mass: Mass::new(1.0),
+            inv_inertia_body: Matrix3::identity(),
+            .. DynamicRigidBody::default()
+        };
+        rb.state.position = position;
+        let entity = self.entity_manager.create();
+        self.bodies.set_component_for_entity(entity, RigidBody::Dynamic(rb));
+        self.dynamic.push(entity);
+        entity
+    }
+
+    /// Adds a body of the given shape with a mass of 2.
+    pub fn add(&mut self, shape: Shape, position: Point3<f64>, velocity: Vector3<f64>) -> Entity {
+        let mass = 2.0;
+        let inertia = match shape {
+            Shape::Cube { half_size } => (2.0 / 3.0) * mass * half_size * half_size,
+            Shape::Sphere { radius } => 0.4 * mass * radius * radius
+        };
+        let mut rb = DynamicRigidBody {
+            mass: Mass::new(mass),
+            inv_inertia_body: Matrix3::identity() * (1.0 / inertia),
+            .. DynamicRigidBody::default()
+        };
+        rb.state.position = position;
+        rb.state.velocity = velocity;
+
+        let entity = self.entity_manager.create();
+        self.bodies.set_component_for_entity(entity, RigidBody::Dynamic(rb));
+        if let Some(material) = self.materials.lookup_component_for_entity(self.anchor).cloned() {
+            self.materials.set_component_for_entity(entity, material);
+        }
+        self.dynamic.push(entity);
+        self.shapes.push((entity, shape));
+        entity
+    }
+
+    pub fn join(&mut self, joint: Joint) {
+        let entity = self.entity_manager.create();
+        self.joints.set_component_for_entity(entity, joint);
+    }
+
+    pub fn body(&self, entity: Entity) -> DynamicRigidBody {
+        self.bodies.lookup_component_for_entity(entity)
+                   .and_then(|rb| rb.as_dynamic())
+                   .cloned()
+                   .unwrap()
+    }
+
+    pub fn body_mut(&mut self, entity: Entity) -> &mut DynamicRigidBody {
+        self.bodies.lookup_component_for_entity_mut(entity)
+                   .and_then(|rb| rb.as_dynamic_mut())
+                   .unwrap()
+    }
+
+fn detect_contacts(&mut self) {
+        // Contacts are included slightly before the bodies touch, and the
+        // faces of supporting cubes are slightly enlarged, so that contacts at
+        // coinciding edges are not lost to round-off errors
+        let prediction = 1e-3;
+        let tolerance = 1e-3;
+        let normal = Vector3::new(0.0, 0.0, 1.0);
+        self.contacts.clear();
+
+        // The top face of the previous cube, given by (entity, height, center, half size)
+        let mut support: (Entity, f64, Point3<f64>, f64) =
+            (self.anchor, 0.0, Point3::origin(), ::std::f64::INFINITY);
+
+        for &(entity, shape) in &self.shapes {
+            let rb = self.body(entity);
+            let x = rb.state.position;
+            match shape {
+                Shape::Sphere { radius } => {
+                    let depth = radius - x.z;
+                    if depth > - prediction {
+                        self.contacts.push(ContactPoint {
+                            entity1: self.anchor,
+                            entity2: entity,
+                            point: x - radius * normal,
+                            normal: normal,
+                            depth: depth
+                        });
+                    }
+                },
+                Shape::Cube { half_size } => {
+                    let (support_entity, height, center, support_half_size) = support;
+                    for &sx in &[-1.0, 1.0] {
+                        for &sy in &[-1.0, 1.0] {
+                            for &sz in &[-1.0, 1.0] {
+                                let local = half_size * Vector3::new(sx, sy, sz);
+                                let corner = x + rb.state.orientation * local;
+                                let depth = height - corner.z;
+                                let extent = support_half_size + tolerance;
+                                let supported = (corner.x - center.x).abs() <= extent
+                                             && (corner.y - center.y).abs() <= extent;
+                                if depth > - prediction && supported {
+                                    self.contacts.push(ContactPoint {
+                                        entity1: support_entity,
+                                        entity2: entity,
+                                        point: corner,
+                                        normal: normal,
+                                        depth: depth
+                                    });
+                                }
+                            }
+                        }
+                    }
+                    support = (entity, x.z + half_size, x, half_size);
+                }
+            }
+        }
+    }
+
+    pub fn step(&mut self, dt: f64) {
+        for &entity in &self.dynamic {
+            let rb = self.bodies.lookup_component_for_entity_mut(entity).unwrap();
+            rb.as_dynamic_mut().unwrap().state.velocity += dt * self.gravity;
+        }
+
+        self.detect_contacts();
+        self.solver.solve(dt, &self.contacts, &self.joints, &mut self.bodies, &self.materials);
+
+        let mut rotation = ImplicitMidpointRotation::new();
+        for &entity in &self.dynamic {
+            let rb = self.bodies.lookup_component_for_entity_mut(entity).unwrap();
+            let rb = rb.as_dynamic_mut().unwrap();
+            rb.state.position += dt * rb.state.velocity;
+            rotation.integrate(dt, &mut rb.state.orientation, &mut rb.state.angular_momentum,
+                               &rb.inv_inertia_body, &zero());
+        }
+    }
+
+    pub fn simulate(&mut self, dt: f64, num_steps: usize) {
+        for _ in 0 .. num_steps {
+            self.step(dt);
+        }
+    }
+}
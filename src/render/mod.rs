@@ -15,7 +15,9 @@ mod primitives;
 pub use self::primitives::{
     icosahedron_renderable,
     unit_sphere_renderable,
-    box_renderable
+    box_renderable,
+    composite_renderable,
+    Shading
 };
 
 mod window;
@@ -81,6 +81,41 @@ pub fn box_renderable(halfx: f32, halfy: f32, halfz: f32)
     build_renderable(&mesh, &normals)
 }
 
+/// Determines how the normals of a mesh are computed for rendering.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Shading {
+    /// Vertex normals are averaged over the neighboring triangles.
+    Smooth,
+    /// Every triangle is rendered with its own normal.
+    Flat
+}
+
+/// Builds a single renderable from several meshes, which are given
+/// in a common coordinate system.
+pub fn composite_renderable(parts: &[(SurfaceMesh<f32>, Shading)])
+    -> SceneRenderable {
+    let mut vertices = Vec::new();
+    let mut normals = Vec::new();
+    let mut triangles = Vec::new();
+
+    for &(ref mesh, shading) in parts {
+        let mesh = match shading {
+            Shading::Smooth => mesh.clone(),
+            Shading::Flat => mesh.replicate_vertices()
+        };
+        let offset = vertices.len();
+        normals.extend(weighted_vertex_normals(&mesh));
+        vertices.extend_from_slice(mesh.vertices());
+        triangles.extend(mesh.triangle_indices().iter().map(|t| {
+            TriangleIndices::new(t.indices[0] + offset, t.indices[1] + offset, t.indices[2] + offset)
+        }));
+    }
+
+    let mesh = SurfaceMesh::from_indices(vertices, triangles)
+        .expect("Indices are offset consistently with the vertices, so the mesh must be valid.");
+    build_renderable(&mesh, &normals)
+}
+
 #[cfg(test)]
 mod tests {
     use super::weighted_vertex_normals;
@@ -1,6 +1,6 @@
 use entity::Entity;
 
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use cgmath::{Point3, Vector3};
 use render::Color;
 
@@ -28,13 +28,13 @@ pub struct SceneRenderable {
 }
 
 pub struct SceneRenderableStore {
-    renderables: HashMap<Entity, SceneRenderable>
+    renderables: BTreeMap<Entity, SceneRenderable>
 }
 
 impl SceneRenderableStore {
     pub fn new() -> SceneRenderableStore {
         SceneRenderableStore {
-            renderables: HashMap::new()
+            renderables: BTreeMap::new()
         }
     }
 
@@ -42,7 +42,7 @@ impl SceneRenderableStore {
         self.renderables.insert(entity, renderable);
     }
 
-    pub fn renderables(&self) -> &HashMap<Entity, SceneRenderable> {
+    pub fn renderables(&self) -> &BTreeMap<Entity, SceneRenderable> {
         &self.renderables
     }
 
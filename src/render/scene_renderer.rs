@@ -138,6 +138,12 @@ impl SceneRenderer {
         self.program = Some(program);
     }
 
+    /// Discards the buffers of all renderables, which must be done whenever
+    /// the entities are reassembled, since entity IDs may then be reused.
+    pub fn clear_buffers(&mut self) {
+        self.buffer_cache.clear();
+    }
+
     pub fn update_buffers(&mut self, window: &Window, renderable_store: &SceneRenderableStore) {
         // Note: This is a stopgap solution!
         for (entity, renderable) in renderable_store.renderables().iter() {
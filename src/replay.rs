@@ -0,0 +1,273 @@
+use message::Message;
+use camera::CameraAction;
+use snapshot::{Line, Fields, invalid_data};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::fs::File;
+use std::path::Path;
+
+const FORMAT_VERSION: u32 = 1;
+
+const CAMERA_ACTIONS: [CameraAction; 20] = [
+    CameraAction::TranslateForwardBegin, CameraAction::TranslateForwardEnd,
+    CameraAction::TranslateBackwardBegin, CameraAction::TranslateBackwardEnd,
+    CameraAction::TranslateLeftBegin, CameraAction::TranslateLeftEnd,
+    CameraAction::TranslateRightBegin, CameraAction::TranslateRightEnd,
+    CameraAction::RotateRightBegin, CameraAction::RotateRightEnd,
+    CameraAction::RotateLeftBegin, CameraAction::RotateLeftEnd,
+    CameraAction::RotateUpBegin, CameraAction::RotateUpEnd,
+    CameraAction::RotateDownBegin, CameraAction::RotateDownEnd,
+    CameraAction::TwistRightBegin, CameraAction::TwistRightEnd,
+    CameraAction::TwistLeftBegin, CameraAction::TwistLeftEnd
+];
+
+/// The input of a lockstep simulation, from which the simulation can be played back
+/// exactly. Every message is stamped with the number of physics steps that had been
+/// taken when it was dispatched.
+///
+/// Only messages which come from the user are recorded. Raw keyboard input is
+/// left out in favor of the commands it was translated into, and messages from
+/// the physics engine are produced again during playback.
+#[derive(Clone, Debug)]
+pub struct Replay {
+    pub timestep: f64,
+    pub steps_per_frame: u32,
+    pub messages: Vec<(u64, Message)>
+}
+
+/// Hands out the messages of a replay as the simulation reaches their steps.
+pub struct Playback {
+    replay: Replay,
+    next: usize
+}
+
+impl Replay {
+    pub fn new(timestep: f64, steps_per_frame: u32) -> Replay {
+        Replay {
+            timestep: timestep,
+            steps_per_frame: steps_per_frame,
+            messages: Vec::new()
+        }
+    }
+
+    /// Records the messages which were dispatched after the given number of steps.
+    pub fn record(&mut self, step: u64, messages: &[Message]) {
+        let recorded = messages.iter().filter(|message| is_recorded(message)).cloned();
+        self.messages.extend(recorded.map(|message| (step, message)));
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Replay> {
+        Replay::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Writes the replay in the line-based text format of snapshots.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "replay {}", FORMAT_VERSION)?;
+
+        let mut line = Line::new("timestep");
+        line.float(self.timestep);
+        writeln!(writer, "{}", line.finish())?;
+        writeln!(writer, "steps_per_frame {}", self.steps_per_frame)?;
+
+        for &(step, ref message) in &self.messages {
+            let mut line = Line::new("message");
+            line.integer(step);
+            match message {
+                &Message::CameraCommand(action) => {
+                    line.token("camera");
+                    line.token(&format!("{:?}", action));
+                },
+                &Message::ReloadScene { index } => {
+                    line.token("reload_scene");
+                    line.integer(index);
+                },
+                &Message::SaveSnapshot => line.token("save_snapshot"),
+                &Message::RestoreSnapshot => line.token("restore_snapshot"),
+                &Message::WindowClosed => line.token("window_closed"),
+                _ => unreachable!("Only recorded messages are kept in a replay.")
+            }
+            writeln!(writer, "{}", line.finish())?;
+        }
+
+        writeln!(writer, "end")
+    }
+
+    /// Reads a replay written by `write`.
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Replay> {
+        let mut lines = reader.lines().enumerate();
+        let mut next_line = || -> io::Result<(usize, String)> {
+            loop {
+                match lines.next() {
+                    Some((index, line)) => {
+                        let line = line?;
+                        if !line.trim().is_empty() {
+                            return Ok((index + 1, line));
+                        }
+                    },
+                    None => return Err(invalid_data("The replay ends before its end marker."))
+                }
+            }
+        };
+
+        let (number, header) = next_line()?;
+        let mut fields = Fields::new(&header, number);
+        fields.keyword("replay")?;
+        let version: u32 = fields.integer()?;
+        if version != FORMAT_VERSION {
+            return Err(invalid_data(&format!("Unsupported replay version {}.", version)));
+        }
+        fields.finish()?;
+
+        let (number, line) = next_line()?;
+        let mut fields = Fields::new(&line, number);
+        fields.keyword("timestep")?;
+        let timestep = fields.float()?;
+        fields.finish()?;
+
+        let (number, line) = next_line()?;
+        let mut fields = Fields::new(&line, number);
+        fields.keyword("steps_per_frame")?;
+        let steps_per_frame = fields.integer()?;
+        fields.finish()?;
+
+        let mut replay = Replay::new(timestep, steps_per_frame);
+        loop {
+            let (number, line) = next_line()?;
+            let mut fields = Fields::new(&line, number);
+            match fields.token()? {
+                "end" => {
+                    fields.finish()?;
+                    return Ok(replay);
+                },
+                "message" => {
+                    let step: u64 = fields.integer()?;
+                    let message = match fields.token()? {
+                        "camera" => {
+                            let name = fields.token()?;
+                            let action = CAMERA_ACTIONS.iter()
+                                .find(|action| format!("{:?}", action) == name)
+                                .cloned()
+                                .ok_or_else(|| fields.error(&format!("Unknown camera action '{}'.", name)))?;
+                            Message::CameraCommand(action)
+                        },
+                        "reload_scene" => Message::ReloadScene { index: fields.integer()? },
+                        "save_snapshot" => Message::SaveSnapshot,
+                        "restore_snapshot" => Message::RestoreSnapshot,
+                        "window_closed" => Message::WindowClosed,
+                        token => return Err(fields.error(&format!("Unknown message '{}'.", token)))
+                    };
+                    if replay.messages.last().map_or(false, |&(last, _)| step < last) {
+                        return Err(fields.error("Messages are not ordered by step."));
+                    }
+                    replay.messages.push((step, message));
+                },
+                token => return Err(fields.error(&format!("Unknown record '{}'.", token)))
+            }
+            fields.finish()?;
+        }
+    }
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        Playback {
+            replay: replay,
+            next: 0
+        }
+    }
+
+    /// The messages which were recorded after at most the given number of steps,
+    /// and which have not been handed out before.
+    pub fn messages_until(&mut self, step: u64) -> Vec<Message> {
+        let remaining = &self.replay.messages[self.next ..];
+        let count = remaining.iter().take_while(|&&(s, _)| s <= step).count();
+        self.next += count;
+        remaining[.. count].iter().map(|&(_, ref message)| message.clone()).collect()
+    }
+}
+
+/// Whether the message comes from the user, as opposed to being raw input
+/// or the outcome of the simulation.
+fn is_recorded(message: &Message) -> bool {
+    match message {
+        &Message::CameraCommand(_) |
+        &Message::ReloadScene { .. } |
+        &Message::SaveSnapshot |
+        &Message::RestoreSnapshot |
+        &Message::WindowClosed => true,
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Replay, Playback};
+    use message::Message;
+    use camera::CameraAction;
+    use entity::EntityManager;
+    use glium::glutin::{ElementState, VirtualKeyCode};
+
+    fn example_replay() -> Replay {
+        let mut entity_manager = EntityManager::new();
+        let a = entity_manager.create();
+        let b = entity_manager.create();
+
+        let mut replay = Replay::new(1.0 / 200.0, 3);
+        replay.record(0, &[Message::KeyboardInputReceived(ElementState::Pressed, VirtualKeyCode::W),
+                           Message::CameraCommand(CameraAction::TranslateForwardBegin)]);
+        replay.record(6, &[Message::CollisionEnded { a: a, b: b },
+                           Message::ReloadScene { index: 2 },
+                           Message::SaveSnapshot]);
+        replay.record(6, &[Message::CameraCommand(CameraAction::TwistLeftEnd)]);
+        replay.record(9, &[Message::RestoreSnapshot, Message::WindowClosed]);
+        replay
+    }
+
+    fn write_to_string(replay: &Replay) -> String {
+        let mut buffer = Vec::new();
+        replay.write(&mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn records_only_messages_from_the_user() {
+        let steps: Vec<u64> = example_replay().messages.iter().map(|&(step, _)| step).collect();
+        assert_eq!(vec![0, 6, 6, 6, 9, 9], steps);
+    }
+
+    #[test]
+    fn replay_survives_round_trip() {
+        let replay = example_replay();
+        let text = write_to_string(&replay);
+        let restored = Replay::read(text.as_bytes()).unwrap();
+
+        assert_eq!(replay.timestep.to_bits(), restored.timestep.to_bits());
+        assert_eq!(replay.steps_per_frame, restored.steps_per_frame);
+        assert_eq!(text, write_to_string(&restored));
+    }
+
+    #[test]
+    fn rejects_malformed_replays() {
+        let text = write_to_string(&example_replay());
+        let unknown_action = text.replace("TwistLeftEnd", "TwistSideways");
+        let truncated = text.replace("end\n", "");
+
+        assert!(Replay::read(unknown_action.as_bytes()).is_err());
+        assert!(Replay::read(truncated.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn playback_hands_out_messages_once_their_step_is_reached() {
+        let mut playback = Playback::new(example_replay());
+
+        assert_eq!(1, playback.messages_until(3).len());
+        assert_eq!(0, playback.messages_until(5).len());
+        assert_eq!(3, playback.messages_until(6).len());
+        assert_eq!(2, playback.messages_until(12).len());
+        assert_eq!(0, playback.messages_until(15).len());
+    }
+}
@@ -0,0 +1,875 @@
+use entity::Entity;
+use core::{Transform, TransformPair};
+use physics::{RigidBody, StaticRigidBody, DynamicRigidBody, DynamicBodyState, KinematicRigidBody,
+    KinematicMotion, Mass, SleepThresholds, CollisionModel, CollisionFilter, PhysicsState, ContactImpulse};
+use geometry::{Sphere, Cuboid, Capsule, Cylinder, Cone, Plane, SurfaceMesh, TriangleIndices};
+use nalgebra::{Point3, Vector3, Matrix3, Quaternion, UnitQuaternion, Isometry3, Translation3};
+use cgmath;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::path::Path;
+use std::str::{FromStr, SplitWhitespace};
+
+const FORMAT_VERSION: u32 = 1;
+
+/// The state of a running scene, from which its simulation can be resumed exactly.
+///
+/// A snapshot only holds state which changes during the simulation. It is restored into
+/// a freshly assembled instance of the scene it was taken from, which provides everything
+/// else, such as renderables, force generators, joints and the motion of kinematic bodies.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub scene_index: usize,
+    pub bodies: Vec<(BodyState, Entity)>,
+    pub transforms: Vec<(TransformPair, Entity)>,
+    pub collision: Vec<(CollisionState, Entity)>,
+    pub physics: PhysicsState
+}
+
+#[derive(Clone, Debug)]
+pub enum BodyState {
+    Static(StaticRigidBody),
+    Dynamic(DynamicRigidBody),
+
+    /// A kinematic body, apart from its motion, which may be given by a script.
+    Kinematic {
+        position: Point3<f64>,
+        orientation: UnitQuaternion<f64>,
+        velocity: Vector3<f64>,
+        angular_velocity: Vector3<f64>,
+        prev_position: Point3<f64>,
+        prev_orientation: UnitQuaternion<f64>,
+        time: f64
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CollisionState {
+    pub model: CollisionModel,
+    pub filter: CollisionFilter,
+    pub sensor: bool
+}
+
+impl BodyState {
+    pub fn of(rb: &RigidBody) -> BodyState {
+        match rb {
+            &RigidBody::Static(ref rb) => BodyState::Static(rb.clone()),
+            &RigidBody::Dynamic(ref rb) => BodyState::Dynamic(rb.clone()),
+            &RigidBody::Kinematic(ref rb) => BodyState::Kinematic {
+                position: rb.position,
+                orientation: rb.orientation,
+                velocity: rb.velocity,
+                angular_velocity: rb.angular_velocity,
+                prev_position: rb.prev_position,
+                prev_orientation: rb.prev_orientation,
+                time: rb.time
+            }
+        }
+    }
+
+    /// Builds the rigid body with this state. A kinematic body keeps the motion
+    /// of the given body of the restored scene, or moves by velocity if there is none.
+    pub fn to_rigid_body(&self, scene_body: Option<&RigidBody>) -> RigidBody {
+        match *self {
+            BodyState::Static(ref rb) => RigidBody::Static(rb.clone()),
+            BodyState::Dynamic(ref rb) => RigidBody::Dynamic(rb.clone()),
+            BodyState::Kinematic { position, orientation, velocity, angular_velocity,
+                                   prev_position, prev_orientation, time } => {
+                let motion = scene_body.and_then(|rb| rb.as_kinematic())
+                                       .map(|rb| rb.motion.clone())
+                                       .unwrap_or(KinematicMotion::Velocity);
+                RigidBody::Kinematic(KinematicRigidBody {
+                    position: position,
+                    orientation: orientation,
+                    velocity: velocity,
+                    angular_velocity: angular_velocity,
+                    prev_position: prev_position,
+                    prev_orientation: prev_orientation,
+                    motion: motion,
+                    time: time
+                })
+            }
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Snapshot> {
+        Snapshot::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Writes the snapshot in a line-based text format. Floating point numbers
+    /// are written as the hexadecimal digits of their bits, so that they are
+    /// read back exactly.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "snapshot {}", FORMAT_VERSION)?;
+        writeln!(writer, "scene {}", self.scene_index)?;
+
+        for &(ref state, entity) in &self.bodies {
+            let mut line = Line::new("body");
+            line.entity(entity);
+            line.body_state(state);
+            writeln!(writer, "{}", line.finish())?;
+        }
+
+        for &(ref pair, entity) in &self.transforms {
+            let mut line = Line::new("transform");
+            line.entity(entity);
+            line.transform(&pair.prev);
+            line.transform(&pair.current);
+            writeln!(writer, "{}", line.finish())?;
+        }
+
+        for &(ref state, entity) in &self.collision {
+            let mut line = Line::new("collision");
+            line.entity(entity);
+            line.integer(state.filter.membership);
+            line.integer(state.filter.whitelist);
+            line.integer(state.filter.blacklist);
+            line.boolean(state.sensor);
+            line.model(&state.model);
+            writeln!(writer, "{}", line.finish())?;
+        }
+
+        let physics = &self.physics;
+        for impulse in &physics.contact_impulses {
+            let mut line = Line::new("contact_impulse");
+            line.entity(impulse.entity1);
+            line.entity(impulse.entity2);
+            line.point(&impulse.point);
+            line.float(impulse.normal);
+            line.float(impulse.tangent1);
+            line.float(impulse.tangent2);
+            writeln!(writer, "{}", line.finish())?;
+        }
+
+        for &(entity, ref impulses) in &physics.joint_impulses {
+            let mut line = Line::new("joint_impulses");
+            line.entity(entity);
+            line.integer(impulses.len());
+            for &impulse in impulses {
+                line.float(impulse);
+            }
+            writeln!(writer, "{}", line.finish())?;
+        }
+
+        let pair_lists = [("touching", &physics.touching),
+                          ("triggered", &physics.triggered),
+                          ("disabled", &physics.disabled_pairs)];
+        for &(keyword, pairs) in &pair_lists {
+            for &(a, b) in pairs.iter() {
+                let mut line = Line::new(keyword);
+                line.entity(a);
+                line.entity(b);
+                writeln!(writer, "{}", line.finish())?;
+            }
+        }
+
+        writeln!(writer, "end")
+    }
+
+    /// Reads a snapshot written by `write`.
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Snapshot> {
+        let mut snapshot = Snapshot {
+            scene_index: 0,
+            bodies: Vec::new(),
+            transforms: Vec::new(),
+            collision: Vec::new(),
+            physics: PhysicsState {
+                contact_impulses: Vec::new(),
+                joint_impulses: Vec::new(),
+                touching: Vec::new(),
+                triggered: Vec::new(),
+                disabled_pairs: Vec::new()
+            }
+        };
+
+        let mut lines = reader.lines().enumerate();
+        let mut next_line = || -> io::Result<(usize, String)> {
+            match lines.next() {
+                Some((index, line)) => Ok((index + 1, line?)),
+                None => Err(invalid_data("The snapshot ends before its end marker."))
+            }
+        };
+
+        let (number, header) = next_line()?;
+        let mut fields = Fields::new(&header, number);
+        fields.keyword("snapshot")?;
+        let version: u32 = fields.integer()?;
+        if version != FORMAT_VERSION {
+            return Err(invalid_data(&format!("Unsupported snapshot version {}.", version)));
+        }
+        fields.finish()?;
+
+        loop {
+            let (number, line) = next_line()?;
+            let mut fields = Fields::new(&line, number);
+            let keyword = match fields.tokens.next() {
+                Some(keyword) => keyword,
+                None => continue
+            };
+
+            match keyword {
+                "end" => {
+                    fields.finish()?;
+                    return Ok(snapshot);
+                },
+                "scene" => snapshot.scene_index = fields.integer()?,
+                "body" => {
+                    let entity = fields.entity()?;
+                    snapshot.bodies.push((fields.body_state()?, entity));
+                },
+                "transform" => {
+                    let entity = fields.entity()?;
+                    let prev = fields.transform()?;
+                    let current = fields.transform()?;
+                    snapshot.transforms.push((TransformPair { prev: prev, current: current }, entity));
+                },
+                "collision" => {
+                    let entity = fields.entity()?;
+                    let filter = CollisionFilter {
+                        membership: fields.integer()?,
+                        whitelist: fields.integer()?,
+                        blacklist: fields.integer()?
+                    };
+                    let sensor = fields.boolean()?;
+                    let model = fields.model()?;
+                    snapshot.collision.push((CollisionState { model: model, filter: filter, sensor: sensor }, entity));
+                },
+                "contact_impulse" => {
+                    snapshot.physics.contact_impulses.push(ContactImpulse {
+                        entity1: fields.entity()?,
+                        entity2: fields.entity()?,
+                        point: fields.point()?,
+                        normal: fields.float()?,
+                        tangent1: fields.float()?,
+                        tangent2: fields.float()?
+                    });
+                },
+                "joint_impulses" => {
+                    let entity = fields.entity()?;
+                    let count: usize = fields.integer()?;
+                    let mut impulses = Vec::with_capacity(count);
+                    for _ in 0 .. count {
+                        impulses.push(fields.float()?);
+                    }
+                    snapshot.physics.joint_impulses.push((entity, impulses));
+                },
+                "touching" => snapshot.physics.touching.push((fields.entity()?, fields.entity()?)),
+                "triggered" => snapshot.physics.triggered.push((fields.entity()?, fields.entity()?)),
+                "disabled" => snapshot.physics.disabled_pairs.push((fields.entity()?, fields.entity()?)),
+                _ => return Err(fields.error(&format!("Unknown record '{}'.", keyword)))
+            }
+            fields.finish()?;
+        }
+    }
+}
+
+pub fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A line of the snapshot, built from whitespace-separated tokens.
+pub struct Line {
+    text: String
+}
+
+impl Line {
+    pub fn new(keyword: &str) -> Line {
+        Line {
+            text: keyword.to_string()
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.text
+    }
+
+    pub fn token(&mut self, token: &str) {
+        self.text.push(' ');
+        self.text.push_str(token);
+    }
+
+    pub fn integer<T: ::std::fmt::Display>(&mut self, value: T) {
+        write!(self.text, " {}", value).unwrap();
+    }
+
+    fn boolean(&mut self, value: bool) {
+        self.integer(if value { 1 } else { 0 });
+    }
+
+    pub fn float(&mut self, value: f64) {
+        write!(self.text, " {:016x}", value.to_bits()).unwrap();
+    }
+
+    fn entity(&mut self, entity: Entity) {
+        self.integer(u32::from(entity));
+    }
+
+    fn vector(&mut self, v: &Vector3<f64>) {
+        self.float(v.x);
+        self.float(v.y);
+        self.float(v.z);
+    }
+
+    fn point(&mut self, p: &Point3<f64>) {
+        self.vector(&p.coords);
+    }
+
+    fn quaternion(&mut self, q: &UnitQuaternion<f64>) {
+        let q = q.unwrap();
+        let xyz = q.vector();
+        self.float(q.scalar());
+        self.float(xyz[0]);
+        self.float(xyz[1]);
+        self.float(xyz[2]);
+    }
+
+    fn isometry(&mut self, isometry: &Isometry3<f64>) {
+        self.vector(&isometry.translation.vector);
+        self.quaternion(&isometry.rotation);
+    }
+
+    fn transform(&mut self, transform: &Transform) {
+        for &value in &[transform.position.x, transform.position.y, transform.position.z,
+                        transform.scale.x, transform.scale.y, transform.scale.z,
+                        transform.orientation.s, transform.orientation.v.x,
+                        transform.orientation.v.y, transform.orientation.v.z] {
+            self.float(value);
+        }
+    }
+
+    fn dynamic_state(&mut self, state: &DynamicBodyState) {
+        self.point(&state.position);
+        self.quaternion(&state.orientation);
+        self.vector(&state.velocity);
+        self.vector(&state.angular_momentum);
+        self.vector(&state.acceleration);
+    }
+
+    fn body_state(&mut self, state: &BodyState) {
+        match *state {
+            BodyState::Static(ref rb) => {
+                self.token("static");
+                self.point(&rb.position);
+                self.quaternion(&rb.orientation);
+            },
+            BodyState::Dynamic(ref rb) => {
+                self.token("dynamic");
+                self.dynamic_state(&rb.state);
+                self.dynamic_state(&rb.prev_state);
+                self.float(rb.mass.value());
+                for i in 0 .. 3 {
+                    for j in 0 .. 3 {
+                        self.float(rb.inv_inertia_body[(i, j)]);
+                    }
+                }
+                self.vector(&rb.accumulated_force);
+                self.vector(&rb.accumulated_torque);
+                self.boolean(rb.ccd);
+                match rb.sleep_thresholds {
+                    Some(thresholds) => {
+                        self.boolean(true);
+                        self.float(thresholds.linear);
+                        self.float(thresholds.angular);
+                    },
+                    None => self.boolean(false)
+                }
+                self.boolean(rb.sleeping);
+                self.float(rb.sleep_timer);
+            },
+            BodyState::Kinematic { ref position, ref orientation, ref velocity, ref angular_velocity,
+                                   ref prev_position, ref prev_orientation, time } => {
+                self.token("kinematic");
+                self.point(position);
+                self.quaternion(orientation);
+                self.vector(velocity);
+                self.vector(angular_velocity);
+                self.point(prev_position);
+                self.quaternion(prev_orientation);
+                self.float(time);
+            }
+        }
+    }
+
+    fn mesh(&mut self, mesh: &SurfaceMesh<f64>) {
+        self.integer(mesh.num_vertices());
+        for vertex in mesh.vertices() {
+            self.float(vertex.x);
+            self.float(vertex.y);
+            self.float(vertex.z);
+        }
+        self.integer(mesh.num_triangles());
+        for triangle in mesh.triangle_indices() {
+            for &index in &triangle.indices {
+                self.integer(index);
+            }
+        }
+    }
+
+    fn model(&mut self, model: &CollisionModel) {
+        match *model {
+            CollisionModel::Sphere(ref sphere) => {
+                self.token("sphere");
+                self.point(&sphere.center);
+                self.float(sphere.radius);
+            },
+            CollisionModel::Cuboid(ref cuboid) => {
+                self.token("cuboid");
+                self.point(&cuboid.center);
+                self.vector(&cuboid.half_size);
+                self.quaternion(&cuboid.rotation);
+            },
+            CollisionModel::Capsule(Capsule { ref center, half_height, radius, ref rotation }) => {
+                self.token("capsule");
+                self.solid_of_revolution(center, half_height, radius, rotation);
+            },
+            CollisionModel::Cylinder(Cylinder { ref center, half_height, radius, ref rotation }) => {
+                self.token("cylinder");
+                self.solid_of_revolution(center, half_height, radius, rotation);
+            },
+            CollisionModel::Cone(Cone { ref center, half_height, radius, ref rotation }) => {
+                self.token("cone");
+                self.solid_of_revolution(center, half_height, radius, rotation);
+            },
+            CollisionModel::Plane(ref plane) => {
+                self.token("plane");
+                self.point(&plane.point);
+                self.vector(&plane.normal);
+            },
+            CollisionModel::ConvexHull(ref mesh) => {
+                self.token("convex_hull");
+                self.mesh(mesh);
+            },
+            CollisionModel::TriMesh(ref mesh) => {
+                self.token("trimesh");
+                self.mesh(mesh);
+            },
+            CollisionModel::Compound(ref children) => {
+                self.token("compound");
+                self.integer(children.len());
+                for &(ref isometry, ref child) in children {
+                    self.isometry(isometry);
+                    self.model(child);
+                }
+            }
+        }
+    }
+
+    fn solid_of_revolution(&mut self,
+                           center: &Point3<f64>,
+                           half_height: f64,
+                           radius: f64,
+                           rotation: &UnitQuaternion<f64>) {
+        self.point(center);
+        self.float(half_height);
+        self.float(radius);
+        self.quaternion(rotation);
+    }
+}
+
+/// The tokens of a line of the snapshot, which are parsed in order.
+pub struct Fields<'a> {
+    tokens: SplitWhitespace<'a>,
+    line_number: usize
+}
+
+impl<'a> Fields<'a> {
+    pub fn new(line: &'a str, line_number: usize) -> Fields<'a> {
+        Fields {
+            tokens: line.split_whitespace(),
+            line_number: line_number
+        }
+    }
+
+    pub fn error(&self, message: &str) -> io::Error {
+        invalid_data(&format!("Line {}: {}", self.line_number, message))
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        match self.tokens.next() {
+            Some(token) => Err(self.error(&format!("Unexpected '{}'.", token))),
+            None => Ok(())
+        }
+    }
+
+    pub fn token(&mut self) -> io::Result<&'a str> {
+        match self.tokens.next() {
+            Some(token) => Ok(token),
+            None => Err(self.error("The line ends too early."))
+        }
+    }
+
+    pub fn keyword(&mut self, expected: &str) -> io::Result<()> {
+        let token = self.token()?;
+        if token == expected {
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}', found '{}'.", expected, token)))
+        }
+    }
+
+    pub fn integer<T: FromStr>(&mut self) -> io::Result<T> {
+        let token = self.token()?;
+        token.parse().map_err(|_| self.error(&format!("Invalid integer '{}'.", token)))
+    }
+
+    fn boolean(&mut self) -> io::Result<bool> {
+        match self.token()? {
+            "0" => Ok(false),
+            "1" => Ok(true),
+            token => Err(self.error(&format!("Invalid boolean '{}'.", token)))
+        }
+    }
+
+    pub fn float(&mut self) -> io::Result<f64> {
+        let token = self.token()?;
+        u64::from_str_radix(token, 16)
+            .map(f64::from_bits)
+            .map_err(|_| self.error(&format!("Invalid number '{}'.", token)))
+    }
+
+    fn entity(&mut self) -> io::Result<Entity> {
+        self.integer::<u32>().map(Entity::from)
+    }
+
+    fn vector(&mut self) -> io::Result<Vector3<f64>> {
+        Ok(Vector3::new(self.float()?, self.float()?, self.float()?))
+    }
+
+    fn point(&mut self) -> io::Result<Point3<f64>> {
+        self.vector().map(Point3::from_coordinates)
+    }
+
+    fn quaternion(&mut self) -> io::Result<UnitQuaternion<f64>> {
+        let w = self.float()?;
+        let v = self.vector()?;
+        // The quaternion was normalized when it was written, and normalizing
+        // it again could change its bits
+        Ok(UnitQuaternion::new_unchecked(Quaternion::new(w, v.x, v.y, v.z)))
+    }
+
+    fn isometry(&mut self) -> io::Result<Isometry3<f64>> {
+        let translation = self.vector()?;
+        let rotation = self.quaternion()?;
+        Ok(Isometry3::from_parts(Translation3::from_vector(translation), rotation))
+    }
+
+    fn transform(&mut self) -> io::Result<Transform> {
+        Ok(Transform {
+            position: cgmath::Point3::new(self.float()?, self.float()?, self.float()?),
+            scale: cgmath::Vector3::new(self.float()?, self.float()?, self.float()?),
+            orientation: cgmath::Quaternion::new(self.float()?, self.float()?, self.float()?, self.float()?)
+        })
+    }
+
+    fn dynamic_state(&mut self) -> io::Result<DynamicBodyState> {
+        Ok(DynamicBodyState {
+            position: self.point()?,
+            orientation: self.quaternion()?,
+            velocity: self.vector()?,
+            angular_momentum: self.vector()?,
+            acceleration: self.vector()?
+        })
+    }
+
+    fn body_state(&mut self) -> io::Result<BodyState> {
+        match self.token()? {
+            "static" => Ok(BodyState::Static(StaticRigidBody {
+                position: self.point()?,
+                orientation: self.quaternion()?
+            })),
+            "dynamic" => {
+                let state = self.dynamic_state()?;
+                let prev_state = self.dynamic_state()?;
+                let mass = self.float()?;
+                if !(mass.is_finite() && mass >= 0.0) {
+                    return Err(self.error("Mass must be a non-negative number."));
+                }
+                let mut inv_inertia_body = Matrix3::identity();
+                for i in 0 .. 3 {
+                    for j in 0 .. 3 {
+                        inv_inertia_body[(i, j)] = self.float()?;
+                    }
+                }
+                let accumulated_force = self.vector()?;
+                let accumulated_torque = self.vector()?;
+                let ccd = self.boolean()?;
+                let sleep_thresholds = if self.boolean()? {
+                    Some(SleepThresholds {
+                        linear: self.float()?,
+                        angular: self.float()?
+                    })
+                } else {
+                    None
+                };
+                Ok(BodyState::Dynamic(DynamicRigidBody {
+                    state: state,
+                    prev_state: prev_state,
+                    mass: Mass::new(mass),
+                    inv_inertia_body: inv_inertia_body,
+                    accumulated_force: accumulated_force,
+                    accumulated_torque: accumulated_torque,
+                    ccd: ccd,
+                    sleep_thresholds: sleep_thresholds,
+                    sleeping: self.boolean()?,
+                    sleep_timer: self.float()?
+                }))
+            },
+            "kinematic" => Ok(BodyState::Kinematic {
+                position: self.point()?,
+                orientation: self.quaternion()?,
+                velocity: self.vector()?,
+                angular_velocity: self.vector()?,
+                prev_position: self.point()?,
+                prev_orientation: self.quaternion()?,
+                time: self.float()?
+            }),
+            token => Err(self.error(&format!("Unknown kind of body '{}'.", token)))
+        }
+    }
+
+    fn mesh(&mut self) -> io::Result<SurfaceMesh<f64>> {
+        let num_vertices: usize = self.integer()?;
+        let mut vertices = Vec::with_capacity(num_vertices);
+        for _ in 0 .. num_vertices {
+            vertices.push(cgmath::Point3::new(self.float()?, self.float()?, self.float()?));
+        }
+        let num_triangles: usize = self.integer()?;
+        let mut triangles = Vec::with_capacity(num_triangles);
+        for _ in 0 .. num_triangles {
+            triangles.push(TriangleIndices::new(self.integer()?, self.integer()?, self.integer()?));
+        }
+        SurfaceMesh::from_indices(vertices, triangles)
+            .ok_or_else(|| self.error("Triangle indices refer to missing vertices."))
+    }
+
+    fn model(&mut self) -> io::Result<CollisionModel> {
+        match self.token()? {
+            "sphere" => Ok(CollisionModel::Sphere(Sphere {
+                center: self.point()?,
+                radius: self.float()?
+            })),
+            "cuboid" => Ok(CollisionModel::Cuboid(Cuboid {
+                center: self.point()?,
+                half_size: self.vector()?,
+                rotation: self.quaternion()?
+            })),
+            "capsule" => Ok(CollisionModel::Capsule(Capsule {
+                center: self.point()?,
+                half_height: self.float()?,
+                radius: self.float()?,
+                rotation: self.quaternion()?
+            })),
+            "cylinder" => Ok(CollisionModel::Cylinder(Cylinder {
+                center: self.point()?,
+                half_height: self.float()?,
+                radius: self.float()?,
+                rotation: self.quaternion()?
+            })),
+            "cone" => Ok(CollisionModel::Cone(Cone {
+                center: self.point()?,
+                half_height: self.float()?,
+                radius: self.float()?,
+                rotation: self.quaternion()?
+            })),
+            "plane" => Ok(CollisionModel::Plane(Plane {
+                point: self.point()?,
+                normal: self.vector()?
+            })),
+            "convex_hull" => self.mesh().map(CollisionModel::ConvexHull),
+            "trimesh" => self.mesh().map(CollisionModel::TriMesh),
+            "compound" => {
+                let count: usize = self.integer()?;
+                let mut children = Vec::with_capacity(count);
+                for _ in 0 .. count {
+                    let isometry = self.isometry()?;
+                    children.push((isometry, self.model()?));
+                }
+                Ok(CollisionModel::Compound(children))
+            },
+            token => Err(self.error(&format!("Unknown collision model '{}'.", token)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Snapshot, BodyState, CollisionState};
+    use core::{Transform, TransformPair};
+    use entity::{Entity, EntityManager};
+    use physics::{RigidBody, StaticRigidBody, DynamicRigidBody, KinematicRigidBody, KinematicMotion,
+        CollisionModel, CollisionFilter, PhysicsState, ContactImpulse};
+    use geometry::{Sphere, Cuboid, Capsule, Plane, SurfaceMesh, TriangleIndices};
+    use nalgebra::{Point3, Vector3, UnitQuaternion, Unit, Isometry3};
+    use cgmath;
+    use std::f64;
+    use std::rc::Rc;
+
+    fn write_to_string(snapshot: &Snapshot) -> String {
+        let mut buffer = Vec::new();
+        snapshot.write(&mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    fn example_snapshot() -> Snapshot {
+        let mut entity_manager = EntityManager::new();
+        let entities: Vec<Entity> = (0 .. 4).map(|_| entity_manager.create()).collect();
+
+        // Values which do not survive a round trip through decimal notation unless
+        // great care is taken, along with signed zeros and infinities
+        let awkward = Vector3::new(0.1 + 0.2, -0.0, 1.0 / 3.0);
+        let orientation = UnitQuaternion::from_axis_angle(&Unit::new_normalize(Vector3::new(1.0, 2.0, 3.0)), 0.3);
+
+        let mut dynamic = DynamicRigidBody::default();
+        dynamic.state.position = Point3::from_coordinates(awkward);
+        dynamic.state.orientation = orientation;
+        dynamic.state.angular_momentum = awkward * f64::consts::PI;
+        dynamic.accumulated_force = Vector3::new(f64::INFINITY, f64::MIN_POSITIVE, f64::EPSILON);
+        dynamic.sleep_thresholds = None;
+        dynamic.sleep_timer = 0.25;
+
+        let mut kinematic = KinematicRigidBody::new(Point3::new(1.0, 2.0, 3.0), orientation, KinematicMotion::Velocity);
+        kinematic.advance(0.1);
+
+        let tetrahedron = SurfaceMesh::from_indices(
+            vec![cgmath::Point3::new(0.0, 0.0, 0.0), cgmath::Point3::new(1.0, 0.0, 0.0),
+                 cgmath::Point3::new(0.0, 1.0, 0.0), cgmath::Point3::new(0.0, 0.0, 1.0)],
+            vec![TriangleIndices::new(0, 2, 1), TriangleIndices::new(0, 1, 3),
+                 TriangleIndices::new(0, 3, 2), TriangleIndices::new(1, 2, 3)]).unwrap();
+        let compound = CollisionModel::Compound(vec![
+            (Isometry3::new(awkward, awkward), CollisionModel::Capsule(Capsule {
+                center: Point3::origin(),
+                half_height: 0.5,
+                radius: 0.25,
+                rotation: orientation
+            })),
+            (Isometry3::identity(), CollisionModel::ConvexHull(tetrahedron.clone()))
+        ]);
+
+        let transform = Transform {
+            position: cgmath::Point3::new(0.1, 0.2, 0.3),
+            scale: cgmath::Vector3::new(2.0, 2.0, 2.0),
+            orientation: cgmath::Quaternion::new(0.0, 1.0, 0.0, 0.0)
+        };
+
+        Snapshot {
+            scene_index: 2,
+            bodies: vec![
+                (BodyState::of(&RigidBody::Static(StaticRigidBody {
+                    position: Point3::origin(),
+                    orientation: UnitQuaternion::identity()
+                })), entities[0]),
+                (BodyState::of(&RigidBody::Dynamic(dynamic)), entities[1]),
+                (BodyState::of(&RigidBody::Kinematic(kinematic)), entities[2])
+            ],
+            transforms: vec![(TransformPair { prev: Transform::default(), current: transform }, entities[1])],
+            collision: vec![
+                (CollisionState {
+                    model: CollisionModel::Plane(Plane { point: Point3::origin(), normal: Vector3::new(0.0, 0.0, 1.0) }),
+                    filter: CollisionFilter::default(),
+                    sensor: false
+                }, entities[0]),
+                (CollisionState {
+                    model: compound,
+                    filter: CollisionFilter::default().member_of(&[1]).with_blacklist(&[1]),
+                    sensor: false
+                }, entities[1]),
+                (CollisionState {
+                    model: CollisionModel::Cuboid(Cuboid {
+                        center: Point3::origin(),
+                        half_size: Vector3::new(1.0, 2.0, 3.0),
+                        rotation: orientation
+                    }),
+                    filter: CollisionFilter::default(),
+                    sensor: false
+                }, entities[2]),
+                (CollisionState {
+                    model: CollisionModel::Sphere(Sphere { center: Point3::origin(), radius: 5.0 }),
+                    filter: CollisionFilter::default(),
+                    sensor: true
+                }, entities[3]),
+                (CollisionState {
+                    model: CollisionModel::TriMesh(tetrahedron),
+                    filter: CollisionFilter::default(),
+                    sensor: true
+                }, entities[3])
+            ],
+            physics: PhysicsState {
+                contact_impulses: vec![ContactImpulse {
+                    entity1: entities[0],
+                    entity2: entities[1],
+                    point: Point3::from_coordinates(awkward),
+                    normal: 0.1,
+                    tangent1: -0.0,
+                    tangent2: 1e-300
+                }],
+                joint_impulses: vec![(entities[3], vec![0.1, 0.2, 0.3]), (entities[2], vec![])],
+                touching: vec![(entities[0], entities[1])],
+                triggered: vec![(entities[3], entities[1]), (entities[3], entities[2])],
+                disabled_pairs: vec![(entities[1], entities[2])]
+            }
+        }
+    }
+
+    #[test]
+    fn snapshot_survives_round_trip_exactly() {
+        let snapshot = example_snapshot();
+        let text = write_to_string(&snapshot);
+        let restored = Snapshot::read(text.as_bytes()).unwrap();
+
+        assert_eq!(text, write_to_string(&restored));
+        assert_eq!(snapshot.scene_index, restored.scene_index);
+        assert_eq!(snapshot.physics, restored.physics);
+
+        // Compare some of the values bit by bit, since -0.0 == 0.0
+        match (&snapshot.bodies[1].0, &restored.bodies[1].0) {
+            (&BodyState::Dynamic(ref original), &BodyState::Dynamic(ref restored)) => {
+                assert_eq!(original.state.position.coords, restored.state.position.coords);
+                assert_eq!((-0.0f64).to_bits(), restored.state.position.y.to_bits());
+                assert_eq!(original.state.orientation, restored.state.orientation);
+                assert_eq!(original.accumulated_force, restored.accumulated_force);
+                assert_eq!(original.sleep_thresholds, restored.sleep_thresholds);
+            },
+            _ => panic!("Expected a dynamic body.")
+        }
+    }
+
+    #[test]
+    fn restored_kinematic_body_keeps_motion_of_scene() {
+        let snapshot = example_snapshot();
+        let script = KinematicMotion::Scripted(Rc::new(|t: f64| {
+            Isometry3::new(Vector3::new(t, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0))
+        }));
+        let scene_body = RigidBody::Kinematic(KinematicRigidBody::new(Point3::origin(), UnitQuaternion::identity(), script));
+
+        let mut restored = snapshot.bodies[2].0.to_rigid_body(Some(&scene_body));
+        let rb = restored.as_kinematic_mut().unwrap();
+        assert_eq!(0.1, rb.time);
+        rb.advance(0.1);
+        assert_eq!(Point3::new(0.2, 0.0, 0.0), rb.position);
+    }
+
+    #[test]
+    fn malformed_snapshots_are_rejected() {
+        let text = write_to_string(&example_snapshot());
+        let truncated = &text[.. text.len() - "end\n".len()];
+        assert!(Snapshot::read(truncated.as_bytes()).is_err());
+
+        let unknown = text.replace("touching", "tickling");
+        assert!(Snapshot::read(unknown.as_bytes()).is_err());
+
+        let wrong_version = text.replace("snapshot 1", "snapshot 2");
+        assert!(Snapshot::read(wrong_version.as_bytes()).is_err());
+
+        let invalid_index = text.replace(" 1 2 3\n", " 1 2 7\n");
+        assert!(invalid_index != text);
+        assert!(Snapshot::read(invalid_index.as_bytes()).is_err());
+    }
+}
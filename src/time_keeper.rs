@@ -4,7 +4,20 @@ pub struct TimeKeeper {
     accumulated: f64,
     produced: f64,
     consumed: f64,
-    timestamp: f64
+    clock: Clock
+}
+
+enum Clock {
+    WallClock { timestamp: f64 },
+
+    /// Every frame produces the same number of steps, regardless of the time that
+    /// has passed. The accumulated time is counted in whole steps, so that
+    /// rounding can never make a frame produce one step less than the others.
+    Lockstep {
+        timestep: f64,
+        steps_per_frame: u32,
+        pending_steps: u32
+    }
 }
 
 impl TimeKeeper {
@@ -13,35 +26,78 @@ impl TimeKeeper {
             accumulated: 0.0,
             produced: 0.0,
             consumed: 0.0,
-            timestamp: time::precise_time_s()
+            clock: Clock::WallClock { timestamp: time::precise_time_s() }
+        }
+    }
+
+    /// A time keeper for which every frame lasts exactly the given number of steps,
+    /// so that the simulation does not depend on the wall clock.
+    pub fn lockstep(timestep: f64, steps_per_frame: u32) -> Self {
+        assert!(timestep > 0.0);
+        TimeKeeper {
+            accumulated: 0.0,
+            produced: 0.0,
+            consumed: 0.0,
+            clock: Clock::Lockstep {
+                timestep: timestep,
+                steps_per_frame: steps_per_frame,
+                pending_steps: 0
+            }
         }
     }
+
     pub fn produce(&mut self, time: f64) {
         self.accumulated += time;
         self.produced += time;
     }
 
     pub fn produce_frame(&mut self) -> f64 {
-        let new_timestamp = time::precise_time_s();
-        let elapsed = new_timestamp - self.timestamp;
+        let elapsed = match self.clock {
+            Clock::WallClock { ref mut timestamp } => {
+                let new_timestamp = time::precise_time_s();
+                let elapsed = new_timestamp - *timestamp;
+                *timestamp = new_timestamp;
+                elapsed
+            },
+            Clock::Lockstep { timestep, steps_per_frame, ref mut pending_steps } => {
+                *pending_steps += steps_per_frame;
+                steps_per_frame as f64 * timestep
+            }
+        };
         self.produce(elapsed);
-        self.timestamp = new_timestamp;
         elapsed
     }
 
     pub fn consume(&mut self, time: f64) -> bool {
-        if time <= self.accumulated {
-            self.accumulated -= time;
-            self.consumed += time;
-            true
-        } else {
-            false
+        match self.clock {
+            Clock::WallClock { .. } => {
+                if time <= self.accumulated {
+                    self.accumulated -= time;
+                    self.consumed += time;
+                    true
+                } else {
+                    false
+                }
+            },
+            Clock::Lockstep { timestep, ref mut pending_steps, .. } => {
+                assert!(time == timestep, "A lockstep time keeper can only be consumed in whole steps.");
+                if *pending_steps > 0 {
+                    *pending_steps -= 1;
+                    self.consumed += time;
+                    true
+                } else {
+                    false
+                }
+            }
         }
     }
 
     #[allow(dead_code)]
     pub fn accumulated(&self) -> f64 {
-        self.accumulated
+        match self.clock {
+            Clock::WallClock { .. } => self.accumulated,
+            Clock::Lockstep { timestep, pending_steps, .. } => pending_steps as f64 * timestep
+        }
     }
 
     #[allow(dead_code)]
@@ -53,4 +109,4 @@ impl TimeKeeper {
     pub fn produced(&self) -> f64 {
         self.produced
     }
-}
\ No newline at end of file
+}